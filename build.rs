@@ -0,0 +1,35 @@
+//! Exposes the git commit the server is built from as `VIMEET_GIT_COMMIT`, if available.
+
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    let commit = Command::new("git")
+        .args(&["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=VIMEET_GIT_COMMIT={}", commit.trim());
+    }
+
+    // HEAD only changes when switching branches, new commits change the branch's ref, which is
+    // either a file below refs/heads or a line in packed-refs
+    let mut watched = vec![
+        ".git/HEAD".to_string(),
+        ".git/refs/heads".to_string(),
+        ".git/packed-refs".to_string(),
+    ];
+    if let Ok(head) = std::fs::read_to_string(".git/HEAD") {
+        if let Some(head_ref) = head.trim().strip_prefix("ref: ") {
+            watched.push(format!(".git/{}", head_ref));
+        }
+    }
+
+    // cargo reruns on every build if a watched path doesn't exist
+    for path in watched.iter().filter(|path| Path::new(path).exists()) {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+}
@@ -0,0 +1,216 @@
+//! HTTP API for integrators, e.g. backend services managing rooms without a websocket.
+//!
+//! Every route requires the `Authorization: Bearer <VIMEET_API_TOKEN>` header and is disabled
+//! if no API token is configured.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use actix::Addr;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::auth;
+use crate::server;
+
+/// API token required by all API routes, `None` disables the API
+#[derive(Clone)]
+pub struct ApiToken(pub Option<String>);
+
+/// Maintenance mode, new websocket connections are refused while enabled
+#[derive(Clone, Default)]
+pub struct Maintenance(Arc<AtomicBool>);
+
+impl Maintenance {
+    /// If new connections are currently refused
+    pub fn enabled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::SeqCst);
+    }
+}
+
+/// Request body to toggle maintenance mode
+///
+/// * `enabled` - If new connections are refused
+#[derive(Deserialize)]
+pub struct MaintenanceBody {
+    pub enabled: bool,
+}
+
+/// Request body to create a poll
+///
+/// * `title` - Title of the poll
+/// * `options` - Titles of the poll's options (optional)
+#[derive(Deserialize)]
+pub struct CreatePollBody {
+    pub title: String,
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+/// Check the request's bearer token
+///
+/// Returns the response to send if the request is not authorized
+pub fn authorize(req: &HttpRequest, api_token: &ApiToken) -> Result<(), HttpResponse> {
+    let api_token = match &api_token.0 {
+        None => return Err(HttpResponse::NotFound().finish()),
+        Some(api_token) => api_token,
+    };
+
+    let bearer = req
+        .headers()
+        .get("Authorization")
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.strip_prefix("Bearer "));
+
+    match bearer {
+        Some(bearer) if auth::secrets_match(bearer, api_token) => Ok(()),
+        _ => Err(HttpResponse::Unauthorized().finish()),
+    }
+}
+
+/// Check that the client accepts the JSON responses of the HTTP routes
+///
+/// Returns the response to send if the `Accept` header only lists other media types, a missing
+/// header accepts everything.
+pub fn accept_json(req: &HttpRequest) -> Result<(), HttpResponse> {
+    let accept = match req.headers().get("Accept") {
+        None => return Ok(()),
+        Some(accept) => accept.to_str().unwrap_or(""),
+    };
+
+    let accepted = accept.split(',').any(|media_range| {
+        let mut parameters = media_range.split(';').map(|parameter| parameter.trim());
+        let media_type = parameters.next().unwrap_or("");
+        // `q=0` explicitly refuses a media type
+        let refused = parameters.any(|parameter| {
+            parameter
+                .strip_prefix("q=")
+                .and_then(|quality| quality.parse::<f32>().ok())
+                == Some(0.0)
+        });
+
+        !refused
+            && ["application/json", "application/*", "*/*"]
+                .iter()
+                .any(|accepted| media_type.eq_ignore_ascii_case(accepted))
+    });
+
+    if accepted {
+        Ok(())
+    } else {
+        Err(error_response(
+            HttpResponse::NotAcceptable(),
+            "not_acceptable",
+        ))
+    }
+}
+
+/// Build an error response in the same shape as websocket `error` messages
+fn error_response(
+    mut builder: actix_web::dev::HttpResponseBuilder,
+    error_code: &str,
+) -> HttpResponse {
+    builder.json(json!({
+        "type": "error",
+        "object": error_code,
+    }))
+}
+
+/// `POST /rooms/{room}/polls`: create a poll with options in an existing room
+pub async fn create_poll_route(
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<CreatePollBody>,
+    srv: web::Data<Addr<server::WebSocketServer>>,
+    api_token: web::Data<ApiToken>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &api_token) {
+        return response;
+    }
+    if let Err(response) = accept_json(&req) {
+        return response;
+    }
+
+    let body = body.into_inner();
+    let result = srv
+        .send(server::CreatePoll {
+            room_name: path.into_inner(),
+            title: body.title,
+            options: body.options,
+        })
+        .await;
+
+    match result {
+        Ok(Ok(())) => HttpResponse::Created().finish(),
+        Ok(Err(error_code @ "room_does_not_exist")) => {
+            error_response(HttpResponse::NotFound(), error_code)
+        }
+        Ok(Err(error_code @ "poll_already_exists")) => {
+            error_response(HttpResponse::Conflict(), error_code)
+        }
+        Ok(Err(error_code)) => error_response(HttpResponse::BadRequest(), error_code),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+/// `POST /admin/maintenance`: toggle maintenance mode and notify all connected users
+///
+/// Existing sessions continue, only new connections are refused.
+pub async fn maintenance_route(
+    req: HttpRequest,
+    body: web::Json<MaintenanceBody>,
+    srv: web::Data<Addr<server::WebSocketServer>>,
+    api_token: web::Data<ApiToken>,
+    maintenance: web::Data<Maintenance>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &api_token) {
+        return response;
+    }
+    if let Err(response) = accept_json(&req) {
+        return response;
+    }
+
+    let enabled = body.into_inner().enabled;
+    if maintenance.enabled() != enabled {
+        maintenance.set(enabled);
+        println!(
+            "Maintenance mode {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+        srv.do_send(server::MaintenanceNotice { enabled });
+    }
+
+    HttpResponse::Ok().json(json!({ "enabled": enabled }))
+}
+
+/// `GET /rooms/{room}/export`: all polls of a room with their tallies, without voter identities
+pub async fn export_room_route(
+    req: HttpRequest,
+    path: web::Path<String>,
+    srv: web::Data<Addr<server::WebSocketServer>>,
+    api_token: web::Data<ApiToken>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&req, &api_token) {
+        return response;
+    }
+    if let Err(response) = accept_json(&req) {
+        return response;
+    }
+
+    let result = srv
+        .send(server::GetRoomExport {
+            room_name: path.into_inner(),
+        })
+        .await;
+
+    match result {
+        Ok(Ok(export)) => HttpResponse::Ok().json(export),
+        Ok(Err(error_code)) => error_response(HttpResponse::NotFound(), error_code),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
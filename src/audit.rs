@@ -0,0 +1,176 @@
+//! `AuditLog` is an actor. It appends every inbound text frame to a file, so developers can
+//! inspect what clients sent. The actor runs on its own thread in a `SyncArbiter`, so writing to
+//! the file never blocks the sessions or the web socket server.
+
+use actix::prelude::*;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Default size of the audit log before it is rotated
+pub const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Session received a text frame
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Entry {
+    pub session_id: usize,
+    pub room: String,
+    pub text: String,
+}
+
+/// `AuditLog` owns the buffered writer of the audit log file
+pub struct AuditLog {
+    path: String,
+    writer: BufWriter<File>,
+    size: u64,
+    max_size: u64,
+}
+
+impl AuditLog {
+    /// Open the audit log, appending to an existing file
+    ///
+    /// # Arguments
+    /// * `path` - Path of the audit log file
+    /// * `max_size` - Size in bytes after which the file is rotated to `<path>.1`
+    pub fn new(path: &str, max_size: u64) -> std::io::Result<AuditLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(AuditLog {
+            path: path.to_string(),
+            writer: BufWriter::new(file),
+            size,
+            max_size,
+        })
+    }
+
+    /// Move the current file to `<path>.1`, replacing an older one, and start a new file
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.writer.flush()?;
+        std::fs::rename(&self.path, format!("{}.1", self.path))?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.writer = BufWriter::new(file);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Actor for AuditLog {
+    type Context = SyncContext<Self>;
+
+    fn stopping(&mut self, _: &mut Self::Context) -> Running {
+        let _ = self.writer.flush();
+        Running::Stop
+    }
+}
+
+impl Handler<Entry> for AuditLog {
+    type Result = ();
+
+    fn handle(&mut self, entry: Entry, _: &mut SyncContext<Self>) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis())
+            .unwrap_or(0);
+
+        // the text is JSON encoded to keep one entry per line
+        let line = format!(
+            "{} {} {} {}\n",
+            timestamp,
+            entry.session_id,
+            serde_json::Value::String(entry.room),
+            serde_json::Value::String(entry.text)
+        );
+
+        // the thread only writes the log, so every entry is flushed right away
+        if let Err(e) = self
+            .writer
+            .write_all(line.as_bytes())
+            .and_then(|_| self.writer.flush())
+        {
+            println!("Cannot write audit log: {}", e);
+            return;
+        }
+        self.size += line.len() as u64;
+
+        if self.size > self.max_size {
+            if let Err(e) = self.rotate() {
+                println!("Cannot rotate audit log: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path of a fresh audit log file in the temp directory
+    fn log_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("vimeet-{}-{}.log", name, std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(format!("{}.1", path));
+        path
+    }
+
+    fn entry(session_id: usize, text: &str) -> Entry {
+        Entry {
+            session_id,
+            room: "room".to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn entries_are_appended_one_per_line() {
+        let path = log_path("audit");
+        let log_path = path.clone();
+        let audit = SyncArbiter::start(1, move || {
+            AuditLog::new(&log_path, DEFAULT_MAX_SIZE).unwrap()
+        });
+
+        audit.send(entry(1, r#"{"type": "raise"}"#)).await.unwrap();
+        audit.send(entry(2, "multi\nline")).await.unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+
+        let fields: Vec<&str> = lines[0].splitn(4, ' ').collect();
+        assert!(fields[0].parse::<u128>().is_ok());
+        assert_eq!(fields[1], "1");
+        assert_eq!(fields[2], r#""room""#);
+        assert_eq!(fields[3], r#""{\"type\": \"raise\"}""#);
+        assert!(lines[1].ends_with(r#""multi\nline""#));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn full_log_is_rotated() {
+        let path = log_path("audit-rotate");
+        let log_path = path.clone();
+        let audit = SyncArbiter::start(1, move || AuditLog::new(&log_path, 50).unwrap());
+
+        audit
+            .send(entry(1, "a message filling the log"))
+            .await
+            .unwrap();
+        audit.send(entry(1, "next")).await.unwrap();
+
+        let rotated = std::fs::read_to_string(format!("{}.1", path)).unwrap();
+        assert!(rotated.contains("filling"));
+        let current = std::fs::read_to_string(&path).unwrap();
+        assert!(current.contains("next"));
+        assert!(!current.contains("filling"));
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(format!("{}.1", path)).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+//! Optional authentication of websocket sessions at connect time.
+//!
+//! Clients pass their token as `token` query parameter. Without a configured shared secret or JWT
+//! key every client may connect to every room.
+
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use serde::Deserialize;
+
+/// How connecting sessions are authenticated
+#[derive(Clone)]
+pub enum Auth {
+    /// Every client may connect
+    None,
+    /// Clients have to send this shared secret
+    Token(String),
+    /// Clients have to send a JWT signed (HS256) with this key
+    Jwt(String),
+}
+
+/// Claims of a JWT used to connect
+///
+/// * `name` - Name of the user, replaces the name in the path (optional)
+/// * `role` - `spectator` connects as spectator (optional)
+#[derive(Deserialize)]
+struct Claims {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    role: Option<String>,
+}
+
+/// Identity of an authenticated session, taken from the token if it carries one
+pub struct Identity {
+    /// Name of the user, the name in the path is used if `None`
+    pub name: Option<String>,
+    /// If the user connects as spectator, taken from the request if `None`
+    pub spectator: Option<bool>,
+}
+
+impl Auth {
+    /// Check the token of a connecting session
+    ///
+    /// # Arguments
+    /// * `token` - The `token` query parameter, if sent
+    pub fn authenticate(&self, token: Option<&str>) -> Result<Identity, &'static str> {
+        let anonymous = Identity {
+            name: None,
+            spectator: None,
+        };
+
+        match (self, token) {
+            (Auth::None, _) => Ok(anonymous),
+            (_, None) => Err("missing_token"),
+            (Auth::Token(secret), Some(token)) if secrets_match(token, secret) => Ok(anonymous),
+            (Auth::Token(_), Some(_)) => Err("invalid_token"),
+            (Auth::Jwt(key), Some(token)) => {
+                let claims = decode::<Claims>(
+                    token,
+                    &DecodingKey::from_secret(key.as_bytes()),
+                    &Validation::default(),
+                )
+                .map_err(|_| "invalid_token")?
+                .claims;
+
+                Ok(Identity {
+                    name: claims.name,
+                    spectator: claims.role.map(|role| role == "spectator"),
+                })
+            }
+        }
+    }
+}
+
+/// Compare a token sent by a client with a secret in constant time
+///
+/// The comparison doesn't stop at the first differing byte, so its duration doesn't tell how much
+/// of a guessed token is right. Only the length of the secret may leak.
+///
+/// # Arguments
+/// * `token` - The token sent by the client
+/// * `secret` - The configured secret
+pub fn secrets_match(token: &str, secret: &str) -> bool {
+    if token.len() != secret.len() {
+        return false;
+    }
+    token
+        .bytes()
+        .zip(secret.bytes())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    #[test]
+    fn everybody_connects_without_auth() {
+        let identity = Auth::None.authenticate(None).unwrap();
+        assert!(identity.name.is_none());
+        assert!(identity.spectator.is_none());
+        assert!(Auth::None.authenticate(Some("anything")).is_ok());
+    }
+
+    #[test]
+    fn shared_secret_is_required() {
+        let auth = Auth::Token("secret".to_string());
+        assert_eq!(auth.authenticate(None).err(), Some("missing_token"));
+        assert_eq!(
+            auth.authenticate(Some("secreT")).err(),
+            Some("invalid_token")
+        );
+        assert_eq!(
+            auth.authenticate(Some("secrets")).err(),
+            Some("invalid_token")
+        );
+        assert_eq!(auth.authenticate(Some("")).err(), Some("invalid_token"));
+        assert!(auth.authenticate(Some("secret")).is_ok());
+    }
+
+    #[test]
+    fn jwt_claims_identify_the_user() {
+        let auth = Auth::Jwt("key".to_string());
+        let token = |key: &str| {
+            encode(
+                &Header::default(),
+                &json!({"name": "alice", "role": "spectator", "exp": 32503680000u64}),
+                &EncodingKey::from_secret(key.as_bytes()),
+            )
+            .unwrap()
+        };
+
+        let identity = auth.authenticate(Some(&token("key"))).unwrap();
+        assert_eq!(identity.name.as_deref(), Some("alice"));
+        assert_eq!(identity.spectator, Some(true));
+
+        assert_eq!(auth.authenticate(None).err(), Some("missing_token"));
+        assert_eq!(
+            auth.authenticate(Some(&token("other"))).err(),
+            Some("invalid_token")
+        );
+        assert_eq!(
+            auth.authenticate(Some("garbage")).err(),
+            Some("invalid_token")
+        );
+    }
+
+    #[test]
+    fn secrets_are_compared_completely() {
+        assert!(secrets_match("secret", "secret"));
+        assert!(!secrets_match("secreT", "secret"));
+        assert!(!secrets_match("Secret", "secret"));
+        assert!(!secrets_match("secret", "secret!"));
+        assert!(secrets_match("", ""));
+    }
+}
@@ -0,0 +1,128 @@
+//! `EventSink` receives lifecycle events of rooms, users and polls from the `WebSocketServer`,
+//! so integrations like webhooks or analytics can react to them.
+
+use actix_web::client::Client;
+use actix_web::web;
+use serde_json::{json, Value as Arbitrary};
+use std::fs::OpenOptions;
+use std::io::Write;
+
+/// Receiver of lifecycle events, every event defaults to doing nothing
+pub trait EventSink {
+    /// A user joined a room
+    fn user_joined(&self, _room_name: &str, _user_id: usize, _user_name: &str) {}
+
+    /// A user left a room
+    fn user_left(&self, _room_name: &str, _user_id: usize, _user_name: &str) {}
+
+    /// A poll has been created in a room
+    fn poll_created(&self, _room_name: &str, _poll_title: &str) {}
+
+    /// A poll has been closed in a room
+    fn poll_closed(&self, _room_name: &str, _poll_title: &str) {}
+
+    /// The final results of a poll that has been closed
+    fn poll_results(&self, _room_name: &str, _results: &Arbitrary) {}
+}
+
+/// `WebhookSink` posts every event as JSON to a URL
+pub struct WebhookSink {
+    url: String,
+}
+
+impl WebhookSink {
+    /// # Arguments
+    /// * `url` - URL every event is posted to
+    pub fn new(url: String) -> WebhookSink {
+        WebhookSink { url }
+    }
+
+    /// Post an event in the background, failures are only logged
+    ///
+    /// # Arguments
+    /// * `event` - The JSON body of the event
+    fn post(&self, event: Arbitrary) {
+        let request = Client::default().post(&self.url).send_json(&event);
+        actix::spawn(async move {
+            if let Err(e) = request.await {
+                println!("Cannot post event to webhook: {}", e);
+            }
+        });
+    }
+}
+
+impl EventSink for WebhookSink {
+    fn user_joined(&self, room_name: &str, user_id: usize, user_name: &str) {
+        self.post(json!({
+            "event": "userjoined",
+            "room": room_name,
+            "userid": user_id,
+            "username": user_name,
+        }));
+    }
+
+    fn user_left(&self, room_name: &str, user_id: usize, user_name: &str) {
+        self.post(json!({
+            "event": "userleft",
+            "room": room_name,
+            "userid": user_id,
+            "username": user_name,
+        }));
+    }
+
+    fn poll_created(&self, room_name: &str, poll_title: &str) {
+        self.post(json!({
+            "event": "pollcreated",
+            "room": room_name,
+            "pollobject": poll_title,
+        }));
+    }
+
+    fn poll_closed(&self, room_name: &str, poll_title: &str) {
+        self.post(json!({
+            "event": "pollclosed",
+            "room": room_name,
+            "pollobject": poll_title,
+        }));
+    }
+
+    fn poll_results(&self, _room_name: &str, results: &Arbitrary) {
+        let mut event = results.clone();
+        event["event"] = json!("pollresults");
+        self.post(event);
+    }
+}
+
+/// `FileSink` appends the results of closed polls as JSON lines to a file
+pub struct FileSink {
+    path: String,
+}
+
+impl FileSink {
+    /// # Arguments
+    /// * `path` - Path of the file the results are appended to
+    pub fn new(path: String) -> FileSink {
+        FileSink { path }
+    }
+}
+
+impl EventSink for FileSink {
+    fn poll_results(&self, _room_name: &str, results: &Arbitrary) {
+        let path = self.path.clone();
+        let line = format!("{}\n", results);
+
+        // write on the blocking thread pool to not stall the web socket server
+        let write = web::block(move || {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)?
+                .write_all(line.as_bytes())
+        });
+        actix::spawn(async move {
+            if let Err(e) = write.await {
+                println!("Cannot write poll results: {}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,71 @@
+//! Length limits of user and room names, checked when a session connects, renames its user or
+//! joins another room.
+
+/// Default maximum number of characters of a user name
+pub const DEFAULT_MAX_NAME_LEN: usize = 64;
+/// Default maximum number of characters of a room name
+pub const DEFAULT_MAX_ROOM_LEN: usize = 64;
+
+/// Maximum lengths of names
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// Maximum number of characters of a user name
+    pub max_name_len: usize,
+    /// Maximum number of characters of a room name
+    pub max_room_len: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_name_len: DEFAULT_MAX_NAME_LEN,
+            max_room_len: DEFAULT_MAX_ROOM_LEN,
+        }
+    }
+}
+
+impl Limits {
+    /// Check the room and user name of a connecting session
+    ///
+    /// Lengths are counted in characters, not bytes.
+    ///
+    /// # Arguments
+    /// * `room_name` - The room the session connects to
+    /// * `user_name` - The name of the connecting user
+    pub fn validate(&self, room_name: &str, user_name: &str) -> Result<(), &'static str> {
+        if room_name.chars().count() > self.max_room_len {
+            return Err("room_too_long");
+        }
+        if user_name.chars().count() > self.max_name_len {
+            return Err("name_too_long");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_within_the_limits_pass() {
+        let limits = Limits {
+            max_name_len: 5,
+            max_room_len: 4,
+        };
+        assert_eq!(limits.validate("room", "alice"), Ok(()));
+        // characters are counted, not bytes
+        assert_eq!(limits.validate("räum", "jörg"), Ok(()));
+    }
+
+    #[test]
+    fn names_beyond_the_limits_are_refused() {
+        let limits = Limits {
+            max_name_len: 5,
+            max_room_len: 4,
+        };
+        assert_eq!(limits.validate("rooms", "alice"), Err("room_too_long"));
+        assert_eq!(limits.validate("room", "alicia"), Err("name_too_long"));
+        assert_eq!(limits.validate("rooms", "alicia"), Err("room_too_long"));
+    }
+}
@@ -1,41 +1,376 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use actix::prelude::SendError;
 use actix::*;
 use actix_files as fs;
+use actix_http::ws::Item as WsItem;
 use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
 
-use serde_json::{Result as JsonResult, Value as Arbitrary};
+use serde_json::{json, Result as JsonResult, Value as Arbitrary};
 
 use dotenv::dotenv;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use std::env;
+use std::io::Write;
+use std::str::FromStr;
 
+mod api;
+mod audit;
+mod auth;
+mod events;
+mod limits;
 mod messages;
 use messages::inbound::GetMessageType;
 mod server;
 
 /// How often heartbeat pings are sent
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// Maximum size of a message reassembled from continuation frames, same as a single frame
+const MAX_MESSAGE_SIZE: usize = 65_536;
 /// How long before lack of client response causes a timeout
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// How long a new session waits for the web socket server to accept it by default
+const DEFAULT_JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Size in bytes from which messages are compressed for clients with the `gzip` capability by default
+const DEFAULT_COMPRESSION_THRESHOLD: usize = 8192;
+
+/// Close codes of server-initiated stops, application codes start at 4000
+const CLOSE_CODE_TIMEOUT: u16 = 4000;
+const CLOSE_CODE_KICKED: u16 = 4001;
+const CLOSE_CODE_ROOM_ENDED: u16 = 4002;
+const CLOSE_CODE_SLOW_CONSUMER: u16 = 4003;
+const CLOSE_CODE_REPLACED: u16 = 4004;
+
+/// Port used if neither `PORT` nor `VIMEET_PORT` are set
+const DEFAULT_PORT: u16 = 8080;
+
+/// Open websocket connections, shared by all workers
+#[derive(Clone)]
+struct MaxConnections {
+    /// Maximum number of simultaneous websocket connections, `None` is unlimited
+    max: Option<usize>,
+    open: Arc<AtomicUsize>,
+}
+
+impl MaxConnections {
+    fn new(max: Option<usize>) -> MaxConnections {
+        MaxConnections {
+            max,
+            open: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Take a connection slot, `None` if all slots are taken
+    fn acquire(&self) -> Option<ConnectionSlot> {
+        let open = self.open.fetch_add(1, Ordering::SeqCst);
+        match self.max {
+            Some(max) if open >= max => {
+                self.open.fetch_sub(1, Ordering::SeqCst);
+                None
+            }
+            _ => Some(ConnectionSlot {
+                open: self.open.clone(),
+            }),
+        }
+    }
+}
+
+/// How long a new session waits for the web socket server to accept it
+#[derive(Clone, Copy)]
+struct JoinTimeout(Duration);
+
+/// Size in bytes from which messages are compressed for clients with the `gzip` capability
+#[derive(Clone, Copy)]
+struct CompressionThreshold(usize);
+
+/// A taken connection slot, freed again when dropped together with its session
+struct ConnectionSlot {
+    open: Arc<AtomicUsize>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Open websocket connections per peer address, shared by all workers
+#[derive(Clone)]
+struct PeerConnections {
+    /// Maximum number of simultaneous connections from the same peer address, `None` is unlimited
+    max: Option<usize>,
+    open: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl PeerConnections {
+    fn new(max: Option<usize>) -> PeerConnections {
+        PeerConnections {
+            max,
+            open: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Take a connection slot of a peer address, `None` if all slots of the address are taken
+    ///
+    /// # Arguments
+    /// * `peer` - Address of the connecting client
+    fn acquire(&self, peer: IpAddr) -> Option<PeerSlot> {
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        let count = open.entry(peer).or_insert(0);
+        match self.max {
+            Some(max) if *count >= max => None,
+            _ => {
+                *count += 1;
+                Some(PeerSlot {
+                    open: self.open.clone(),
+                    peer,
+                })
+            }
+        }
+    }
+}
+
+/// A taken connection slot of a peer address, freed again when dropped together with its session
+struct PeerSlot {
+    open: Arc<Mutex<HashMap<IpAddr, usize>>>,
+    peer: IpAddr,
+}
+
+impl Drop for PeerSlot {
+    fn drop(&mut self) {
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = open.get_mut(&self.peer) {
+            *count -= 1;
+            // forget addresses without connections, the map would grow with every client otherwise
+            if *count == 0 {
+                open.remove(&self.peer);
+            }
+        }
+    }
+}
+
+/// Time the server has been started at
+#[derive(Clone, Copy)]
+struct StartTime {
+    system: SystemTime,
+    instant: Instant,
+}
+
+/// `GET /info`: version, commit, start time and uptime of the server
+async fn info_route(req: HttpRequest, start_time: web::Data<StartTime>) -> HttpResponse {
+    if let Err(response) = api::accept_json(&req) {
+        return response;
+    }
+
+    let started = start_time
+        .system
+        .duration_since(UNIX_EPOCH)
+        .map(|started| started.as_secs())
+        .unwrap_or(0);
+
+    HttpResponse::Ok().json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "commit": option_env!("VIMEET_GIT_COMMIT"),
+        "started": started,
+        "uptime": start_time.instant.elapsed().as_secs(),
+    }))
+}
+
+/// Routes of the demo client, not registered in API-only mode
+fn static_routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        // redirect to websocket.html
+        .service(web::resource("/").route(web::get().to(|| {
+            HttpResponse::Found()
+                .header("LOCATION", "/static/websocket.html")
+                .finish()
+        })))
+        // static resources
+        .service(fs::Files::new("/static/", "static/"));
+}
+
+/// `POST /validate`: check the shape of an inbound message without sending it to any room
+async fn validate_route(req: HttpRequest, body: String) -> HttpResponse {
+    if let Err(response) = api::accept_json(&req) {
+        return response;
+    }
+
+    HttpResponse::Ok().json(messages::inbound::validate(&body))
+}
+
 /// Entry point for our route
 async fn web_socket_route(
     req: HttpRequest,
     path: web::Path<(String, String)>,
+    query: web::Query<HashMap<String, String>>,
     stream: web::Payload,
     srv: web::Data<Addr<server::WebSocketServer>>,
+    audit: web::Data<Option<Addr<audit::AuditLog>>>,
+    max_connections: web::Data<MaxConnections>,
+    peer_connections: web::Data<PeerConnections>,
+    limits: web::Data<limits::Limits>,
+    join_timeout: web::Data<JoinTimeout>,
+    auth: web::Data<auth::Auth>,
+    compression_threshold: web::Data<CompressionThreshold>,
+    maintenance: web::Data<api::Maintenance>,
 ) -> Result<HttpResponse, Error> {
-    ws::start(
+    if maintenance.enabled() {
+        println!("Maintenance mode enabled, rejecting connection");
+        return Ok(HttpResponse::ServiceUnavailable().body("maintenance"));
+    }
+
+    let identity = match auth.authenticate(query.get("token").map(|token| token.as_str())) {
+        Ok(identity) => identity,
+        Err(error_code) => {
+            println!(
+                "Authentication failed ({}), rejecting connection",
+                error_code
+            );
+            return Ok(HttpResponse::Unauthorized().body(error_code));
+        }
+    };
+
+    let connection = match max_connections.acquire() {
+        Some(connection) => connection,
+        None => {
+            println!("Maximum number of connections reached, rejecting connection");
+            return Ok(HttpResponse::ServiceUnavailable().finish());
+        }
+    };
+
+    let peer_connection = match req.peer_addr() {
+        None => None,
+        Some(peer) => match peer_connections.acquire(peer.ip()) {
+            Some(peer_connection) => Some(peer_connection),
+            None => {
+                println!(
+                    "Maximum number of connections from {} reached, rejecting connection",
+                    peer.ip()
+                );
+                return Ok(HttpResponse::TooManyRequests().finish());
+            }
+        },
+    };
+
+    // a name in the token replaces the name in the path
+    let name = match identity.name {
+        Some(name) => Ok(name),
+        None => path_name(&req),
+    };
+    let name = match name.and_then(|name| normalize_name(&name)) {
+        Ok(name) => name,
+        Err(error_code) => {
+            println!("Invalid name '{}', rejecting connection", path.1);
+            return Ok(HttpResponse::BadRequest().body(error_code));
+        }
+    };
+
+    if let Err(error_code) = limits.validate(&path.0, &name) {
+        println!("Name or room too long, rejecting connection");
+        return Ok(HttpResponse::BadRequest().body(error_code));
+    }
+
+    let capabilities = negotiate_capabilities(query.get("caps").map(String::as_str));
+
+    // clients requesting subprotocols must speak at least one of ours
+    if let Some(requested) = req.headers().get("Sec-WebSocket-Protocol") {
+        let supported = requested
+            .to_str()
+            .unwrap_or("")
+            .split(',')
+            .any(|protocol| messages::outbound::SUBPROTOCOLS.contains(&protocol.trim()));
+        if !supported {
+            println!("No supported subprotocol requested, rejecting connection");
+            return Ok(HttpResponse::BadRequest().body("unsupported_subprotocol"));
+        }
+    }
+
+    ws::start_with_protocols(
         WsWebSocketSession {
             id: get_id(),
             hb: Instant::now(),
+            ping_nonce: 0,
             room: path.0.clone(),
-            name: path.1.clone(),
+            name,
             addr: srv.get_ref().clone(),
+            audit: audit.get_ref().clone(),
+            continuation: None,
+            capabilities,
+            spectator: identity.spectator.unwrap_or_else(|| {
+                query
+                    .get("spectator")
+                    .map_or(false, |spectator| spectator == "true")
+            }),
+            admin: false,
+            limits: *limits.get_ref(),
+            join_timeout: join_timeout.0,
+            compression_threshold: compression_threshold.0,
+            busy: false,
+            _connection: connection,
+            _peer_connection: peer_connection,
         },
+        messages::outbound::SUBPROTOCOLS,
+        &req,
+        stream,
+    )
+}
+
+/// Entry point for admin sessions, hidden observers of a room guarded by the API token
+///
+/// Admin sessions receive every broadcast of the room including the ones for elevated users, but
+/// are no users of the room and can't act in it.
+async fn admin_route(
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    stream: web::Payload,
+    srv: web::Data<Addr<server::WebSocketServer>>,
+    api_token: web::Data<api::ApiToken>,
+    max_connections: web::Data<MaxConnections>,
+    join_timeout: web::Data<JoinTimeout>,
+    compression_threshold: web::Data<CompressionThreshold>,
+) -> Result<HttpResponse, Error> {
+    if let Err(response) = api::authorize(&req, &api_token) {
+        println!("Admin session not authorized, rejecting connection");
+        return Ok(response);
+    }
+
+    let connection = match max_connections.acquire() {
+        Some(connection) => connection,
+        None => {
+            println!("Maximum number of connections reached, rejecting connection");
+            return Ok(HttpResponse::ServiceUnavailable().finish());
+        }
+    };
+
+    ws::start_with_protocols(
+        WsWebSocketSession {
+            id: get_id(),
+            hb: Instant::now(),
+            ping_nonce: 0,
+            room: path.0.clone(),
+            name: "admin".to_string(),
+            addr: srv.get_ref().clone(),
+            audit: None,
+            continuation: None,
+            capabilities: HashSet::new(),
+            spectator: true,
+            admin: true,
+            limits: limits::Limits::default(),
+            join_timeout: join_timeout.0,
+            compression_threshold: compression_threshold.0,
+            busy: false,
+            _connection: connection,
+            _peer_connection: None,
+        },
+        messages::outbound::SUBPROTOCOLS,
         &req,
         stream,
     )
@@ -47,12 +382,36 @@ struct WsWebSocketSession {
     /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
     /// otherwise we drop connection.
     hb: Instant,
+    /// payload of the last ping, the client's pong has to echo it
+    ping_nonce: u64,
     /// joined room
     room: String,
     /// peer name
     name: String,
     /// web socket server
     addr: Addr<server::WebSocketServer>,
+    /// audit log of inbound messages, if enabled
+    audit: Option<Addr<audit::AuditLog>>,
+    /// fragments of a message received in continuation frames as `(is_text, data)`
+    continuation: Option<(bool, Vec<u8>)>,
+    /// capabilities negotiated with the client
+    capabilities: HashSet<String>,
+    /// if the user joins rooms as spectator
+    spectator: bool,
+    /// if the session is a hidden admin observer of its room
+    admin: bool,
+    /// length limits of names, checked on renames and joined rooms
+    limits: limits::Limits,
+    /// how long to wait for the web socket server to accept the session
+    join_timeout: Duration,
+    /// size in bytes from which messages are compressed, if the client has the `gzip` capability
+    compression_threshold: usize,
+    /// connection slot, freed on every way the session ends
+    _connection: ConnectionSlot,
+    /// connection slot of the peer address, `None` if the address is unknown
+    _peer_connection: Option<PeerSlot>,
+    /// if a request of the current message has been dropped, the web socket server was busy
+    busy: bool,
 }
 
 impl Actor for WsWebSocketSession {
@@ -70,17 +429,30 @@ impl Actor for WsWebSocketSession {
         let addr = ctx.address();
         self.addr
             .send(server::Join {
-                addr: addr.recipient(),
+                addr: addr.clone().recipient(),
+                stop: addr.recipient(),
                 room_name: self.room.clone(),
                 user_id: self.id,
                 user_name: self.name.clone(),
+                capabilities: self.capabilities.clone(),
+                spectator: self.spectator,
+                admin: self.admin,
             })
+            .timeout(self.join_timeout)
             .into_actor(self)
-            .then(|res, _, ctx| {
+            .then(|res, act, ctx| {
                 match res {
                     Ok(_) => (), // act.id = res,
-                    // something is wrong with web socket server
-                    _ => ctx.stop(),
+                    // web socket server is overloaded or something is wrong with it
+                    Err(e) => {
+                        println!("Cannot join session {}: {}", act.id, e);
+                        act.send_error(
+                            ctx,
+                            "server_busy",
+                            "The server is busy, please try again later",
+                        );
+                        act.close(ctx, server::StopReason::Busy);
+                    }
                 }
                 fut::ready(())
             })
@@ -99,7 +471,32 @@ impl Handler<server::Message> for WsWebSocketSession {
     type Result = ();
 
     fn handle(&mut self, msg: server::Message, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+        // the server already converted the message for camelcase sessions
+        let text = msg.0;
+
+        if text.len() >= self.compression_threshold
+            && self
+                .capabilities
+                .contains(messages::outbound::CAPABILITY_GZIP)
+        {
+            match gzip(&text) {
+                Ok(compressed) => {
+                    ctx.binary(compressed);
+                    return;
+                }
+                Err(e) => println!("Cannot compress message, sending it uncompressed: {}", e),
+            }
+        }
+        ctx.text(&*text);
+    }
+}
+
+/// Stop the session on request of the web socket server
+impl Handler<server::Stop> for WsWebSocketSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: server::Stop, ctx: &mut Self::Context) {
+        self.close(ctx, msg.0);
     }
 }
 
@@ -108,7 +505,7 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsWebSocketSessio
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         let msg = match msg {
             Err(_) => {
-                ctx.stop();
+                self.close(ctx, server::StopReason::ProtocolError);
                 return;
             }
             Ok(msg) => msg,
@@ -116,179 +513,638 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsWebSocketSessio
 
         match msg {
             ws::Message::Ping(msg) => {
-                self.hb = Instant::now();
+                self.touch();
                 ctx.pong(&msg);
             }
 
-            ws::Message::Pong(_) => {
-                self.hb = Instant::now();
+            ws::Message::Pong(payload) => {
+                if answers_ping(self.ping_nonce, &payload) {
+                    // pongs answer the server's pings, the session is alive but the user may be
+                    // idle, so the rooms aren't touched
+                    self.hb = Instant::now();
+                } else {
+                    println!("Unexpected pong payload, ignoring");
+                }
             }
 
-            ws::Message::Text(text) => {
-                let m = text.trim();
-                let msg: Result<messages::inbound::HashMapObject, _> = serde_json::from_str(m);
-                match msg {
-                    Ok(msg) => match msg.get_type() {
-                        Ok(messages::inbound::Types::Poll) => match msg.object.get("poll_title") {
-                            Some(poll_title) => {
-                                self.addr.do_send(server::Poll {
-                                    title: poll_title.to_string(),
-                                    owner_id: self.id,
-                                    owner_name: self.name.clone(),
-                                    room_name: self.room.to_owned(),
-                                    options: Vec::new(),
-                                    votes: HashMap::new(),
-                                    closed: false,
-                                });
-                                return;
-                            }
-                            _ => (),
-                        },
-                        Ok(messages::inbound::Types::PollOption) => match (
-                            msg.object.get("poll_title"),
-                            msg.object.get("poll_option_title"),
-                        ) {
-                            (Some(poll_title), Some(poll_option_title)) => {
-                                self.addr.do_send(server::PollOption {
-                                    poll_title: poll_title.to_string(),
-                                    title: poll_option_title.to_string(),
-                                    owner_id: self.id,
-                                    owner_name: self.name.clone(),
-                                    room_name: self.room.to_owned(),
-                                });
-                                return;
-                            }
-                            (_, _) => (),
-                        },
-                        Ok(messages::inbound::Types::Vote) => match (
-                            msg.object.get("poll_title"),
-                            msg.object.get("poll_option_title"),
-                        ) {
-                            (Some(poll_title), Some(poll_option_title)) => {
-                                self.addr.do_send(server::PollVoteHelper {
-                                    owner_id: self.id,
-                                    owner_name: self.name.clone(),
-                                    room_name: self.room.to_owned(),
-                                    poll_title: poll_title.to_string(),
-                                    option_title: poll_option_title.to_string(),
-                                });
-                                return;
-                            }
-                            (_, _) => (),
-                        },
-                        Ok(messages::inbound::Types::PollClose) => {
-                            match msg.object.get("poll_title") {
-                                Some(poll_title) => {
-                                    self.addr.do_send(server::PollCloseHelper {
-                                        poll_title: poll_title.to_string(),
-                                        sender_id: self.id,
-                                        sender_name: self.name.clone(),
-                                        room_name: self.room.to_owned(),
-                                    });
-                                    return;
-                                }
-                                _ => (),
-                            }
+            ws::Message::Text(text) => self.handle_text(text, ctx),
+
+            ws::Message::Binary(_) => println!("Unexpected binary"),
+            ws::Message::Close(_) => {
+                ctx.stop();
+            }
+
+            ws::Message::Continuation(item) => {
+                match continue_message(&mut self.continuation, item) {
+                    Ok(Some((true, buffer))) => match String::from_utf8(buffer) {
+                        Ok(text) => self.handle_text(text, ctx),
+                        Err(_) => {
+                            println!("Fragmented message is not UTF-8, disconnecting!");
+                            self.close(ctx, server::StopReason::ProtocolError);
                         }
-                        Ok(_) | Err(_) => (),
                     },
-                    Err(_) => (),
+                    Ok(Some((false, _))) => println!("Unexpected binary"),
+                    Ok(None) => (),
+                    Err(error) => {
+                        println!("{}, disconnecting!", error);
+                        self.close(ctx, server::StopReason::ProtocolError);
+                    }
                 }
+            }
 
-                let msg: Result<messages::inbound::UsizeObject, _> = serde_json::from_str(m);
-                match msg {
-                    Ok(msg) => match msg.get_type() {
-                        Ok(messages::inbound::Types::Elevate) => {
-                            self.addr.do_send(server::Elevate {
-                                object: msg.object,
-                                owner_id: self.id,
-                                room_name: self.room.to_owned(),
-                            });
-                            return;
-                        }
-                        Ok(messages::inbound::Types::Recede) => {
-                            self.addr.do_send(server::Recede {
-                                object: msg.object,
-                                owner_id: self.id,
-                                room_name: self.room.to_owned(),
-                            });
-                            return;
-                        }
-                        Ok(_) | Err(_) => (),
+            ws::Message::Nop => (),
+        }
+    }
+}
+
+impl WsWebSocketSession {
+    /// handle a complete text message of the client
+    fn handle_text(&mut self, text: String, ctx: &mut ws::WebsocketContext<Self>) {
+        self.dispatch_text(text, ctx);
+
+        if std::mem::take(&mut self.busy) {
+            self.send_error(
+                ctx,
+                "server_busy",
+                "The server is busy, please try again later",
+            );
+        }
+    }
+
+    /// parse a complete text message of the client and forward it to the web socket server
+    fn dispatch_text(&mut self, text: String, ctx: &mut ws::WebsocketContext<Self>) {
+        // every message counts as heartbeat, so an application level ping keeps the session
+        // alive even if a proxy drops ping frames
+        self.touch();
+
+        if let Some(pong) = app_pong(&text) {
+            ctx.text(pong);
+            return;
+        }
+
+        if self.admin {
+            self.send_error(
+                ctx,
+                "observer_forbidden",
+                "Admin sessions only observe the room",
+            );
+            return;
+        }
+
+        if let Some(audit) = &self.audit {
+            // log the room the message targets, which is not the connected one for joined rooms
+            let room = serde_json::from_str::<Arbitrary>(&text)
+                .ok()
+                .and_then(|msg| {
+                    msg.get("room")
+                        .and_then(|room| room.as_str())
+                        .map(String::from)
+                });
+            audit.do_send(audit::Entry {
+                session_id: self.id,
+                room: self.target_room(&room),
+                text: text.clone(),
+            });
+        }
+
+        if self
+            .capabilities
+            .contains(messages::outbound::CAPABILITY_STRICT)
+        {
+            if let Err(e) = messages::inbound::parse_strict(&text) {
+                self.send_error(ctx, "strict_parse_error", &e);
+                return;
+            }
+        }
+
+        let m = text.trim();
+        // polls carry typed settings, a poll that doesn't match its skeleton is refused
+        let msg: Result<messages::inbound::ArbitraryObject, _> = serde_json::from_str(m);
+        if let Ok(Ok(messages::inbound::Types::Poll)) = msg.map(|msg| msg.get_type()) {
+            let msg: Result<messages::inbound::PollObject, _> = serde_json::from_str(m);
+            match msg {
+                Ok(msg) => self.forward(server::Poll {
+                    title: msg.object.poll_title,
+                    owner_id: self.id,
+                    owner_name: self.name.clone(),
+                    room_name: self.target_room(&msg.room),
+                    options: Vec::new(),
+                    votes: HashMap::new(),
+                    state: if msg.object.draft {
+                        server::PollState::Draft
+                    } else {
+                        server::PollState::Open
                     },
-                    Err(_) => (),
+                    lock_after_first_vote_secs: msg.object.lock_after_first_vote_secs,
+                    voting_locked: false,
+                    created: Instant::now(),
+                    msg_id: msg.msg_id,
+                }),
+                Err(e) => self.send_error(ctx, "bad_object", &e.to_string()),
+            }
+            return;
+        }
+
+        let msg: Result<messages::inbound::HashMapObject, _> = serde_json::from_str(m);
+        match msg {
+            Ok(msg) => match msg.get_type() {
+                Ok(messages::inbound::Types::PollOption) => match (
+                    msg.object.get("poll_title"),
+                    msg.object.get("poll_option_title"),
+                ) {
+                    (Some(poll_title), Some(poll_option_title)) => {
+                        self.forward(server::PollOption {
+                            poll_title: poll_title.to_string(),
+                            title: poll_option_title.to_string(),
+                            owner_id: self.id,
+                            owner_name: self.name.clone(),
+                            room_name: self.target_room(&msg.room),
+                            order: 0,
+                            msg_id: msg.msg_id.clone(),
+                        });
+                        return;
+                    }
+                    (_, _) => (),
+                },
+                Ok(messages::inbound::Types::Vote) => match (
+                    msg.object.get("poll_title"),
+                    msg.object.get("poll_option_title"),
+                ) {
+                    (Some(poll_title), Some(poll_option_title)) => {
+                        self.forward(server::PollVoteHelper {
+                            owner_id: self.id,
+                            owner_name: self.name.clone(),
+                            room_name: self.target_room(&msg.room),
+                            poll_title: poll_title.to_string(),
+                            option_title: poll_option_title.to_string(),
+                            msg_id: msg.msg_id.clone(),
+                        });
+                        return;
+                    }
+                    (_, _) => (),
+                },
+                Ok(messages::inbound::Types::PollOptionRename) => match (
+                    msg.object.get("poll_title"),
+                    msg.object.get("poll_option_title"),
+                    msg.object.get("new_poll_option_title"),
+                ) {
+                    (Some(poll_title), Some(old_title), Some(new_title)) => {
+                        self.forward(server::PollOptionRename {
+                            owner_id: self.id,
+                            room_name: self.target_room(&msg.room),
+                            poll_title: poll_title.to_string(),
+                            old_title: old_title.to_string(),
+                            new_title: new_title.to_string(),
+                            msg_id: msg.msg_id,
+                        });
+                        return;
+                    }
+                    _ => (),
+                },
+                Ok(messages::inbound::Types::PollOpen) => match msg.object.get("poll_title") {
+                    Some(poll_title) => {
+                        self.forward(server::PollOpen {
+                            poll_title: poll_title.to_string(),
+                            sender_id: self.id,
+                            room_name: self.target_room(&msg.room),
+                            msg_id: msg.msg_id.clone(),
+                        });
+                        return;
+                    }
+                    _ => (),
+                },
+                Ok(messages::inbound::Types::PollStatus) => match msg.object.get("poll_title") {
+                    Some(poll_title) => {
+                        self.forward(server::PollStatus {
+                            poll_title: poll_title.to_string(),
+                            sender_id: self.id,
+                            room_name: self.target_room(&msg.room),
+                            msg_id: msg.msg_id.clone(),
+                        });
+                        return;
+                    }
+                    _ => (),
+                },
+                Ok(messages::inbound::Types::PollClose) => match msg.object.get("poll_title") {
+                    Some(poll_title) => {
+                        self.forward(server::PollCloseHelper {
+                            poll_title: poll_title.to_string(),
+                            sender_id: self.id,
+                            sender_name: self.name.clone(),
+                            room_name: self.target_room(&msg.room),
+                            msg_id: msg.msg_id.clone(),
+                        });
+                        return;
+                    }
+                    _ => (),
+                },
+                Ok(_) | Err(_) => (),
+            },
+            Err(_) => (),
+        }
+
+        let msg: Result<messages::inbound::SpectatorObject, _> = serde_json::from_str(m);
+        match msg {
+            Ok(msg) => match msg.get_type() {
+                Ok(messages::inbound::Types::SetSpectator) => {
+                    self.forward(server::SetSpectator {
+                        object: msg.object,
+                        spectator: msg.spectator,
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
                 }
+                Ok(_) | Err(_) => (),
+            },
+            Err(_) => (),
+        }
 
-                let msg: Result<messages::inbound::ArbitraryObject, _> = serde_json::from_str(m);
-                match msg {
-                    Ok(msg) => match msg.get_type() {
-                        Ok(messages::inbound::Types::Instant) => {
-                            self.addr.do_send(server::Instant {
-                                object: msg.object,
-                                owner_id: self.id,
-                                owner_name: self.name.clone(),
-                                room_name: self.room.to_owned(),
-                            });
-                            return;
+        let msg: Result<messages::inbound::KickObject, _> = serde_json::from_str(m);
+        match msg {
+            Ok(msg) => match msg.get_type() {
+                Ok(messages::inbound::Types::Kick) => {
+                    self.forward(server::Kick {
+                        object: msg.object,
+                        ban: msg.ban,
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(_) | Err(_) => (),
+            },
+            Err(_) => (),
+        }
+
+        let msg: Result<messages::inbound::UsizeObject, _> = serde_json::from_str(m);
+        match msg {
+            Ok(msg) => match msg.get_type() {
+                Ok(messages::inbound::Types::Elevate) => {
+                    self.forward(server::Elevate {
+                        object: msg.object,
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::Recede) => {
+                    self.forward(server::Recede {
+                        object: msg.object,
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::ConfirmElevate) => {
+                    self.forward(server::ConfirmElevate {
+                        object: msg.object,
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::TransferElevation) => {
+                    self.forward(server::TransferElevation {
+                        object: msg.object,
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::Mute) => {
+                    self.forward(server::Mute {
+                        object: msg.object,
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::Unmute) => {
+                    self.forward(server::Unmute {
+                        object: msg.object,
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(_) | Err(_) => (),
+            },
+            Err(_) => (),
+        }
+
+        let msg: Result<messages::inbound::ArbitraryObject, _> = serde_json::from_str(m);
+        match msg {
+            Ok(msg) => match msg.get_type() {
+                Ok(messages::inbound::Types::Instant) => {
+                    self.forward(server::Instant {
+                        object: msg.object,
+                        owner_id: self.id,
+                        owner_name: self.name.clone(),
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                        delay: msg.delay_secs.map(Duration::from_secs),
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::Raise) => {
+                    self.forward(server::Raise {
+                        object: msg.object,
+                        owner_id: self.id,
+                        owner_name: self.name.clone(),
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::Lower) => {
+                    self.forward(server::Lower {
+                        object: msg.object,
+                        owner_id: self.id,
+                        owner_name: self.name.clone(),
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                // numeric ids are handled above, accept ids encoded as strings as well
+                Ok(messages::inbound::Types::Elevate) => {
+                    match parse_user_id(&msg.object) {
+                        Some(object) => self.forward(server::Elevate {
+                            object,
+                            owner_id: self.id,
+                            room_name: self.target_room(&msg.room),
+                            msg_id: msg.msg_id,
+                        }),
+                        None => {
+                            self.send_error(ctx, "bad_object", "The object has to be a user id")
                         }
-                        Ok(messages::inbound::Types::Raise) => {
-                            self.addr.do_send(server::Raise {
-                                object: msg.object,
-                                owner_id: self.id,
-                                owner_name: self.name.clone(),
-                                room_name: self.room.to_owned(),
-                            });
-                            return;
+                    }
+                    return;
+                }
+                Ok(messages::inbound::Types::Recede) => {
+                    match parse_user_id(&msg.object) {
+                        Some(object) => self.forward(server::Recede {
+                            object,
+                            owner_id: self.id,
+                            room_name: self.target_room(&msg.room),
+                            msg_id: msg.msg_id,
+                        }),
+                        None => {
+                            self.send_error(ctx, "bad_object", "The object has to be a user id")
                         }
-                        Ok(messages::inbound::Types::Lower) => {
-                            self.addr.do_send(server::Lower {
-                                object: msg.object,
-                                owner_id: self.id,
-                                owner_name: self.name.clone(),
-                                room_name: self.room.to_owned(),
-                            });
+                    }
+                    return;
+                }
+                Ok(messages::inbound::Types::RaiseMany) => match msg.object {
+                    Arbitrary::Array(objects) => {
+                        self.forward(server::RaiseMany {
+                            objects,
+                            owner_id: self.id,
+                            owner_name: self.name.clone(),
+                            room_name: self.target_room(&msg.room),
+                            msg_id: msg.msg_id,
+                        });
+                        return;
+                    }
+                    _ => (),
+                },
+                Ok(messages::inbound::Types::EndRoom) => {
+                    self.forward(server::EndRoom {
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::ClearRaised) => {
+                    self.forward(server::ClearRaised {
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::CloseAllPolls) => {
+                    self.forward(server::CloseAllPolls {
+                        owner_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                        msg_id: msg.msg_id,
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::Roster) => {
+                    self.forward(server::Roster {
+                        user_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::RaisedCount) => {
+                    self.forward(server::RaisedCount {
+                        user_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::MyRaised) => {
+                    self.forward(server::MyRaised {
+                        user_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::ListPolls) => {
+                    self.forward(server::ListPolls {
+                        user_id: self.id,
+                        room_name: self.target_room(&msg.room),
+                    });
+                    return;
+                }
+                Ok(messages::inbound::Types::ElevateByName) => match msg.object.as_str() {
+                    Some(user_name) => {
+                        self.forward(server::ElevateByName {
+                            object: user_name.to_string(),
+                            owner_id: self.id,
+                            room_name: self.target_room(&msg.room),
+                            msg_id: msg.msg_id,
+                        });
+                        return;
+                    }
+                    None => (),
+                },
+                Ok(messages::inbound::Types::RecedeByName) => match msg.object.as_str() {
+                    Some(user_name) => {
+                        self.forward(server::RecedeByName {
+                            object: user_name.to_string(),
+                            owner_id: self.id,
+                            room_name: self.target_room(&msg.room),
+                            msg_id: msg.msg_id,
+                        });
+                        return;
+                    }
+                    None => (),
+                },
+                Ok(messages::inbound::Types::JoinRoom) => match msg.object.as_str() {
+                    Some(room_name) => {
+                        if let Err(error_code) = self.limits.validate(room_name, &self.name) {
+                            self.send_error(ctx, error_code, "The room name is too long");
                             return;
                         }
-                        Ok(_) | Err(_) => (),
-                    },
-                    Err(_) => (),
-                }
-
-                let testmsg: JsonResult<HashMap<String, Arbitrary>> = serde_json::from_str(m);
-                match testmsg {
-                    Err(_) => println!("Malformatted messge detected: {}", text),
-                    Ok(jsonmsg) => {
-                        let r#type = match jsonmsg["type"].as_str() {
-                            Some(res) => res,
-                            None => "NOT PARSEABLE",
-                        };
 
-                        match r#type {
-                            "raise" | "lower" | "instant" | "elevate" | "recede" | "poll"
-                            | "polloption" | "vote" | "closepoll" => {
-                                println!("[{}] Old delegating, DEPRECATED!", r#type)
+                        self.forward(server::JoinRoom {
+                            user_id: self.id,
+                            user_name: self.name.clone(),
+                            room_name: room_name.to_string(),
+                            spectator: self.spectator,
+                        });
+                        return;
+                    }
+                    None => (),
+                },
+                Ok(messages::inbound::Types::LeaveRoom) => match msg.object.as_str() {
+                    Some(room_name) => {
+                        self.forward(server::LeaveRoom {
+                            user_id: self.id,
+                            room_name: room_name.to_string(),
+                        });
+                        return;
+                    }
+                    None => (),
+                },
+                Ok(messages::inbound::Types::Rename) => match msg.object.as_str() {
+                    Some(user_name) => {
+                        let user_name = match normalize_name(user_name) {
+                            Ok(user_name) => user_name,
+                            Err(error_code) => {
+                                self.send_error(ctx, error_code, "The name is not allowed");
+                                return;
                             }
-                            _ => (),
+                        };
+                        if let Err(error_code) = self.limits.validate(&self.room, &user_name) {
+                            self.send_error(ctx, error_code, "The name is too long");
+                            return;
                         }
+
+                        self.name = user_name.clone();
+                        self.forward(server::RenameUser {
+                            user_id: self.id,
+                            user_name,
+                        });
+                        return;
                     }
-                };
-            }
+                    None => (),
+                },
+                Ok(messages::inbound::Types::Unban) => match msg.object.as_str() {
+                    Some(user_name) => {
+                        self.forward(server::Unban {
+                            object: user_name.to_string(),
+                            owner_id: self.id,
+                            room_name: self.target_room(&msg.room),
+                            msg_id: msg.msg_id,
+                        });
+                        return;
+                    }
+                    None => (),
+                },
+                Ok(_) | Err(_) => (),
+            },
+            Err(_) => (),
+        }
 
-            ws::Message::Binary(_) => println!("Unexpected binary"),
-            ws::Message::Close(_) => {
-                ctx.stop();
+        let testmsg: JsonResult<HashMap<String, Arbitrary>> = serde_json::from_str(m);
+        match testmsg {
+            Err(e) => {
+                println!("Malformatted messge detected: {}", text);
+
+                self.send_error(ctx, "parse_error", &parse_error_description(&e));
             }
+            Ok(jsonmsg) => {
+                let r#type = match jsonmsg["type"].as_str() {
+                    Some(res) => res,
+                    None => "NOT PARSEABLE",
+                };
 
-            ws::Message::Continuation(_) => {
-                ctx.stop();
+                match r#type {
+                    "raise" | "lower" | "instant" | "elevate" | "recede" | "poll"
+                    | "polloption" | "vote" | "closepoll" | "pollclose" => {
+                        println!("[{}] Old delegating, DEPRECATED!", r#type)
+                    }
+                    _ => (),
+                }
             }
+        };
+    }
 
-            ws::Message::Nop => (),
+    /// record activity of the client
+    ///
+    /// Keeps the session alive and the joined rooms from being closed as idle. Only traffic the
+    /// client starts counts, pongs answering the server's pings just keep the session alive.
+    fn touch(&mut self) {
+        self.hb = Instant::now();
+        // rooms are touched by the next activity if the web socket server is busy
+        let _ = self.addr.try_send(server::Activity { id: self.id });
+    }
+
+    /// forward a request of the client to the web socket server
+    ///
+    /// While the server's mailbox is full the request is dropped and the client gets a
+    /// `server_busy` error, so a flood of requests can't grow the mailbox without bound.
+    fn forward<M>(&mut self, msg: M)
+    where
+        M: actix::Message + Send + 'static,
+        M::Result: Send,
+        server::WebSocketServer: Handler<M>,
+    {
+        if let Err(SendError::Full(_)) = self.addr.try_send(msg) {
+            println!("Web socket server is busy, dropping request of {}", self.id);
+            self.busy = true;
+        }
+    }
+
+    /// send an error message to this session only
+    ///
+    /// # Arguments
+    /// * `error_code` - a string slice with a short error name
+    /// * `error_description` - a string slice with a longer description what went wrong
+    fn send_error(
+        &self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        error_code: &str,
+        error_description: &str,
+    ) {
+        let error_message = messages::outbound::to_frame(&messages::outbound::Error {
+            r#type: messages::outbound::Types::Error,
+            object: error_code.to_string(),
+            description: error_description.to_string(),
+        });
+        ctx.text(error_message);
+    }
+
+    /// close the connection with the close code of the reason and stop the session
+    fn close(&self, ctx: &mut ws::WebsocketContext<Self>, reason: server::StopReason) {
+        ctx.close(Some(close_reason(reason)));
+        ctx.stop();
+    }
+
+    /// get the room a message is targeted at
+    ///
+    /// Falls back to the room the session connected to if the message names no room
+    fn target_room(&self, room: &Option<String>) -> String {
+        match room {
+            Some(room) => room.to_owned(),
+            None => self.room.to_owned(),
         }
     }
-}
 
-impl WsWebSocketSession {
     /// helper method that sends ping to client every second.
     ///
     /// also this method checks heartbeats from client
@@ -299,57 +1155,1186 @@ impl WsWebSocketSession {
                 // heartbeat timed out
                 println!("Websocket Client heartbeat failed, disconnecting!");
 
-                // notify web socket server
-                act.addr.do_send(server::Disconnect { id: act.id });
-
-                // stop actor
-                ctx.stop();
+                // stop actor, stopping notifies the web socket server
+                act.close(ctx, server::StopReason::Timeout);
 
                 // don't try to send a ping
                 return;
             }
 
-            ctx.ping(b"");
+            act.ping_nonce = rand::random();
+            ctx.ping(&act.ping_nonce.to_be_bytes());
         });
     }
 }
 
+/// Close frame sent to the client when the server stops its session
+///
+/// | Reason          | Code | Description         |
+/// |-----------------|------|---------------------|
+/// | `Timeout`       | 4000 | `heartbeat_timeout` |
+/// | `Kicked`        | 4001 | `kicked`            |
+/// | `RoomEnded`     | 4002 | `room_ended`        |
+/// | `SlowConsumer`  | 4003 | `slow_consumer`     |
+/// | `ProtocolError` | 1002 | `protocol_error`    |
+/// | `Refused`       | 1008 | `join_refused`      |
+/// | `Busy`          | 1013 | `server_busy`       |
+fn close_reason(reason: server::StopReason) -> ws::CloseReason {
+    let (code, description) = match reason {
+        server::StopReason::Timeout => (
+            ws::CloseCode::Other(CLOSE_CODE_TIMEOUT),
+            "heartbeat_timeout",
+        ),
+        server::StopReason::Kicked => (ws::CloseCode::Other(CLOSE_CODE_KICKED), "kicked"),
+        server::StopReason::RoomEnded => {
+            (ws::CloseCode::Other(CLOSE_CODE_ROOM_ENDED), "room_ended")
+        }
+        server::StopReason::SlowConsumer => (
+            ws::CloseCode::Other(CLOSE_CODE_SLOW_CONSUMER),
+            "slow_consumer",
+        ),
+        server::StopReason::Replaced => (ws::CloseCode::Other(CLOSE_CODE_REPLACED), "replaced"),
+        server::StopReason::ProtocolError => (ws::CloseCode::Protocol, "protocol_error"),
+        server::StopReason::Refused => (ws::CloseCode::Policy, "join_refused"),
+        server::StopReason::Busy => (ws::CloseCode::Again, "server_busy"),
+    };
+
+    ws::CloseReason {
+        code,
+        description: Some(description.to_string()),
+    }
+}
+
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     env_logger::init();
     dotenv().ok();
 
-    let port = env::var("PORT").unwrap_or(env::var("VIMEET_PORT").unwrap_or("8080".to_string()));
+    let port = match select_port(env::var("PORT").ok(), env::var("VIMEET_PORT").ok()) {
+        Ok(port) => port,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
     let mut bind_address = env::var("VIMEET_BIND_ADDRESS").unwrap_or("127.0.0.1".to_string());
 
     bind_address.push_str(":");
-    bind_address.push_str(port.as_str());
-    println!("Binding server to {}", bind_address);
+    bind_address.push_str(port.to_string().as_str());
+
+    let mut config = server::Config::default();
+    if let Some(ban_duration) = env_duration_secs("VIMEET_BAN_SECS") {
+        config.ban_duration = ban_duration;
+    }
+    config.room_idle_timeout = env_duration_secs("VIMEET_ROOM_IDLE_SECS");
+    config.max_poll_options = env_number("VIMEET_MAX_POLL_OPTIONS");
+    config.max_open_polls = env_number("VIMEET_MAX_OPEN_POLLS");
+    config.poll_cooldown = env_duration_secs("VIMEET_POLL_COOLDOWN_SECS");
+    config.close_polls_on_owner_leave = env_flag("VIMEET_CLOSE_POLLS_ON_OWNER_LEAVE", false);
+    config.confirm_elevation = env_flag("VIMEET_CONFIRM_ELEVATION", false);
+    if let Some(mailbox_capacity) = env_number("VIMEET_SERVER_MAILBOX_CAPACITY") {
+        config.mailbox_capacity = mailbox_capacity;
+    }
+    if let Some(max_title_length) = env_number("VIMEET_MAX_TITLE_LENGTH") {
+        config.max_title_length = max_title_length;
+    }
+    config.raise_requires_elevation = env_flag("VIMEET_RAISE_REQUIRES_ELEVATION", false);
+    config.max_object_raises = env_number("VIMEET_MAX_OBJECT_RAISES");
+    config.hide_participants = env_flag("VIMEET_HIDE_PARTICIPANTS", false);
+    config.snapshot_interval = env_duration_secs("VIMEET_SNAPSHOT_INTERVAL_SECS");
+    config.max_votes_per_user = env_number("VIMEET_MAX_VOTES_PER_USER");
+    if let Ok(admin_names) = env::var("VIMEET_ADMIN_NAMES") {
+        config.admin_names = admin_names
+            .split(',')
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string())
+            .collect();
+    }
+    let has_admin_names = !config.admin_names.is_empty();
+    if let Ok(pattern) = env::var("VIMEET_ROOM_NAME_PATTERN") {
+        // the pattern has to match the whole room name
+        match regex::Regex::new(&format!("^(?:{})$", pattern)) {
+            Ok(pattern) => config.room_name_pattern = Some(pattern),
+            Err(e) => println!(
+                "Invalid VIMEET_ROOM_NAME_PATTERN '{}': {}, ignoring",
+                pattern, e
+            ),
+        }
+    }
+    if let Ok(echo) = env::var("VIMEET_ECHO") {
+        config.echo = echo
+            .split(',')
+            .map(|echo| echo.trim())
+            .filter(|echo| !echo.is_empty())
+            .filter_map(|echo| match echo.parse::<server::Echo>() {
+                Ok(echo) => Some(echo),
+                Err(e) => {
+                    println!("{}, ignoring", e);
+                    None
+                }
+            })
+            .collect();
+    }
+    if let Ok(elevation_mode) = env::var("VIMEET_ELEVATION_MODE") {
+        match elevation_mode.parse::<server::ElevationMode>() {
+            Ok(elevation_mode) => config.elevation_mode = elevation_mode,
+            Err(e) => println!("{}, ignoring", e),
+        }
+    }
 
     // Start web socket server actor
-    let server = server::WebSocketServer::default().start();
+    let mut server = server::WebSocketServer::new(config);
+    if let Ok(webhook_url) = env::var("VIMEET_WEBHOOK_URL") {
+        println!("Posting events to webhook {}", webhook_url);
+        server = server.with_event_sink(Box::new(events::WebhookSink::new(webhook_url)));
+    }
+    if let Ok(path) = env::var("VIMEET_POLL_RESULTS_FILE") {
+        println!("Writing poll results to {}", path);
+        server = server.with_event_sink(Box::new(events::FileSink::new(path)));
+    }
+    let server = server.start();
+
+    // Start audit log actor, if enabled
+    let audit = match env::var("VIMEET_AUDIT_LOG") {
+        Err(_) => None,
+        Ok(path) => {
+            let max_size = env::var("VIMEET_AUDIT_LOG_MAX_BYTES")
+                .ok()
+                .and_then(|max_size| max_size.parse::<u64>().ok())
+                .unwrap_or(audit::DEFAULT_MAX_SIZE);
+
+            // open the file once to report errors, the log's thread opens it for writing
+            match audit::AuditLog::new(&path, max_size) {
+                Ok(_) => {
+                    println!("Writing audit log to {}", path);
+                    Some(SyncArbiter::start(1, move || {
+                        audit::AuditLog::new(&path, max_size).expect("Cannot reopen audit log")
+                    }))
+                }
+                Err(e) => {
+                    println!("Cannot open audit log {}: {}", path, e);
+                    None
+                }
+            }
+        }
+    };
+
+    let api_token = api::ApiToken(env::var("VIMEET_API_TOKEN").ok());
+    let auth = match (env::var("VIMEET_AUTH_TOKEN"), env::var("VIMEET_JWT_SECRET")) {
+        (_, Ok(jwt_secret)) => auth::Auth::Jwt(jwt_secret),
+        (Ok(auth_token), Err(_)) => auth::Auth::Token(auth_token),
+        (Err(_), Err(_)) => auth::Auth::None,
+    };
+    if has_admin_names && matches!(auth, auth::Auth::None) {
+        println!("VIMEET_ADMIN_NAMES is set without authentication, anybody can join as admin");
+    }
+    let max_connections = MaxConnections::new(env_number("VIMEET_MAX_CONNECTIONS"));
+    let peer_connections = PeerConnections::new(env_number("VIMEET_MAX_CONNECTIONS_PER_PEER"));
+    let maintenance = api::Maintenance::default();
+    let join_timeout = JoinTimeout(
+        env_number::<u64>("VIMEET_JOIN_TIMEOUT_MS")
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_JOIN_TIMEOUT),
+    );
+    let compression_threshold = CompressionThreshold(
+        env_number("VIMEET_COMPRESSION_THRESHOLD").unwrap_or(DEFAULT_COMPRESSION_THRESHOLD),
+    );
+    let serve_static = env_flag("VIMEET_SERVE_STATIC", true);
+    let mut limits = limits::Limits::default();
+    if let Some(max_name_len) = env_number("VIMEET_MAX_NAME_LEN") {
+        limits.max_name_len = max_name_len;
+    }
+    if let Some(max_room_len) = env_number("VIMEET_MAX_ROOM_LEN") {
+        limits.max_room_len = max_room_len;
+    }
+    let start_time = StartTime {
+        system: SystemTime::now(),
+        instant: Instant::now(),
+    };
 
     // Create Http server with websocket support
-    HttpServer::new(move || {
-        App::new()
+    let mut http_server = HttpServer::new(move || {
+        let app = App::new()
             .data(server.clone())
-            // redirect to websocket.html
-            .service(web::resource("/").route(web::get().to(|| {
-                HttpResponse::Found()
-                    .header("LOCATION", "/static/websocket.html")
-                    .finish()
-            })))
+            .data(audit.clone())
+            .data(api_token.clone())
+            .data(max_connections.clone())
+            .data(peer_connections.clone())
+            .data(maintenance.clone())
+            .data(start_time)
+            .data(limits)
+            .data(join_timeout)
+            .data(auth.clone())
+            .data(compression_threshold)
+            // server info
+            .service(web::resource("/info").route(web::get().to(info_route)))
+            .service(web::resource("/validate").route(web::post().to(validate_route)))
             // websocket
             .service(web::resource("/ws/{room}/{name}/").to(web_socket_route))
-            // static resources
-            .service(fs::Files::new("/static/", "static/"))
-    })
-    .bind(bind_address.as_str())?
-    .run()
-    .await
+            .service(
+                web::resource("/admin/maintenance").route(web::post().to(api::maintenance_route)),
+            )
+            .service(web::resource("/admin/{room}/").to(admin_route))
+            // http api
+            .service(
+                web::resource("/rooms/{room}/polls").route(web::post().to(api::create_poll_route)),
+            )
+            .service(
+                web::resource("/rooms/{room}/export").route(web::get().to(api::export_room_route)),
+            );
+
+        if serve_static {
+            app.configure(static_routes)
+        } else {
+            app
+        }
+    });
+
+    // connection timeouts, actix defaults are used if unset
+    if let Some(keep_alive) = env_number::<usize>("VIMEET_KEEPALIVE_SECS") {
+        http_server = http_server.keep_alive(keep_alive);
+    }
+    if let Some(client_shutdown) = env_number::<u64>("VIMEET_CLIENT_SHUTDOWN_SECS") {
+        http_server = http_server.client_shutdown(client_shutdown.saturating_mul(1000));
+    }
+    if let Some(client_timeout) = env_number::<u64>("VIMEET_CLIENT_TIMEOUT_MS") {
+        http_server = http_server.client_timeout(client_timeout);
+    }
+
+    #[cfg(unix)]
+    {
+        if let Ok(socket_path) = env::var("VIMEET_UNIX_SOCKET") {
+            if let Err(e) = remove_stale_socket(&socket_path) {
+                println!("Cannot remove stale unix socket {}: {}", socket_path, e);
+                return Err(e);
+            }
+
+            println!("Binding server to unix socket {}", socket_path);
+            return match http_server.bind_uds(&socket_path) {
+                Ok(http_server) => http_server.run().await,
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::PermissionDenied {
+                        println!("Permission denied binding to unix socket {}", socket_path);
+                    }
+                    Err(e)
+                }
+            };
+        }
+    }
+
+    println!("Binding server to {}", bind_address);
+    http_server.bind(bind_address.as_str())?.run().await
+}
+
+/// Remove the socket file a previous run left behind
+///
+/// Any other kind of file at the path is refused, so a misconfigured path can't delete data.
+///
+/// # Arguments
+/// * `socket_path` - The path the server binds its unix socket to
+#[cfg(unix)]
+fn remove_stale_socket(socket_path: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = match std::fs::symlink_metadata(socket_path) {
+        Err(_) => return Ok(()),
+        Ok(metadata) => metadata,
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a unix socket", socket_path),
+        ));
+    }
+    std::fs::remove_file(socket_path)
 }
 
 fn get_id() -> usize {
     static COUNTER: AtomicUsize = AtomicUsize::new(1);
     COUNTER.fetch_add(1, Ordering::Relaxed)
 }
+
+/// Take the user name from the last segment of the raw URL path
+///
+/// actix decodes path parameters except for `%2F` (`/`) and `%2B` (`+`), decoding those once more
+/// would also decode an encoded `%` twice. So the raw segment is decoded here in a single pass,
+/// a name is then the same no matter how the client encoded it.
+///
+/// # Arguments
+/// * `req` - The request to connect to a room
+fn path_name(req: &HttpRequest) -> Result<String, &'static str> {
+    let segment = req
+        .uri()
+        .path()
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    percent_decode(segment)
+}
+
+/// Decode a percent-encoded URL path segment
+///
+/// Fails for malformed escapes and if the decoded bytes are no valid UTF-8.
+///
+/// # Arguments
+/// * `segment` - The raw path segment
+fn percent_decode(segment: &str) -> Result<String, &'static str> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = segment
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or("invalid_name")?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| "invalid_name")
+}
+
+/// Normalize a decoded user name
+///
+/// Names may contain any characters except `/` and control characters, surrounding whitespace is
+/// trimmed.
+///
+/// # Arguments
+/// * `name` - The decoded name, from the path, the token or a rename
+fn normalize_name(name: &str) -> Result<String, &'static str> {
+    let name = name.trim();
+
+    if name.is_empty() || name.contains('/') || name.chars().any(char::is_control) {
+        return Err("invalid_name");
+    }
+    Ok(name.to_string())
+}
+
+/// Answer an application level `ping` message with a `pong`
+///
+/// Returns `None` if the message is no `ping`. Unrelated to websocket ping frames.
+///
+/// # Arguments
+/// * `text` - A text message of a client
+fn app_pong(text: &str) -> Option<String> {
+    let ping: messages::inbound::ArbitraryObject = serde_json::from_str(text.trim()).ok()?;
+    match ping.get_type() {
+        Ok(messages::inbound::Types::Ping) => {
+            Some(messages::outbound::to_frame(&messages::outbound::Pong {
+                r#type: messages::outbound::Types::Pong,
+                object: ping.object,
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Compress a message with gzip
+///
+/// # Arguments
+/// * `text` - The message to compress
+fn gzip(text: &str) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    encoder.finish()
+}
+
+/// Parse a user id sent either as number or as string
+///
+/// # Arguments
+/// * `object` - The `object` of an inbound message
+fn parse_user_id(object: &Arbitrary) -> Option<usize> {
+    match object {
+        Arbitrary::Number(number) => number.as_u64().map(|user_id| user_id as usize),
+        Arbitrary::String(string) => string.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// If a pong answers the last ping, which carried the nonce as payload
+///
+/// # Arguments
+/// * `ping_nonce` - The nonce of the last ping
+/// * `payload` - The payload of the received pong
+fn answers_ping(ping_nonce: u64, payload: &[u8]) -> bool {
+    payload == &ping_nonce.to_be_bytes()[..]
+}
+
+/// Describe where a message isn't valid JSON
+///
+/// Only the position is reported, the payload may be large or sensitive.
+fn parse_error_description(error: &serde_json::Error) -> String {
+    format!(
+        "Invalid JSON at line {} column {}",
+        error.line(),
+        error.column()
+    )
+}
+
+/// Keep the requested capabilities the server supports
+///
+/// # Arguments
+/// * `caps` - Comma separated capabilities of the `caps` query parameter, if sent
+fn negotiate_capabilities(caps: Option<&str>) -> HashSet<String> {
+    match caps {
+        None => HashSet::new(),
+        Some(caps) => caps
+            .split(',')
+            .map(|capability| capability.trim())
+            .filter(|capability| messages::outbound::CAPABILITIES.contains(capability))
+            .map(|capability| capability.to_string())
+            .collect(),
+    }
+}
+
+/// Add a continuation frame to the fragments of a message
+///
+/// Returns the complete message as `(is_text, data)` with its last fragment, `None` while more
+/// fragments are expected and an error on a protocol violation.
+///
+/// # Arguments
+/// * `continuation` - Fragments received so far as `(is_text, data)`, `None` outside a message
+/// * `item` - The received continuation frame
+fn continue_message(
+    continuation: &mut Option<(bool, Vec<u8>)>,
+    item: WsItem,
+) -> Result<Option<(bool, Vec<u8>)>, &'static str> {
+    let (first, data, last) = match item {
+        WsItem::FirstText(data) => (Some(true), data, false),
+        WsItem::FirstBinary(data) => (Some(false), data, false),
+        WsItem::Continue(data) => (None, data, false),
+        WsItem::Last(data) => (None, data, true),
+    };
+
+    // a new fragmented message must not start before the last one is complete,
+    // and continued fragments need a started message
+    match (first, &mut *continuation) {
+        (Some(is_text), None) => *continuation = Some((is_text, Vec::new())),
+        (None, Some(_)) => (),
+        (_, _) => return Err("Unexpected continuation frame"),
+    }
+
+    if let Some((_, buffer)) = continuation {
+        buffer.extend_from_slice(&data);
+        if buffer.len() > MAX_MESSAGE_SIZE {
+            return Err("Fragmented message too large");
+        }
+    }
+
+    if last {
+        Ok(continuation.take())
+    } else {
+        Ok(None)
+    }
+}
+
+/// Select the port to bind to
+///
+/// Precedence is `PORT` > `VIMEET_PORT` > `DEFAULT_PORT`
+///
+/// # Arguments
+/// * `port` - Value of the `PORT` environment variable, if set
+/// * `vimeet_port` - Value of the `VIMEET_PORT` environment variable, if set
+fn select_port(port: Option<String>, vimeet_port: Option<String>) -> Result<u16, String> {
+    let (key, value) = match (port, vimeet_port) {
+        (Some(port), _) => ("PORT", port),
+        (None, Some(vimeet_port)) => ("VIMEET_PORT", vimeet_port),
+        (None, None) => return Ok(DEFAULT_PORT),
+    };
+
+    value
+        .trim()
+        .parse::<u16>()
+        .map_err(|_| format!("Invalid {} '{}', expected a port number", key, value))
+}
+
+/// Read an environment variable holding a number of seconds
+///
+/// Returns `None` if the variable is unset or not a valid number of seconds
+fn env_duration_secs(key: &str) -> Option<Duration> {
+    env_number::<u64>(key).map(Duration::from_secs)
+}
+
+/// Read an environment variable holding a flag
+///
+/// Accepts `true`/`1` and `false`/`0`, returns `default` if the variable is unset or invalid
+fn env_flag(key: &str, default: bool) -> bool {
+    match env::var(key).as_ref().map(|value| value.as_str()) {
+        Ok("true") | Ok("1") => true,
+        Ok("false") | Ok("0") => false,
+        Ok(value) => {
+            println!("Invalid {} '{}', ignoring", key, value);
+            default
+        }
+        Err(_) => default,
+    }
+}
+
+/// Read an environment variable holding a number
+///
+/// Returns `None` if the variable is unset or not a valid number
+fn env_number<T: FromStr>(key: &str) -> Option<T> {
+    match env::var(key) {
+        Err(_) => None,
+        Ok(value) => match value.parse::<T>() {
+            Ok(number) => Some(number),
+            Err(_) => {
+                println!("Invalid {} '{}', ignoring", key, value);
+                None
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+
+    #[cfg(unix)]
+    #[test]
+    fn only_stale_sockets_are_removed() {
+        let dir = std::env::temp_dir().join(format!("vimeet-socket-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+
+        // nothing to remove
+        assert!(remove_stale_socket(&path("missing.sock")).is_ok());
+
+        let socket = path("stale.sock");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        assert!(remove_stale_socket(&socket).is_ok());
+        assert!(!std::path::Path::new(&socket).exists());
+
+        let file = path("data.txt");
+        std::fs::write(&file, "keep me").unwrap();
+        let error = remove_stale_socket(&file).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep me");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[actix_rt::test]
+    async fn the_server_answers_on_a_unix_socket() {
+        use std::io::Read;
+        use std::os::unix::net::UnixStream;
+
+        let dir = std::env::temp_dir().join(format!("vimeet-uds-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("vimeet.sock");
+
+        let start_time = StartTime {
+            system: SystemTime::now(),
+            instant: Instant::now(),
+        };
+        let server = HttpServer::new(move || {
+            App::new()
+                .data(start_time)
+                .service(web::resource("/info").route(web::get().to(info_route)))
+        })
+        .workers(1)
+        .disable_signals()
+        .bind_uds(&socket)
+        .unwrap()
+        .run();
+
+        // the client blocks, so it runs on the thread pool while the workers serve it
+        let client_socket = socket.clone();
+        let response = web::block(move || -> std::io::Result<String> {
+            let mut stream = UnixStream::connect(&client_socket)?;
+            stream
+                .write_all(b"GET /info HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response)?;
+            Ok(response)
+        })
+        .await
+        .unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let body: Arbitrary =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+
+        server.stop(true).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn app_level_pings_are_answered() {
+        let pong: Arbitrary =
+            serde_json::from_str(&app_pong(r#"{"type": "ping", "object": 7}"#).unwrap()).unwrap();
+        assert_eq!(pong, json!({"type": "pong", "object": 7}));
+
+        let pong: Arbitrary =
+            serde_json::from_str(&app_pong(r#"{"type": "ping"}"#).unwrap()).unwrap();
+        assert_eq!(pong["object"], Arbitrary::Null);
+
+        assert_eq!(app_pong(r#"{"type": "raise", "object": "hand"}"#), None);
+        assert_eq!(app_pong("ping"), None);
+    }
+
+    #[test]
+    fn connections_per_peer_are_capped() {
+        let peers = PeerConnections::new(Some(2));
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+
+        let first = peers.acquire(client).unwrap();
+        let second = peers.acquire(client).unwrap();
+        assert!(peers.acquire(client).is_none());
+        assert!(peers.acquire(other).is_some());
+
+        // a disconnect frees the slot of its address
+        drop(first);
+        let third = peers.acquire(client);
+        assert!(third.is_some());
+        assert!(peers.acquire(client).is_none());
+
+        drop(second);
+        drop(third);
+        assert!(peers.open.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn connections_per_peer_are_unlimited_by_default() {
+        let peers = PeerConnections::new(None);
+        let client: IpAddr = "192.0.2.1".parse().unwrap();
+        let slots: Vec<PeerSlot> = (0..100).filter_map(|_| peers.acquire(client)).collect();
+        assert_eq!(slots.len(), 100);
+    }
+
+    #[actix_rt::test]
+    async fn maintenance_mode_refuses_new_connections() {
+        let maintenance = api::Maintenance::default();
+        let mut app = test::init_service(
+            App::new()
+                .data(server::WebSocketServer::new(server::Config::default()).start())
+                .data(None::<Addr<audit::AuditLog>>)
+                .data(api::ApiToken(Some("secret".to_string())))
+                .data(MaxConnections::new(None))
+                .data(PeerConnections::new(None))
+                .data(limits::Limits::default())
+                .data(JoinTimeout(DEFAULT_JOIN_TIMEOUT))
+                .data(auth::Auth::None)
+                .data(CompressionThreshold(DEFAULT_COMPRESSION_THRESHOLD))
+                .data(maintenance.clone())
+                .service(
+                    web::resource("/admin/maintenance")
+                        .route(web::post().to(api::maintenance_route)),
+                )
+                .service(web::resource("/ws/{room}/{name}/").to(web_socket_route)),
+        )
+        .await;
+        let toggle = |enabled: bool, token: &str| {
+            test::TestRequest::post()
+                .uri("/admin/maintenance")
+                .header("Authorization", format!("Bearer {}", token))
+                .set_json(&json!({ "enabled": enabled }))
+                .to_request()
+        };
+        let connect = || {
+            test::TestRequest::get()
+                .uri("/ws/room/alice/")
+                .header("Connection", "upgrade")
+                .header("Upgrade", "websocket")
+                .header("Sec-WebSocket-Version", "13")
+                .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+                .to_request()
+        };
+
+        let response = test::call_service(&mut app, toggle(true, "wrong")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(!maintenance.enabled());
+
+        let response = test::call_service(&mut app, toggle(true, "secret")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = test::call_service(&mut app, connect()).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(&test::read_body(response).await[..], b"maintenance");
+
+        let response = test::call_service(&mut app, toggle(false, "secret")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = test::call_service(&mut app, connect()).await;
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    }
+
+    #[actix_rt::test]
+    async fn web_socket_subprotocol_is_negotiated() {
+        let mut app = test::init_service(
+            App::new()
+                .data(server::WebSocketServer::new(server::Config::default()).start())
+                .data(None::<Addr<audit::AuditLog>>)
+                .data(MaxConnections::new(None))
+                .data(PeerConnections::new(None))
+                .data(limits::Limits::default())
+                .data(JoinTimeout(DEFAULT_JOIN_TIMEOUT))
+                .data(auth::Auth::None)
+                .data(CompressionThreshold(DEFAULT_COMPRESSION_THRESHOLD))
+                .data(api::Maintenance::default())
+                .service(web::resource("/ws/{room}/{name}/").to(web_socket_route)),
+        )
+        .await;
+        let connect = |protocols: &str| {
+            test::TestRequest::get()
+                .uri("/ws/room/alice/")
+                .header("Connection", "upgrade")
+                .header("Upgrade", "websocket")
+                .header("Sec-WebSocket-Version", "13")
+                .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+                .header("Sec-WebSocket-Protocol", protocols)
+                .to_request()
+        };
+
+        let response = test::call_service(&mut app, connect("chat, vimeet.v2")).await;
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(
+            response.headers().get("Sec-WebSocket-Protocol").unwrap(),
+            "vimeet.v2"
+        );
+
+        let response = test::call_service(&mut app, connect("vimeet.v1")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            &test::read_body(response).await[..],
+            b"unsupported_subprotocol"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn http_routes_negotiate_json() {
+        let mut app = test::init_service(
+            App::new()
+                .data(StartTime {
+                    system: SystemTime::now(),
+                    instant: Instant::now(),
+                })
+                .service(web::resource("/info").route(web::get().to(info_route)))
+                .service(web::resource("/validate").route(web::post().to(validate_route))),
+        )
+        .await;
+        let info = |accept: Option<&str>| {
+            let request = test::TestRequest::get().uri("/info");
+            match accept {
+                Some(accept) => request.header("Accept", accept).to_request(),
+                None => request.to_request(),
+            }
+        };
+
+        for accept in &[
+            None,
+            Some("application/json"),
+            Some("text/html, application/json;q=0.5"),
+            Some("*/*"),
+        ] {
+            let response = test::call_service(&mut app, info(*accept)).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(
+                response.headers().get("Content-Type").unwrap(),
+                "application/json"
+            );
+        }
+
+        for accept in &["text/html", "application/json;q=0", "text/plain, image/*"] {
+            let response = test::call_service(&mut app, info(Some(accept))).await;
+            assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        }
+
+        let request = test::TestRequest::post()
+            .uri("/validate")
+            .header("Accept", "text/html")
+            .set_payload(r#"{"type": "raise", "object": "hand"}"#)
+            .to_request();
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+        assert_eq!(
+            response.headers().get("Content-Type").unwrap(),
+            "application/json"
+        );
+    }
+
+    #[test]
+    fn heartbeat_timeout_closes_with_timeout_code() {
+        let reason = close_reason(server::StopReason::Timeout);
+        assert_eq!(reason.code, ws::CloseCode::Other(4000));
+        assert_eq!(reason.description.as_deref(), Some("heartbeat_timeout"));
+    }
+
+    #[test]
+    fn server_stops_have_distinct_close_codes() {
+        let codes: Vec<u16> = vec![
+            server::StopReason::Timeout,
+            server::StopReason::ProtocolError,
+            server::StopReason::Busy,
+            server::StopReason::Refused,
+            server::StopReason::Kicked,
+            server::StopReason::RoomEnded,
+            server::StopReason::SlowConsumer,
+            server::StopReason::Replaced,
+        ]
+        .into_iter()
+        .map(|reason| close_reason(reason).code.into())
+        .collect();
+        let distinct: HashSet<&u16> = codes.iter().collect();
+        assert_eq!(distinct.len(), codes.len());
+    }
+
+    #[test]
+    fn port_precedence_and_validation() {
+        let some = |port: &str| Some(port.to_string());
+
+        assert_eq!(select_port(None, None), Ok(DEFAULT_PORT));
+        assert_eq!(select_port(some("9000"), None), Ok(9000));
+        assert_eq!(select_port(None, some("9001")), Ok(9001));
+        assert_eq!(select_port(some("9000"), some("9001")), Ok(9000));
+        assert_eq!(select_port(some(" 9000\n"), None), Ok(9000));
+
+        assert!(select_port(some("http"), None)
+            .unwrap_err()
+            .contains("PORT"));
+        assert!(select_port(None, some("65536"))
+            .unwrap_err()
+            .contains("VIMEET_PORT"));
+        // an invalid PORT isn't hidden by a valid VIMEET_PORT
+        assert!(select_port(some("-1"), some("9001")).is_err());
+    }
+
+    #[test]
+    fn fragmented_messages_are_reassembled() {
+        let fragment = |data: &'static str| bytes::Bytes::from_static(data.as_bytes());
+        let mut continuation = None;
+
+        let frames = vec![
+            WsItem::FirstText(fragment(r#"{"type": "raise", "#)),
+            WsItem::Continue(fragment(r#""object": "#)),
+            WsItem::Last(fragment(r#""hand", "room": "room"}"#)),
+        ];
+        let mut messages: Vec<_> = frames
+            .into_iter()
+            .map(|item| continue_message(&mut continuation, item).unwrap())
+            .collect();
+
+        assert_eq!(messages[0], None);
+        assert_eq!(messages[1], None);
+        let (is_text, data) = messages.pop().unwrap().unwrap();
+        assert!(is_text);
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            r#"{"type": "raise", "object": "hand", "room": "room"}"#
+        );
+        assert!(continuation.is_none());
+    }
+
+    #[test]
+    fn unexpected_and_oversized_fragments_are_refused() {
+        let mut continuation = None;
+        assert!(continue_message(&mut continuation, WsItem::Continue("a".into())).is_err());
+        assert!(continue_message(&mut continuation, WsItem::Last("a".into())).is_err());
+
+        let mut continuation = None;
+        continue_message(&mut continuation, WsItem::FirstText("a".into())).unwrap();
+        assert!(continue_message(&mut continuation, WsItem::FirstText("a".into())).is_err());
+
+        let mut continuation = None;
+        let chunk = bytes::Bytes::from(vec![b'a'; MAX_MESSAGE_SIZE / 2]);
+        continue_message(&mut continuation, WsItem::FirstText(chunk.clone())).unwrap();
+        continue_message(&mut continuation, WsItem::Continue(chunk)).unwrap();
+        assert!(continue_message(&mut continuation, WsItem::Last("a".into())).is_err());
+    }
+
+    #[test]
+    fn only_supported_capabilities_are_agreed() {
+        assert!(negotiate_capabilities(None).is_empty());
+        assert!(negotiate_capabilities(Some("")).is_empty());
+
+        let agreed = negotiate_capabilities(Some("delta, chat,gzip,delta"));
+        let mut agreed: Vec<&str> = agreed.iter().map(String::as_str).collect();
+        agreed.sort();
+        assert_eq!(agreed, vec!["delta", "gzip"]);
+    }
+
+    #[test]
+    fn parse_errors_report_the_position_but_not_the_payload() {
+        let text = "{\n  \"type\": \"raise\",\n  \"object\": secret\n}";
+        let error = serde_json::from_str::<HashMap<String, Arbitrary>>(text).unwrap_err();
+
+        let description = parse_error_description(&error);
+        assert_eq!(description, "Invalid JSON at line 3 column 13");
+        assert!(!description.contains("secret"));
+    }
+
+    #[test]
+    fn connections_are_capped() {
+        let connections = MaxConnections::new(Some(2));
+
+        let first = connections.acquire().unwrap();
+        let second = connections.acquire();
+        assert!(second.is_some());
+        assert!(connections.acquire().is_none());
+
+        // a stopped session frees its slot, whatever stopped it
+        drop(first);
+        let third = connections.acquire();
+        assert!(third.is_some());
+        assert!(connections.acquire().is_none());
+
+        drop(second);
+        drop(third);
+        assert_eq!(connections.open.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn connections_are_unlimited_by_default() {
+        let connections = MaxConnections::new(None);
+        let slots: Vec<ConnectionSlot> = (0..100).filter_map(|_| connections.acquire()).collect();
+        assert_eq!(slots.len(), 100);
+    }
+
+    #[actix_rt::test]
+    async fn info_reports_version_and_uptime() {
+        let started = SystemTime::now() - Duration::from_secs(90);
+        let mut app = test::init_service(
+            App::new()
+                .data(StartTime {
+                    system: started,
+                    instant: Instant::now() - Duration::from_secs(90),
+                })
+                .service(web::resource("/info").route(web::get().to(info_route))),
+        )
+        .await;
+
+        let request = test::TestRequest::get().uri("/info").to_request();
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let info: Arbitrary = serde_json::from_slice(&test::read_body(response).await).unwrap();
+
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["commit"].as_str(), option_env!("VIMEET_GIT_COMMIT"));
+        assert_eq!(
+            info["started"],
+            started.duration_since(UNIX_EPOCH).unwrap().as_secs()
+        );
+        assert!(info["uptime"].as_u64().unwrap() >= 90);
+    }
+
+    #[test]
+    fn only_pongs_with_the_ping_nonce_answer_the_ping() {
+        let nonce: u64 = 0x0123_4567_89ab_cdef;
+        assert!(answers_ping(nonce, &nonce.to_be_bytes()));
+        assert!(!answers_ping(nonce, &(nonce - 1).to_be_bytes()));
+        assert!(!answers_ping(nonce, &nonce.to_le_bytes()));
+        assert!(!answers_ping(nonce, b""));
+        assert!(!answers_ping(nonce, &nonce.to_be_bytes()[..4]));
+    }
+
+    #[test]
+    fn user_ids_are_parsed_from_numbers_and_strings() {
+        assert_eq!(parse_user_id(&json!(5)), Some(5));
+        assert_eq!(parse_user_id(&json!(0)), Some(0));
+        assert_eq!(parse_user_id(&json!("5")), Some(5));
+        assert_eq!(parse_user_id(&json!(" 42 ")), Some(42));
+
+        assert_eq!(parse_user_id(&json!(-1)), None);
+        assert_eq!(parse_user_id(&json!(1.5)), None);
+        assert_eq!(parse_user_id(&json!("five")), None);
+        assert_eq!(parse_user_id(&json!("\"5\"")), None);
+        assert_eq!(parse_user_id(&json!(null)), None);
+        assert_eq!(parse_user_id(&json!([5])), None);
+    }
+
+    #[test]
+    fn invalid_numbers_fall_back_to_the_default() {
+        // keys only used by this test, the environment is shared by all tests
+        env::set_var("VIMEET_TEST_KEEPALIVE_SECS", "30");
+        env::set_var("VIMEET_TEST_CLIENT_SHUTDOWN_SECS", "soon");
+        env::set_var("VIMEET_TEST_CLIENT_TIMEOUT_MS", "-1");
+
+        assert_eq!(env_number::<usize>("VIMEET_TEST_KEEPALIVE_SECS"), Some(30));
+        assert_eq!(env_number::<u64>("VIMEET_TEST_CLIENT_SHUTDOWN_SECS"), None);
+        assert_eq!(env_number::<u64>("VIMEET_TEST_CLIENT_TIMEOUT_MS"), None);
+        assert_eq!(env_number::<u64>("VIMEET_TEST_UNSET"), None);
+    }
+
+    #[actix_rt::test]
+    async fn root_is_only_redirected_when_serving_static_files() {
+        let mut app = test::init_service(App::new().configure(static_routes)).await;
+        let request = test::TestRequest::get().uri("/").to_request();
+        let response = test::call_service(&mut app, request).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers().get("Location").unwrap(),
+            "/static/websocket.html"
+        );
+
+        let mut app = test::init_service(
+            App::new().service(web::resource("/info").route(web::get().to(info_route))),
+        )
+        .await;
+        for uri in &["/", "/static/websocket.html"] {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&mut app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[test]
+    fn names_are_normalized_whatever_the_encoding() {
+        assert_eq!(normalize_name("alice"), Ok("alice".to_string()));
+        assert_eq!(normalize_name(" alice bob "), Ok("alice bob".to_string()));
+        assert_eq!(normalize_name("a+b"), Ok("a+b".to_string()));
+        assert_eq!(normalize_name("a%2Fb"), Ok("a%2Fb".to_string()));
+
+        assert_eq!(normalize_name("a/b"), Err("invalid_name"));
+        assert_eq!(normalize_name("a\u{7}b"), Err("invalid_name"));
+        assert_eq!(normalize_name("   "), Err("invalid_name"));
+    }
+
+    #[test]
+    fn path_segments_are_decoded_once() {
+        assert_eq!(percent_decode("alice"), Ok("alice".to_string()));
+        assert_eq!(percent_decode("a%2Bb"), Ok("a+b".to_string()));
+        assert_eq!(percent_decode("a%2fb"), Ok("a/b".to_string()));
+        assert_eq!(percent_decode("50%25"), Ok("50%".to_string()));
+        assert_eq!(percent_decode("a%252Fb"), Ok("a%2Fb".to_string()));
+        assert_eq!(percent_decode("%C3%A4"), Ok("\u{e4}".to_string()));
+
+        assert_eq!(percent_decode("50%"), Err("invalid_name"));
+        assert_eq!(percent_decode("%zz"), Err("invalid_name"));
+        assert_eq!(percent_decode("%+1"), Err("invalid_name"));
+        assert_eq!(percent_decode("%FF"), Err("invalid_name"));
+    }
+
+    #[actix_rt::test]
+    async fn encoded_names_are_decoded_in_the_path() {
+        let mut app = test::init_service(
+            App::new()
+                .data(server::WebSocketServer::new(server::Config::default()).start())
+                .data(None::<Addr<audit::AuditLog>>)
+                .data(MaxConnections::new(None))
+                .data(PeerConnections::new(None))
+                .data(limits::Limits::default())
+                .data(JoinTimeout(DEFAULT_JOIN_TIMEOUT))
+                .data(auth::Auth::None)
+                .data(CompressionThreshold(DEFAULT_COMPRESSION_THRESHOLD))
+                .data(api::Maintenance::default())
+                .service(web::resource("/ws/{room}/{name}/").to(web_socket_route)),
+        )
+        .await;
+        let connect = |name: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/ws/room/{}/", name))
+                .header("Connection", "upgrade")
+                .header("Upgrade", "websocket")
+                .header("Sec-WebSocket-Version", "13")
+                .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+                .to_request()
+        };
+
+        for name in &[
+            "alice%20bob",
+            "alice+bob",
+            "alice%2Bbob",
+            "50%25",
+            "a%252Fb",
+        ] {
+            let response = test::call_service(&mut app, connect(name)).await;
+            assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+        }
+        for name in &["alice%2Fbob", "alice%2fbob", "%20", "alice%07"] {
+            let response = test::call_service(&mut app, connect(name)).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            assert_eq!(&test::read_body(response).await[..], b"invalid_name");
+        }
+    }
+
+    /// Keeps the web socket server's arbiter busy, like an overloaded server
+    #[derive(Message)]
+    #[rtype(result = "()")]
+    struct Block(Duration);
+
+    impl Handler<Block> for server::WebSocketServer {
+        type Result = ();
+
+        fn handle(&mut self, msg: Block, _: &mut Context<Self>) {
+            std::thread::sleep(msg.0);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn joining_a_blocked_server_reports_server_busy() {
+        use futures::StreamExt;
+
+        let arbiter = Arbiter::new();
+        let server = server::WebSocketServer::start_in_arbiter(&arbiter, |_| {
+            server::WebSocketServer::new(server::Config::default())
+        });
+        server.do_send(Block(Duration::from_millis(500)));
+
+        let mut srv = test::start(move || {
+            App::new()
+                .data(server.clone())
+                .data(None::<Addr<audit::AuditLog>>)
+                .data(MaxConnections::new(None))
+                .data(PeerConnections::new(None))
+                .data(limits::Limits::default())
+                .data(JoinTimeout(Duration::from_millis(50)))
+                .data(auth::Auth::None)
+                .data(CompressionThreshold(DEFAULT_COMPRESSION_THRESHOLD))
+                .data(api::Maintenance::default())
+                .service(web::resource("/ws/{room}/{name}/").to(web_socket_route))
+        });
+        let mut framed = srv.ws_at("/ws/room/alice/").await.unwrap();
+
+        let error = match framed.next().await {
+            Some(Ok(ws::Frame::Text(text))) => serde_json::from_slice::<Arbitrary>(&text).unwrap(),
+            other => panic!("Expected an error frame, got {:?}", other),
+        };
+        assert_eq!(error["type"], "error");
+        assert_eq!(error["object"], "server_busy");
+
+        match framed.next().await {
+            Some(Ok(ws::Frame::Close(Some(reason)))) => {
+                assert_eq!(reason.code, ws::CloseCode::Again)
+            }
+            other => panic!("Expected a close frame, got {:?}", other),
+        }
+
+        arbiter.stop();
+    }
+
+    #[test]
+    fn large_snapshots_round_trip_through_gzip() {
+        use std::io::Read;
+
+        let joined: serde_json::Map<String, Arbitrary> = (0..500)
+            .map(|user_id| {
+                (
+                    user_id.to_string(),
+                    json!({"name": format!("user{}", user_id), "elevated": false}),
+                )
+            })
+            .collect();
+        let text =
+            json!({"type": "all", "raised": [], "joined": joined, "room": "room"}).to_string();
+        assert!(text.len() > DEFAULT_COMPRESSION_THRESHOLD);
+
+        let compressed = gzip(&text).unwrap();
+        assert!(compressed.len() < text.len() / 4);
+
+        let mut decompressed = String::new();
+        flate2::read::GzDecoder::new(&compressed[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, text);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod inbound {
+    use serde::de::DeserializeOwned;
     use serde::{Deserialize, Serialize};
     use serde_json::Value as Arbitrary;
     use std::collections::HashMap;
@@ -34,6 +35,9 @@ pub mod inbound {
     }
 
     /// All known types of incoming messages
+    ///
+    /// Closing and opening polls are named like their outbound messages, `pollclose` and
+    /// `pollopen`. The former names `closepoll` and `openpoll` stay accepted.
     #[derive(Debug)]
     pub enum Types {
         Raise,
@@ -45,6 +49,30 @@ pub mod inbound {
         PollOption,
         Vote,
         PollClose,
+        Kick,
+        Unban,
+        Mute,
+        Unmute,
+        JoinRoom,
+        LeaveRoom,
+        ElevateByName,
+        RecedeByName,
+        EndRoom,
+        RaiseMany,
+        TransferElevation,
+        PollOpen,
+        PollOptionRename,
+        SetSpectator,
+        ListPolls,
+        Rename,
+        CloseAllPolls,
+        ClearRaised,
+        Ping,
+        MyRaised,
+        PollStatus,
+        RaisedCount,
+        ConfirmElevate,
+        Roster,
     }
 
     impl FromStr for Types {
@@ -63,7 +91,31 @@ pub mod inbound {
                 "poll" => Ok(Types::Poll),
                 "polloption" => Ok(Types::PollOption),
                 "vote" => Ok(Types::Vote),
-                "closepoll" => Ok(Types::PollClose),
+                "pollclose" | "closepoll" => Ok(Types::PollClose),
+                "kick" => Ok(Types::Kick),
+                "unban" => Ok(Types::Unban),
+                "mute" => Ok(Types::Mute),
+                "unmute" => Ok(Types::Unmute),
+                "joinroom" => Ok(Types::JoinRoom),
+                "leaveroom" => Ok(Types::LeaveRoom),
+                "elevatebyname" => Ok(Types::ElevateByName),
+                "recedebyname" => Ok(Types::RecedeByName),
+                "endroom" => Ok(Types::EndRoom),
+                "raisemany" => Ok(Types::RaiseMany),
+                "transferelevation" => Ok(Types::TransferElevation),
+                "pollopen" | "openpoll" => Ok(Types::PollOpen),
+                "renameoption" => Ok(Types::PollOptionRename),
+                "setspectator" => Ok(Types::SetSpectator),
+                "listpolls" => Ok(Types::ListPolls),
+                "rename" => Ok(Types::Rename),
+                "closeallpolls" => Ok(Types::CloseAllPolls),
+                "clearraised" => Ok(Types::ClearRaised),
+                "ping" => Ok(Types::Ping),
+                "myraised" => Ok(Types::MyRaised),
+                "pollstatus" => Ok(Types::PollStatus),
+                "raisedcount" => Ok(Types::RaisedCount),
+                "confirmelevate" => Ok(Types::ConfirmElevate),
+                "roster" => Ok(Types::Roster),
                 _ => Err(InvalidMessageType {}),
             }
         }
@@ -72,11 +124,22 @@ pub mod inbound {
     /// Inbound message skeleton: Arbitrary object
     ///
     /// * `type` - Message type, see [Types](#struct.Types)
-    /// * `object` - Any value a JSON parameter can hold
+    /// * `object` - Any value a JSON parameter can hold, raised and instant objects must not contain
+    ///   the keys `type` or `room` at any depth. A missing object is `null`.
+    /// * `msg_id` - Client chosen ID to acknowledge the message with (optional)
+    /// * `room` - Joined room the message targets, defaults to the connected room (optional)
+    /// * `delay_secs` - Seconds to delay an instant by (optional)
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct ArbitraryObject {
         pub r#type: String,
+        #[serde(default)]
         pub object: Arbitrary,
+        #[serde(default)]
+        pub msg_id: Option<Arbitrary>,
+        #[serde(default)]
+        pub room: Option<String>,
+        #[serde(default)]
+        pub delay_secs: Option<u64>,
     }
 
     impl GetMessageType for ArbitraryObject {
@@ -101,10 +164,16 @@ pub mod inbound {
     ///
     /// * `type` - Message type, see [Types](#struct.Types)
     /// * `object` - A `usize` value
+    /// * `msg_id` - Client chosen ID to acknowledge the message with (optional)
+    /// * `room` - Joined room the message targets, defaults to the connected room (optional)
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct UsizeObject {
         pub r#type: String,
         pub object: usize,
+        #[serde(default)]
+        pub msg_id: Option<Arbitrary>,
+        #[serde(default)]
+        pub room: Option<String>,
     }
 
     impl GetMessageType for UsizeObject {
@@ -125,15 +194,121 @@ pub mod inbound {
         }
     }
 
+    /// Inbound message skeleton: Make a user spectator or participant
+    ///
+    /// * `type` - Message type, see [Types](#struct.Types)
+    /// * `object` - ID of the user
+    /// * `spectator` - If the user only observes the room
+    /// * `msg_id` - Client chosen ID to acknowledge the message with (optional)
+    /// * `room` - Joined room the message targets, defaults to the connected room (optional)
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct SpectatorObject {
+        pub r#type: String,
+        pub object: usize,
+        pub spectator: bool,
+        #[serde(default)]
+        pub msg_id: Option<Arbitrary>,
+        #[serde(default)]
+        pub room: Option<String>,
+    }
+
+    impl GetMessageType for SpectatorObject {
+        /// Get message type or error
+        fn get_type(&self) -> Result<Types, InvalidMessageType> {
+            Types::from_str(self.r#type.as_str())
+        }
+    }
+
+    /// Inbound message skeleton: Kick a user
+    ///
+    /// * `type` - Message type, see [Types](#struct.Types)
+    /// * `object` - ID of the user to kick
+    /// * `ban` - If the user's name should be banned from rejoining the room (optional)
+    /// * `msg_id` - Client chosen ID to acknowledge the message with (optional)
+    /// * `room` - Joined room the message targets, defaults to the connected room (optional)
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct KickObject {
+        pub r#type: String,
+        pub object: usize,
+        #[serde(default)]
+        pub ban: bool,
+        #[serde(default)]
+        pub msg_id: Option<Arbitrary>,
+        #[serde(default)]
+        pub room: Option<String>,
+    }
+
+    impl GetMessageType for KickObject {
+        /// Get message type or error
+        ///
+        /// # Example
+        /// ```
+        /// let msg: Result<StringObject, _> = serde_json::from_str(m);
+        ///     match msg {
+        ///         Ok(msg) => match msg.get_type() {
+        ///             Ok(Types::Raised) => ()
+        ///             _ => )_
+        ///         }
+        ///     }
+        /// ```
+        fn get_type(&self) -> Result<Types, InvalidMessageType> {
+            Types::from_str(self.r#type.as_str())
+        }
+    }
+
+    /// Inbound message skeleton: Create a poll
+    ///
+    /// * `type` - Message type, see [Types](#struct.Types)
+    /// * `object` - Title and settings of the poll, see [PollFields](#struct.PollFields)
+    /// * `msg_id` - Client chosen ID to acknowledge the message with (optional)
+    /// * `room` - Joined room the message targets, defaults to the connected room (optional)
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct PollObject {
+        pub r#type: String,
+        pub object: PollFields,
+        #[serde(default)]
+        pub msg_id: Option<Arbitrary>,
+        #[serde(default)]
+        pub room: Option<String>,
+    }
+
+    /// Object of a poll message
+    ///
+    /// * `poll_title` - Title of the poll
+    /// * `draft` - If the poll is created as draft, hidden from voting until opened (optional)
+    /// * `lock_after_first_vote_secs` - Seconds after the first vote votes can't be changed anymore
+    ///   (optional)
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct PollFields {
+        pub poll_title: String,
+        #[serde(default)]
+        pub draft: bool,
+        #[serde(default)]
+        pub lock_after_first_vote_secs: Option<u64>,
+    }
+
+    impl GetMessageType for PollObject {
+        /// Get message type or error
+        fn get_type(&self) -> Result<Types, InvalidMessageType> {
+            Types::from_str(self.r#type.as_str())
+        }
+    }
+
     /// Inbound message skeleton: Vec objects
     ///
     /// * `type` - Message type, see [Types](#struct.Types)
     /// * `pollobject` - A `String` value
     /// * `polloptionobject` - A `String` value
+    /// * `msg_id` - Client chosen ID to acknowledge the message with (optional)
+    /// * `room` - Joined room the message targets, defaults to the connected room (optional)
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct HashMapObject {
         pub r#type: String,
         pub object: HashMap<String, String>,
+        #[serde(default)]
+        pub msg_id: Option<Arbitrary>,
+        #[serde(default)]
+        pub room: Option<String>,
     }
 
     impl GetMessageType for HashMapObject {
@@ -153,6 +328,246 @@ pub mod inbound {
             Types::from_str(self.r#type.as_str())
         }
     }
+
+    /// Strict inbound message skeleton: Arbitrary object, used to refuse unknown fields
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)] // only deserialized to check the fields
+    struct StrictArbitraryObject {
+        r#type: String,
+        #[serde(default)]
+        object: Arbitrary,
+        #[serde(default)]
+        msg_id: Option<Arbitrary>,
+        #[serde(default)]
+        room: Option<String>,
+        #[serde(default)]
+        delay_secs: Option<u64>,
+    }
+
+    /// Strict inbound message skeleton: Make a user spectator or participant
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)] // only deserialized to check the fields
+    struct StrictSpectatorObject {
+        r#type: String,
+        object: Arbitrary,
+        spectator: Arbitrary,
+        #[serde(default)]
+        msg_id: Option<Arbitrary>,
+        #[serde(default)]
+        room: Option<String>,
+    }
+
+    /// Strict inbound message skeleton: Kick a user
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)] // only deserialized to check the fields
+    struct StrictKickObject {
+        r#type: String,
+        object: Arbitrary,
+        #[serde(default)]
+        ban: Option<Arbitrary>,
+        #[serde(default)]
+        msg_id: Option<Arbitrary>,
+        #[serde(default)]
+        room: Option<String>,
+    }
+
+    /// Strict inbound message skeleton: Poll objects
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)] // only deserialized to check the fields
+    struct StrictPollObject {
+        r#type: String,
+        object: StrictPollFields,
+        #[serde(default)]
+        msg_id: Option<Arbitrary>,
+        #[serde(default)]
+        room: Option<String>,
+    }
+
+    /// Fields of the object of poll messages, all optional as the lenient parsing checks them
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    #[allow(dead_code)] // only deserialized to check the fields
+    struct StrictPollFields {
+        #[serde(default)]
+        poll_title: Option<Arbitrary>,
+        #[serde(default)]
+        poll_option_title: Option<Arbitrary>,
+        #[serde(default)]
+        new_poll_option_title: Option<Arbitrary>,
+        #[serde(default)]
+        draft: Option<Arbitrary>,
+        #[serde(default)]
+        lock_after_first_vote_secs: Option<Arbitrary>,
+    }
+
+    /// Check that an inbound message has no fields its type doesn't know, e.g. misspelled ones
+    ///
+    /// Used for clients with the `strict` capability. Messages that aren't JSON or have an
+    /// unknown type pass, they are refused by the lenient parsing either way.
+    ///
+    /// # Arguments
+    /// * `text` - The message as sent by a client
+    pub fn parse_strict(text: &str) -> Result<(), String> {
+        let message: Arbitrary = match serde_json::from_str(text.trim()) {
+            Ok(message) => message,
+            Err(_) => return Ok(()),
+        };
+        let r#type = match message.get("type").and_then(|r#type| r#type.as_str()) {
+            Some(r#type) => Types::from_str(r#type),
+            None => return Ok(()),
+        };
+
+        let result = match r#type {
+            Err(_) => return Ok(()),
+            Ok(Types::Poll)
+            | Ok(Types::PollOpen)
+            | Ok(Types::PollClose)
+            | Ok(Types::PollStatus)
+            | Ok(Types::PollOption)
+            | Ok(Types::Vote)
+            | Ok(Types::PollOptionRename) => {
+                serde_json::from_value::<StrictPollObject>(message).map(|_| ())
+            }
+            Ok(Types::SetSpectator) => {
+                serde_json::from_value::<StrictSpectatorObject>(message).map(|_| ())
+            }
+            Ok(Types::Kick) => serde_json::from_value::<StrictKickObject>(message).map(|_| ()),
+            Ok(_) => serde_json::from_value::<StrictArbitraryObject>(message).map(|_| ()),
+        };
+
+        result.map_err(|e| e.to_string())
+    }
+
+    /// Result of validating an inbound message without processing it
+    ///
+    /// * `valid` - If the websocket handler would accept the message's shape
+    /// * `type` - The message's type, if it has a string `type`
+    /// * `errors` - Error codes with details why the message is invalid
+    #[derive(Serialize, Debug)]
+    pub struct Validation {
+        pub valid: bool,
+        pub r#type: Option<String>,
+        pub errors: Vec<String>,
+    }
+
+    /// Validate the shape of an inbound message like the websocket handler parses it
+    ///
+    /// Only the message itself is checked, not whether it would succeed in a room.
+    ///
+    /// # Arguments
+    /// * `text` - The message as sent by a client
+    pub fn validate(text: &str) -> Validation {
+        let message: Arbitrary = match serde_json::from_str(text.trim()) {
+            Ok(message) => message,
+            Err(e) => {
+                return Validation {
+                    valid: false,
+                    r#type: None,
+                    errors: vec![format!(
+                        "parse_error: Invalid JSON at line {} column {}",
+                        e.line(),
+                        e.column()
+                    )],
+                }
+            }
+        };
+
+        let type_name = match message.get("type").and_then(|r#type| r#type.as_str()) {
+            Some(type_name) => type_name.to_string(),
+            None => {
+                return Validation {
+                    valid: false,
+                    r#type: None,
+                    errors: vec!["missing_type".to_string()],
+                }
+            }
+        };
+
+        let errors = match Types::from_str(&type_name) {
+            Err(_) => vec!["unknown_type".to_string()],
+            Ok(Types::Poll) => validate_shape::<PollObject>(&message),
+            Ok(Types::PollOpen) | Ok(Types::PollClose) | Ok(Types::PollStatus) => {
+                validate_fields(&message, &["poll_title"])
+            }
+            Ok(Types::PollOption) | Ok(Types::Vote) => {
+                validate_fields(&message, &["poll_title", "poll_option_title"])
+            }
+            Ok(Types::PollOptionRename) => validate_fields(
+                &message,
+                &["poll_title", "poll_option_title", "new_poll_option_title"],
+            ),
+            Ok(Types::SetSpectator) => validate_shape::<SpectatorObject>(&message),
+            Ok(Types::Kick) => validate_shape::<KickObject>(&message),
+            Ok(Types::TransferElevation)
+            | Ok(Types::ConfirmElevate)
+            | Ok(Types::Mute)
+            | Ok(Types::Unmute) => validate_shape::<UsizeObject>(&message),
+            Ok(Types::Elevate) | Ok(Types::Recede) => validate_object(&message, |object| {
+                object.is_u64()
+                    || object
+                        .as_str()
+                        .map_or(false, |object| object.trim().parse::<usize>().is_ok())
+            }),
+            Ok(Types::Raise) | Ok(Types::Lower) | Ok(Types::Instant) => {
+                validate_object(&message, |object| !object.is_null())
+            }
+            Ok(Types::RaiseMany) => validate_object(&message, Arbitrary::is_array),
+            Ok(Types::ElevateByName)
+            | Ok(Types::RecedeByName)
+            | Ok(Types::JoinRoom)
+            | Ok(Types::LeaveRoom)
+            | Ok(Types::Unban)
+            | Ok(Types::Rename) => validate_object(&message, Arbitrary::is_string),
+            Ok(Types::EndRoom)
+            | Ok(Types::ListPolls)
+            | Ok(Types::CloseAllPolls)
+            | Ok(Types::ClearRaised)
+            | Ok(Types::Ping)
+            | Ok(Types::MyRaised)
+            | Ok(Types::RaisedCount)
+            | Ok(Types::Roster) => validate_shape::<ArbitraryObject>(&message),
+        };
+
+        Validation {
+            valid: errors.is_empty(),
+            r#type: Some(type_name),
+            errors,
+        }
+    }
+
+    /// Check that a message parses into a skeleton
+    fn validate_shape<T: DeserializeOwned>(message: &Arbitrary) -> Vec<String> {
+        match serde_json::from_value::<T>(message.clone()) {
+            Ok(_) => Vec::new(),
+            Err(e) => vec![format!("bad_shape: {}", e)],
+        }
+    }
+
+    /// Check that a message's object holds the given string fields
+    fn validate_fields(message: &Arbitrary, fields: &[&str]) -> Vec<String> {
+        match serde_json::from_value::<HashMapObject>(message.clone()) {
+            Ok(message) => fields
+                .iter()
+                .filter(|field| !message.object.contains_key(**field))
+                .map(|field| format!("missing_field: {}", field))
+                .collect(),
+            Err(e) => vec![format!("bad_shape: {}", e)],
+        }
+    }
+
+    /// Check a message's object with a predicate
+    fn validate_object(message: &Arbitrary, is_valid: fn(&Arbitrary) -> bool) -> Vec<String> {
+        match serde_json::from_value::<ArbitraryObject>(message.clone()) {
+            Ok(message) if message.object.is_null() => vec!["missing_object".to_string()],
+            Ok(message) if !is_valid(&message.object) => vec!["bad_object".to_string()],
+            Ok(_) => Vec::new(),
+            Err(e) => vec![format!("bad_shape: {}", e)],
+        }
+    }
 }
 
 pub mod outbound {
@@ -162,6 +577,129 @@ pub mod outbound {
     use std::{error, fmt};
 
     use crate::server;
+
+    /// Version of the outbound protocol, sent with the `hello` message
+    ///
+    /// * `1` - Initial protocol
+    /// * `2` - Sessions can join multiple rooms, every room message carries a `room` field
+    pub const PROTOCOL_VERSION: usize = 2;
+
+    /// WebSocket subprotocols the server speaks, in order of preference, named after the
+    /// [PROTOCOL_VERSION](constant.PROTOCOL_VERSION.html)
+    pub const SUBPROTOCOLS: &[&str] = &["vimeet.v2"];
+
+    /// Capability to receive `votechanged` messages as elevated user
+    pub const CAPABILITY_VOTE_CHANGED: &str = "votechanged";
+
+    /// Capability to receive the state replayed on joining a room as a single `batch` message
+    pub const CAPABILITY_BATCH: &str = "batch";
+
+    /// Capability to receive camelCase field names, e.g. `ownerId` instead of `owner_id`
+    pub const CAPABILITY_CAMEL_CASE: &str = "camelcase";
+
+    /// Capability to receive large messages gzip compressed in binary frames
+    ///
+    /// Text frames are never compressed, every binary frame holds a gzip compressed message.
+    pub const CAPABILITY_GZIP: &str = "gzip";
+
+    /// Capability to receive only `user` and `userleft` deltas on membership changes
+    ///
+    /// Clients without it get a full `all` snapshot of the room whenever a user leaves. The `all`
+    /// snapshot sent on joining a room is sent either way.
+    pub const CAPABILITY_DELTA: &str = "delta";
+
+    /// Capability to have messages with unknown fields refused with `strict_parse_error`
+    ///
+    /// Without it unknown fields are ignored, e.g. a misspelled optional field.
+    pub const CAPABILITY_STRICT: &str = "strict";
+
+    /// All capabilities a client can request with the `caps` query parameter
+    pub const CAPABILITIES: &[&str] = &[
+        CAPABILITY_VOTE_CHANGED,
+        CAPABILITY_BATCH,
+        CAPABILITY_CAMEL_CASE,
+        CAPABILITY_GZIP,
+        CAPABILITY_DELTA,
+        CAPABILITY_STRICT,
+    ];
+
+    /// Field names sent to clients with the `camelcase` capability instead of the default ones
+    const CAMEL_CASE_FIELDS: &[(&str, &str)] = &[
+        ("owner_id", "ownerId"),
+        ("owner_name", "ownerName"),
+        ("pollobject", "pollObject"),
+        ("polloptionobject", "pollOptionObject"),
+        ("oldpolloptionobject", "oldPollOptionObject"),
+        ("newpolloptionobject", "newPollOptionObject"),
+        ("username", "userName"),
+        ("userid", "userId"),
+    ];
+
+    /// Serialize an outbound message to the text sent to clients
+    ///
+    /// A message failing to serialize must not take the server down, it is logged and replaced by
+    /// an `error` message with the code `serialization_failed`.
+    ///
+    /// # Arguments
+    /// * `message` - The outbound message skeleton
+    pub fn to_frame<T: Serialize>(message: &T) -> String {
+        match serde_json::to_string(message) {
+            Ok(frame) => frame,
+            Err(e) => {
+                println!(
+                    "Cannot serialize outbound message, sending an error instead: {}",
+                    e
+                );
+                r#"{"type":"error","object":"serialization_failed","description":"The message could not be serialized"}"#
+                    .to_string()
+            }
+        }
+    }
+
+    /// Rename the fields of an outbound message for clients with the `camelcase` capability
+    ///
+    /// # Arguments
+    /// * `message` - The JSON message as sent to other clients
+    pub fn to_camel_case(message: &str) -> String {
+        match serde_json::from_str::<Arbitrary>(message) {
+            Ok(message) => rename_fields(message).to_string(),
+            Err(_) => message.to_owned(),
+        }
+    }
+
+    /// Rename the fields of a message, including raised objects and batched messages
+    ///
+    /// Raised and instant objects are client data and keep their fields.
+    fn rename_fields(message: Arbitrary) -> Arbitrary {
+        let message = match message {
+            Arbitrary::Object(message) => message,
+            other => return other,
+        };
+        let is_batch = message.get("type").and_then(|r#type| r#type.as_str()) == Some("batch");
+
+        Arbitrary::Object(
+            message
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        Arbitrary::Array(items)
+                            if key == "raised" || (key == "object" && is_batch) =>
+                        {
+                            Arbitrary::Array(items.into_iter().map(rename_fields).collect())
+                        }
+                        value => value,
+                    };
+                    let key = CAMEL_CASE_FIELDS
+                        .iter()
+                        .find(|(from, _)| *from == key.as_str())
+                        .map(|(_, to)| to.to_string())
+                        .unwrap_or(key);
+                    (key, value)
+                })
+                .collect(),
+        )
+    }
+
     /// Error if message has unknown message type
     ///
     /// For all known types, see
@@ -202,6 +740,14 @@ pub mod outbound {
         //         "id": user_id,
         //         "elevated": elevated
         //     }
+        UserLeft,
+        // {
+        //     "type": "userleft",
+        //     "object" : {
+        //         "name": user_name,
+        //         "id": user_id,
+        //         "elevated": elevated
+        //     }
         All,
         // {
         //     "type": "all",
@@ -242,6 +788,12 @@ pub mod outbound {
         //     "type": "elevated",
         //     "object": msg.object,
         // }
+        ElevationPending,
+        // {
+        //     "type": "elevationpending",
+        //     "object": user.id, // user to elevate once another elevated user confirms
+        //     "requester": requester.id,
+        // }
         Receded,
         // {
         //     "type": "receded",
@@ -263,6 +815,7 @@ pub mod outbound {
         // {
         //     "type": "poll",
         //     "object": "amazing poll title",
+        //     "state": "open", // or "draft"
         // }
         PollOption,
         // {
@@ -280,8 +833,139 @@ pub mod outbound {
         // }
         PollClose,
         // {
-        //      "type": "closepoll",
-        //      "pollobject": poll.title,
+        //      "type": "pollclose",
+        //      "object": poll.title,
+        // }
+        PollOpen,
+        // {
+        //      "type": "pollopen",
+        //      "object": poll.title,
+        // }
+        VoteReceipt,
+        // {
+        //      "type": "votereceipt",
+        //      "pollobject": poll_title,
+        //      "polloptionobject": poll_option_title, // or null if the user has no vote
+        // }
+        PollOptionRenamed,
+        // {
+        //      "type": "polloptionrenamed",
+        //      "pollobject": poll_title,
+        //      "oldpolloptionobject": old_poll_option_title,
+        //      "newpolloptionobject": new_poll_option_title,
+        // }
+        VoteChanged,
+        // {
+        //      "type": "votechanged",
+        //      "pollobject": poll_title,
+        //      "oldpolloptionobject": old_poll_option_title,
+        //      "newpolloptionobject": new_poll_option_title,
+        //      "username": vote.owner_name,
+        //      "userid": vote.owner_id,
+        // }
+        Spectator,
+        // {
+        //     "type": "spectator",
+        //     "object": msg.object,
+        // }
+        Participant,
+        // {
+        //     "type": "participant",
+        //     "object": msg.object,
+        // }
+        Muted,
+        // {
+        //     "type": "muted",
+        //     "object": msg.object,
+        // }
+        Unmuted,
+        // {
+        //     "type": "unmuted",
+        //     "object": msg.object,
+        // }
+        RoomClosing,
+        // {
+        //     "type": "roomclosing",
+        //     "object": room_name,
+        // }
+        RoomEnded,
+        // {
+        //     "type": "roomended",
+        //     "object": room_name,
+        // }
+        Hello,
+        // {
+        //     "type": "hello",
+        //     "version": PROTOCOL_VERSION,
+        //     "capabilities": ["votechanged"],
+        // }
+        Ack,
+        // {
+        //     "type": "ack",
+        //     "object": msg_id,
+        //     "success": true,
+        //     "error": null, // or the error code on failure
+        // }
+        Batch,
+        // {
+        //     "type": "batch",
+        //     "object": [message, ...], // applied in order
+        // }
+        UserRenamed,
+        // {
+        //     "type": "userrenamed",
+        //     "object": user_id,
+        //     "oldname": old_user_name,
+        //     "newname": new_user_name,
+        // }
+        MaintenanceNotice,
+        // {
+        //     "type": "maintenancenotice",
+        //     "object": enabled, // true if new connections are refused, existing ones continue
+        // }
+        MyRaised,
+        // {
+        //     "type": "myraised",
+        //     "object": [raised, ...], // objects raised by the receiver, same format as in `all`
+        // }
+        Roster,
+        // {
+        //     "type": "roster",
+        //     "object": {user.id: user, ...}, // every user, same format as `joined` in `all`
+        // }
+        RaisedCount,
+        // {
+        //     "type": "raisedcount",
+        //     "object": 3, // number of raised objects in the room
+        //     "objects": [{"object": raised.object, "count": 2}, ...],
+        // }
+        PollStatus,
+        // {
+        //     "type": "pollstatus",
+        //     "object": {...}, // same as in `pollresults` with the poll's "state"
+        // }
+        Pong,
+        // {
+        //     "type": "pong",
+        //     "object": ping.object, // echoed, null if the ping had none
+        // }
+        RaisedCleared,
+        // {
+        //     "type": "raisedcleared",
+        //     "object": user_id, // the elevated user who cleared all raised objects
+        // }
+        PollResults,
+        // {
+        //     "type": "pollresults",
+        //     "object": {
+        //         "pollobject": poll.title,
+        //         "options": [{"polloptionobject": option.title, "votes": 2}, ...],
+        //         "ranking": [option.title, ...], // by votes, ties in the order options were added
+        //         "winners": [option.title, ...], // all options with the most votes
+        //         "votes": 2,
+        //         "quorum": {"voters": 2, "eligible": 5}, // participants, spectators don't count
+        //         "duration_secs": 120,
+        //     },
         // }
     }
 
@@ -289,10 +973,21 @@ pub mod outbound {
     #[derive(Serialize)]
     pub struct All {
         pub r#type: Types,
-        pub raised: Vec<server::Raised>,
+        pub raised: Vec<RaisedFormat>,
         pub joined: HashMap<usize, server::User>,
     }
 
+    /// Representation of a raised object within the state of a room
+    ///
+    /// Carries the same owner information as a live `raised` message
+    #[derive(Serialize, Clone)]
+    pub struct RaisedFormat {
+        pub object: Arbitrary,
+        pub owner_id: usize,
+        pub owner_name: String,
+        pub elevated: bool,
+    }
+
     #[derive(Serialize)]
     pub struct UserFormat {
         pub id: usize,
@@ -301,6 +996,8 @@ pub mod outbound {
     }
 
     /// Message skeleton containing the current state of a user
+    ///
+    /// Used with type `User` when the user joined and `UserLeft` when the user left
     #[derive(Serialize)]
     pub struct User {
         pub r#type: Types,
@@ -322,9 +1019,21 @@ pub mod outbound {
         pub elevated: bool,
     }
 
+    /// Message skeleton announcing an elevation waiting for confirmation to elevated users
+    /// # Parameters
+    /// * `type` - Message type. Exprected: ElevationPending
+    /// * `object` - ID of the user to elevate
+    /// * `requester` - ID of the elevated user who requested the elevation
+    #[derive(Serialize)]
+    pub struct ElevationPending {
+        pub r#type: Types,
+        pub object: usize,
+        pub requester: usize,
+    }
+
     /// Message skeleton to change a user's permissions
     /// # Parameters
-    /// * `type` - Message type. Exprected: Elevated, Receded
+    /// * `type` - Message type. Exprected: Elevated, Receded, Muted, Unmuted
     /// * `object` - Target user's ID
     #[derive(Serialize)]
     pub struct PermissionChange {
@@ -344,6 +1053,20 @@ pub mod outbound {
         pub description: String,
     }
 
+    /// Message skeleton to refuse lowering an object that is not raised
+    /// # Parameters
+    /// * `type` - Message type. Exprected: Error
+    /// * `object` - Error Code, `not_raised`
+    /// * `description` - Error Description
+    /// * `raised` - Objects currently raised by the user
+    #[derive(Serialize)]
+    pub struct NotRaised {
+        pub r#type: Types,
+        pub object: String,
+        pub description: String,
+        pub raised: Vec<Arbitrary>,
+    }
+
     /// Message skeleton to delete a user's vote
     /// # Parameters
     /// * `type` - Message type. Exprected: VoteDelete
@@ -362,10 +1085,12 @@ pub mod outbound {
     /// # Parameters
     /// * `type` - Message type. Exprected: Poll
     /// * `object` - Title of the poll
+    /// * `state` - State of the poll
     #[derive(Serialize)]
     pub struct Poll {
         pub r#type: Types,
         pub object: String,
+        pub state: server::PollState,
     }
 
     // Message skeleton to send a poll-option
@@ -373,11 +1098,13 @@ pub mod outbound {
     /// * `type` - Message type. Exprected: PollOption
     /// * `pollobject` - Title of the poll
     /// * `polloptionobject` - Title of the poll-option
+    /// * `order` - Position of the poll-option within the poll, in order of creation
     #[derive(Serialize)]
     pub struct PollOption {
         pub r#type: Types,
         pub pollobject: String,
         pub polloptionobject: String,
+        pub order: usize,
     }
 
     // Message skeleton to send a vote
@@ -396,13 +1123,432 @@ pub mod outbound {
         pub userid: usize,
     }
 
-    // Message skeleton to close a poll
+    // Message skeleton to close or open a poll
     /// # Parameters
-    /// * `type` - Message type. Exprected: PollClose
+    /// * `type` - Message type. Exprected: PollClose or PollOpen
     /// * `object` - Title of the poll
     #[derive(Serialize)]
     pub struct PollClose {
         pub r#type: Types,
         pub object: String,
     }
+
+    // Message skeleton to confirm a user's vote to the user only
+    /// # Parameters
+    /// * `type` - Message type. Exprected: VoteReceipt
+    /// * `pollobject` - Title of the poll
+    /// * `polloptionobject` - Title of the poll-option the user currently votes for, if any
+    #[derive(Serialize)]
+    pub struct VoteReceipt {
+        pub r#type: Types,
+        pub pollobject: String,
+        pub polloptionobject: Option<String>,
+    }
+
+    // Message skeleton to announce a renamed poll-option
+    /// # Parameters
+    /// * `type` - Message type. Exprected: PollOptionRenamed
+    /// * `pollobject` - Title of the poll
+    /// * `oldpolloptionobject` - Previous title of the poll-option
+    /// * `newpolloptionobject` - New title of the poll-option
+    #[derive(Serialize)]
+    pub struct PollOptionRenamed {
+        pub r#type: Types,
+        pub pollobject: String,
+        pub oldpolloptionobject: String,
+        pub newpolloptionobject: String,
+    }
+
+    // Message skeleton to inform elevated users about a changed vote
+    /// # Parameters
+    /// * `type` - Message type. Exprected: VoteChanged
+    /// * `pollobject` - Title of the poll
+    /// * `oldpolloptionobject` - Title of the previously voted poll-option
+    /// * `newpolloptionobject` - Title of the newly voted poll-option
+    /// * `username` - Name of the voting-user
+    /// * `userid` - ID of the voting-user
+    #[derive(Serialize)]
+    pub struct VoteChanged {
+        pub r#type: Types,
+        pub pollobject: String,
+        pub oldpolloptionobject: String,
+        pub newpolloptionobject: String,
+        pub username: String,
+        pub userid: usize,
+    }
+
+    // Message skeleton to announce a room is about to be closed
+    /// # Parameters
+    /// * `type` - Message type. Exprected: RoomClosing
+    /// * `object` - Name of the room
+    #[derive(Serialize)]
+    pub struct RoomClosing {
+        pub r#type: Types,
+        pub object: String,
+    }
+
+    // Message skeleton to announce a room has been ended by a moderator
+    /// # Parameters
+    /// * `type` - Message type. Exprected: RoomEnded
+    /// * `object` - Name of the room
+    #[derive(Serialize)]
+    pub struct RoomEnded {
+        pub r#type: Types,
+        pub object: String,
+    }
+
+    // Message skeleton to acknowledge an inbound message
+    /// # Parameters
+    /// * `type` - Message type. Exprected: Ack
+    /// * `object` - `msg_id` of the acknowledged message
+    /// * `success` - If the message has been processed successfully
+    /// * `error` - Error code if the message failed
+    #[derive(Serialize)]
+    pub struct Ack {
+        pub r#type: Types,
+        pub object: Arbitrary,
+        pub success: bool,
+        pub error: Option<String>,
+    }
+
+    // Message skeleton to acknowledge raising several objects at once
+    /// # Parameters
+    /// * `type` - Message type. Exprected: Ack
+    /// * `object` - `msg_id` of the acknowledged message
+    /// * `success` - If every object has been raised
+    /// * `error` - Error code of the first object that failed
+    /// * `failed` - Every object that failed, in order
+    #[derive(Serialize)]
+    pub struct RaiseManyAck {
+        pub r#type: Types,
+        pub object: Arbitrary,
+        pub success: bool,
+        pub error: Option<String>,
+        pub failed: Vec<FailedRaise>,
+    }
+
+    // An object of a `raisemany` message that could not be raised
+    /// # Parameters
+    /// * `index` - Position of the object in the message
+    /// * `object` - The object
+    /// * `error` - Error code why it was not raised
+    #[derive(Serialize)]
+    pub struct FailedRaise {
+        pub index: usize,
+        pub object: Arbitrary,
+        pub error: String,
+    }
+
+    // Message skeleton greeting a newly connected session
+    /// # Parameters
+    /// * `type` - Message type. Exprected: Hello
+    /// * `version` - Version of the protocol spoken by the server
+    /// * `capabilities` - Capabilities requested by the client and supported by the server
+    #[derive(Serialize)]
+    pub struct Hello {
+        pub r#type: Types,
+        pub version: usize,
+        pub capabilities: Vec<String>,
+    }
+
+    // Message skeleton carrying several messages at once
+    /// # Parameters
+    /// * `type` - Message type. Exprected: Batch
+    /// * `object` - Messages to apply in order
+    #[derive(Serialize)]
+    pub struct Batch {
+        pub r#type: Types,
+        pub object: Vec<Arbitrary>,
+    }
+
+    // Message skeleton listing all users of a room to an elevated user
+    /// # Parameters
+    /// * `type` - Message type. Exprected: Roster
+    /// * `object` - Every user of the room by id, with name, elevation and roles
+    #[derive(Serialize)]
+    pub struct Roster {
+        pub r#type: Types,
+        pub object: HashMap<usize, server::User>,
+    }
+
+    // Message skeleton summarizing the objects raised in a room
+    /// # Parameters
+    /// * `type` - Message type. Exprected: RaisedCount
+    /// * `object` - Number of raised objects
+    /// * `objects` - How often each object is raised, in the order the objects were first raised
+    #[derive(Serialize)]
+    pub struct RaisedCount {
+        pub r#type: Types,
+        pub object: usize,
+        pub objects: Vec<ObjectCount>,
+    }
+
+    /// How often an object is raised in a room
+    #[derive(Serialize)]
+    pub struct ObjectCount {
+        pub object: Arbitrary,
+        pub count: usize,
+    }
+
+    // Message skeleton announcing maintenance mode
+    /// # Parameters
+    /// * `type` - Message type. Exprected: MaintenanceNotice
+    /// * `object` - If maintenance mode has been enabled or disabled
+    #[derive(Serialize)]
+    pub struct MaintenanceNotice {
+        pub r#type: Types,
+        pub object: bool,
+    }
+
+    // Message skeleton listing the objects raised by the receiver
+    /// # Parameters
+    /// * `type` - Message type. Exprected: MyRaised
+    /// * `object` - The raised objects in the order they have been raised
+    #[derive(Serialize)]
+    pub struct MyRaised {
+        pub r#type: Types,
+        pub object: Vec<RaisedFormat>,
+    }
+
+    // Message skeleton answering an application level `ping`
+    /// # Parameters
+    /// * `type` - Message type. Exprected: Pong
+    /// * `object` - The `object` of the ping, echoed
+    #[derive(Serialize)]
+    pub struct Pong {
+        pub r#type: Types,
+        pub object: Arbitrary,
+    }
+
+    // Message skeleton announcing that all raised objects of a room have been lowered
+    ///
+    /// Sent once instead of a `lower` per object, clients drop all raised objects of the room.
+    /// # Parameters
+    /// * `type` - Message type. Exprected: RaisedCleared
+    /// * `object` - ID of the elevated user who cleared the raised objects
+    #[derive(Serialize)]
+    pub struct RaisedCleared {
+        pub r#type: Types,
+        pub object: usize,
+    }
+
+    // Message skeleton publishing the final results of a closed poll
+    /// # Parameters
+    /// * `type` - Message type. Exprected: PollResults
+    /// * `object` - Votes per option, total votes and duration of the poll
+    #[derive(Serialize)]
+    pub struct PollResults {
+        pub r#type: Types,
+        pub object: Arbitrary,
+    }
+
+    // Message skeleton containing the current tally of a poll that is still running
+    /// # Parameters
+    /// * `type` - Message type. Exprected: PollStatus
+    /// * `object` - Votes per option, total votes, duration and state of the poll
+    #[derive(Serialize)]
+    pub struct PollStatus {
+        pub r#type: Types,
+        pub object: Arbitrary,
+    }
+
+    // Message skeleton announcing a user's new name
+    /// # Parameters
+    /// * `type` - Message type. Exprected: UserRenamed
+    /// * `object` - ID of the renamed user
+    /// * `oldname` - Name of the user before
+    /// * `newname` - Name of the user now
+    #[derive(Serialize)]
+    pub struct UserRenamed {
+        pub r#type: Types,
+        pub object: usize,
+        pub oldname: String,
+        pub newname: String,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::inbound::{parse_strict, validate, Types};
+    use super::outbound::to_frame;
+    use std::str::FromStr;
+
+    #[test]
+    fn valid_messages_report_their_type() {
+        let validation = validate(
+            r#"{"type": "vote", "object": {"poll_title": "lunch", "poll_option_title": "pizza"}}"#,
+        );
+        assert!(validation.valid);
+        assert_eq!(validation.r#type.as_deref(), Some("vote"));
+        assert!(validation.errors.is_empty());
+
+        assert!(validate(r#"{"type": "elevate", "object": "3"}"#).valid);
+        assert!(validate(r#"{"type": "raise", "object": {"topic": 1}, "msg_id": 7}"#).valid);
+    }
+
+    #[test]
+    fn invalid_messages_report_diagnostics() {
+        let validation = validate(r#"{"type": "vote", "object": {"poll_title": "lunch"}}"#);
+        assert!(!validation.valid);
+        assert_eq!(validation.errors, vec!["missing_field: poll_option_title"]);
+
+        let validation = validate(r#"{"type": "raise"}"#);
+        assert_eq!(validation.errors, vec!["missing_object"]);
+
+        let validation = validate(r#"{"type": "dance", "object": 1}"#);
+        assert_eq!(validation.r#type.as_deref(), Some("dance"));
+        assert_eq!(validation.errors, vec!["unknown_type"]);
+
+        let validation = validate(r#"{"type": "kick", "object": "bob"}"#);
+        assert!(validation.errors[0].starts_with("bad_shape"));
+
+        let validation = validate(r#"{"type": "raise", "#);
+        assert_eq!(validation.r#type, None);
+        assert!(validation.errors[0].starts_with("parse_error"));
+    }
+
+    #[test]
+    fn unserializable_messages_become_error_frames() {
+        struct Broken;
+
+        impl serde::Serialize for Broken {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("broken"))
+            }
+        }
+
+        let frame: serde_json::Value = serde_json::from_str(&to_frame(&Broken)).unwrap();
+        assert_eq!(frame["type"], "error");
+        assert_eq!(frame["object"], "serialization_failed");
+    }
+
+    #[test]
+    fn messages_serialize_to_frames() {
+        let frame = to_frame(&super::outbound::PermissionChange {
+            r#type: super::outbound::Types::Elevated,
+            object: 3,
+        });
+        assert_eq!(frame, r#"{"type":"elevated","object":3}"#);
+    }
+
+    #[test]
+    fn strict_parsing_refuses_unknown_fields() {
+        let typo = r#"{"type": "raise", "object": "hand", "msg_idd": 1}"#;
+        assert!(parse_strict(typo).unwrap_err().contains("msg_idd"));
+        // the lenient parsing ignores it
+        assert!(validate(typo).valid);
+
+        let typo = r#"{"type": "poll", "object": {"poll_title": "lunch", "darft": "true"}}"#;
+        assert!(parse_strict(typo).unwrap_err().contains("darft"));
+        assert!(validate(typo).valid);
+
+        for message in &[
+            r#"{"type": "raise", "object": "hand", "msg_id": 1, "room": "room"}"#,
+            r#"{"type": "instant", "object": "clap", "delay_secs": 2}"#,
+            r#"{"type": "vote", "object": {"poll_title": "lunch", "poll_option_title": "pizza"}}"#,
+            r#"{"type": "kick", "object": 2, "ban": true}"#,
+            r#"{"type": "setspectator", "object": 2, "spectator": true}"#,
+            r#"{"type": "unknown", "whatever": 1}"#,
+            "not json",
+        ] {
+            assert_eq!(parse_strict(message), Ok(()), "{}", message);
+        }
+    }
+
+    #[test]
+    fn poll_close_and_open_accept_both_names() {
+        for name in &["pollclose", "closepoll"] {
+            assert!(matches!(Types::from_str(name), Ok(Types::PollClose)));
+            let message = format!(
+                r#"{{"type": "{}", "object": {{"poll_title": "lunch"}}}}"#,
+                name
+            );
+            assert!(validate(&message).valid);
+        }
+        for name in &["pollopen", "openpoll"] {
+            assert!(matches!(Types::from_str(name), Ok(Types::PollOpen)));
+        }
+    }
+
+    #[test]
+    fn poll_draft_is_a_bool() {
+        let msg: PollObject = serde_json::from_str(
+            r#"{"type": "poll", "object": {"poll_title": "lunch", "draft": true}}"#,
+        )
+        .unwrap();
+        assert!(msg.object.draft);
+
+        let msg: PollObject =
+            serde_json::from_str(r#"{"type": "poll", "object": {"poll_title": "lunch"}}"#).unwrap();
+        assert!(!msg.object.draft);
+
+        let validation =
+            validate(r#"{"type": "poll", "object": {"poll_title": "lunch", "draft": "true"}}"#);
+        assert!(!validation.valid);
+        assert!(validation.errors[0].starts_with("bad_shape"));
+    }
+
+    #[test]
+    fn poll_lock_window_is_a_number() {
+        let msg: PollObject = serde_json::from_str(
+            r#"{"type": "poll", "object": {"poll_title": "lunch", "lock_after_first_vote_secs": 30}}"#,
+        )
+        .unwrap();
+        assert_eq!(msg.object.lock_after_first_vote_secs, Some(30));
+
+        let validation = validate(
+            r#"{"type": "poll", "object": {"poll_title": "lunch", "lock_after_first_vote_secs": "30"}}"#,
+        );
+        assert!(!validation.valid);
+        assert!(validation.errors[0].starts_with("bad_shape"));
+    }
+
+    #[test]
+    fn camel_case_frames_carry_the_same_data_under_other_names() {
+        use super::outbound::{to_camel_case, Types, Vote};
+        use serde_json::{json, Value};
+
+        let frame = to_frame(&Vote {
+            r#type: Types::Vote,
+            pollobject: "lunch".to_string(),
+            polloptionobject: "pizza".to_string(),
+            username: "alice".to_string(),
+            userid: 1,
+        });
+        let default: Value = serde_json::from_str(&frame).unwrap();
+        let camel_case: Value = serde_json::from_str(&to_camel_case(&frame)).unwrap();
+        assert_eq!(
+            default,
+            json!({"type": "vote", "pollobject": "lunch", "polloptionobject": "pizza",
+                   "username": "alice", "userid": 1})
+        );
+        assert_eq!(
+            camel_case,
+            json!({"type": "vote", "pollObject": "lunch", "pollOptionObject": "pizza",
+                   "userName": "alice", "userId": 1})
+        );
+    }
+
+    #[test]
+    fn camel_case_keeps_the_fields_of_client_objects() {
+        use super::outbound::to_camel_case;
+        use serde_json::{json, Value};
+
+        let frame = json!({
+            "type": "all",
+            "raised": [{"owner_id": 1, "owner_name": "alice", "object": {"owner_id": 7}}],
+        })
+        .to_string();
+        let camel_case: Value = serde_json::from_str(&to_camel_case(&frame)).unwrap();
+        assert_eq!(
+            camel_case["raised"][0],
+            json!({"ownerId": 1, "ownerName": "alice", "object": {"owner_id": 7}})
+        );
+
+        let batch = json!({"type": "batch", "object": [{"type": "vote", "userid": 1}]}).to_string();
+        let camel_case: Value = serde_json::from_str(&to_camel_case(&batch)).unwrap();
+        assert_eq!(camel_case["object"][0]["userId"], 1);
+
+        assert_eq!(to_camel_case("not json"), "not json");
+    }
 }
@@ -3,16 +3,213 @@
 //! room through `WebSocketServer`.
 
 use actix::prelude::*;
+use regex::Regex;
 use serde::Serialize;
 use serde_json::{json, Value as Arbitrary};
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
+use crate::events::EventSink;
 use crate::messages;
 
+/// How long a banned user's name is refused by default
+const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(600);
+/// Number of messages queued for the server by default before sessions drop their requests
+const DEFAULT_MAILBOX_CAPACITY: usize = 1024;
+/// Default maximum number of characters of poll and poll-option titles
+const DEFAULT_MAX_TITLE_LENGTH: usize = 100;
+/// ID of the system user owning polls created through the HTTP API, never used by sessions
+const SYSTEM_USER_ID: usize = 0;
+/// Name of the system user owning polls created through the HTTP API
+const SYSTEM_USER_NAME: &str = "system";
+/// User ID sent instead of the voting user's ID to users not allowed to see it
+const ANONYMOUS_USER_ID: usize = 0;
+/// Longest time an instant can be delayed by
+const MAX_INSTANT_DELAY: Duration = Duration::from_secs(3600);
+/// How many droppable messages in a row a session may miss before it's disconnected
+const MAX_DROPPED_MESSAGES: usize = 100;
+/// How often rooms are checked for idleness
+const ROOM_SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+/// How often rooms are checked for users without a session
+const ORPHAN_REAP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Which users are elevated when joining a room
+#[derive(Clone, Copy, Debug)]
+pub enum ElevationMode {
+    /// The first participant in a room is elevated, spectators are skipped
+    First,
+    /// Nobody is elevated automatically
+    None,
+    /// Everybody is elevated
+    All,
+}
+
+impl FromStr for ElevationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(ElevationMode::First),
+            "none" => Ok(ElevationMode::None),
+            "all" => Ok(ElevationMode::All),
+            _ => Err(format!("Invalid elevation mode '{}'", s)),
+        }
+    }
+}
+
+/// Broadcasts of a user's action that may skip the acting user
+///
+/// All other broadcasts reach the acting user as well, e.g. polls, votes and elevations, so its
+/// state of the room stays the same as everybody else's.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Echo {
+    /// `raised` of raise and raisemany
+    Raise,
+    /// `lower` of lower
+    Lower,
+    /// `instant` of instant
+    Instant,
+    /// Peer messages relayed with `ClientMessage`
+    ClientMessage,
+}
+
+impl FromStr for Echo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raise" => Ok(Echo::Raise),
+            "lower" => Ok(Echo::Lower),
+            "instant" => Ok(Echo::Instant),
+            "message" => Ok(Echo::ClientMessage),
+            _ => Err(format!("Invalid echo '{}'", s)),
+        }
+    }
+}
+
+/// Broadcasts echoed to the acting user by default
+pub const DEFAULT_ECHO: &[Echo] = &[Echo::Raise, Echo::Lower, Echo::Instant];
+
+/// Lifecycle state of a poll
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PollState {
+    /// Options can be added, votes are rejected until the poll is opened
+    Draft,
+    /// Options can be added and votes are accepted
+    Open,
+    /// Neither options nor votes are accepted anymore
+    Closed,
+}
+
+/// Short error name sent to clients as the `object` of an `error` message
+pub type ErrorCode = &'static str;
+
+/// Runtime configuration of the `WebSocketServer`
+#[derive(Clone)]
+pub struct Config {
+    /// How long a user kicked with `ban` is refused to rejoin the room
+    pub ban_duration: Duration,
+    /// How long a room may be idle before it is closed, never closed if `None`
+    pub room_idle_timeout: Option<Duration>,
+    /// Maximum number of options per poll, unlimited if `None`
+    pub max_poll_options: Option<usize>,
+    /// Maximum number of open polls per room, unlimited if `None`
+    pub max_open_polls: Option<usize>,
+    /// Which users are elevated when joining a room
+    pub elevation_mode: ElevationMode,
+    /// Maximum number of characters of poll and poll-option titles
+    pub max_title_length: usize,
+    /// If only elevated users may raise objects
+    pub raise_requires_elevation: bool,
+    /// Maximum number of users raising the same object per room, unlimited if `None`
+    pub max_object_raises: Option<usize>,
+    /// Pattern room names have to match entirely, any name is allowed if `None`
+    pub room_name_pattern: Option<Regex>,
+    /// Maximum number of open polls per room a user may vote in at once, unlimited if `None`
+    pub max_votes_per_user: Option<usize>,
+    /// Names of users elevated on joining any room, regardless of the elevation mode
+    pub admin_names: HashSet<String>,
+    /// If only elevated users see the other participants, others see elevated users and themselves
+    pub hide_participants: bool,
+    /// How often the state of every room is sent to its users, never if `None`
+    pub snapshot_interval: Option<Duration>,
+    /// Minimum time between two polls created in a room, unlimited if `None`
+    pub poll_cooldown: Option<Duration>,
+    /// If the polls of a user are closed when the user leaves their room
+    pub close_polls_on_owner_leave: bool,
+    /// If elevating a user has to be confirmed by a second elevated user of the room
+    ///
+    /// Server-wide like the other elevation policies: as a room setting the elevated users it is
+    /// meant to check could switch it off themselves.
+    pub confirm_elevation: bool,
+    /// Number of messages from sessions queued for the server before sessions drop their requests
+    pub mailbox_capacity: usize,
+    /// Broadcasts of a user's action sent to the acting user as well
+    pub echo: HashSet<Echo>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            ban_duration: DEFAULT_BAN_DURATION,
+            room_idle_timeout: None,
+            max_poll_options: None,
+            max_open_polls: None,
+            elevation_mode: ElevationMode::First,
+            max_title_length: DEFAULT_MAX_TITLE_LENGTH,
+            raise_requires_elevation: false,
+            max_object_raises: None,
+            room_name_pattern: None,
+            max_votes_per_user: None,
+            admin_names: HashSet::new(),
+            hide_participants: false,
+            snapshot_interval: None,
+            poll_cooldown: None,
+            close_polls_on_owner_leave: false,
+            confirm_elevation: false,
+            mailbox_capacity: DEFAULT_MAILBOX_CAPACITY,
+            echo: DEFAULT_ECHO.iter().cloned().collect(),
+        }
+    }
+}
+
 /// web socket server sends this messages to session
+///
+/// The text is shared, so a broadcast allocates it once for all recipients. It is already in the
+/// field naming the session negotiated.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Message(pub Arc<str>);
+
+/// Why a session is stopped, sent to the client as close code
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    /// The client stopped answering heartbeats
+    Timeout,
+    /// The client sent frames violating the protocol
+    ProtocolError,
+    /// The web socket server did not accept the session in time
+    Busy,
+    /// The web socket server refused the session to join
+    Refused,
+    /// The user has been kicked from the room
+    Kicked,
+    /// The room has been ended or closed as idle
+    RoomEnded,
+    /// The client can't keep up with the messages
+    SlowConsumer,
+    /// Another session joined with the same id
+    Replaced,
+}
+
+/// web socket server tells a session to stop
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct Message(pub String);
+pub struct Stop(pub StopReason);
 
 /// Session is disconnected
 #[derive(Message)]
@@ -21,12 +218,30 @@ pub struct Disconnect {
     pub id: usize,
 }
 
+/// Session showed activity, e.g. sent a message or answered a ping
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Activity {
+    pub id: usize,
+}
+
 #[derive(Message, Serialize, Clone)]
 #[rtype(result = "()")]
 pub struct Elevate {
     pub object: usize,
     pub owner_id: usize,
     pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+/// Confirm the pending elevation of a user requested by another elevated user
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct ConfirmElevate {
+    pub object: usize,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -35,6 +250,180 @@ pub struct Recede {
     pub object: usize,
     pub owner_id: usize,
     pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct TransferElevation {
+    pub object: usize,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct ElevateByName {
+    pub object: String,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct RecedeByName {
+    pub object: String,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct Mute {
+    pub object: usize,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct Unmute {
+    pub object: usize,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct SetSpectator {
+    pub object: usize,
+    pub spectator: bool,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct ListPolls {
+    pub user_id: usize,
+    pub room_name: String,
+}
+
+/// List the objects a user currently raises in a room, only to the user
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct MyRaised {
+    pub user_id: usize,
+    pub room_name: String,
+}
+
+/// Send all users of a room with their roles only to the elevated requester
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct Roster {
+    pub user_id: usize,
+    pub room_name: String,
+}
+
+/// Count the objects raised in a room, only to the user
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct RaisedCount {
+    pub user_id: usize,
+    pub room_name: String,
+}
+
+/// Notify all connected users that maintenance mode has been toggled
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct MaintenanceNotice {
+    pub enabled: bool,
+}
+
+/// Export all polls of a room with their tallies but without voter identities
+///
+/// Fails with `room_does_not_exist` if the room doesn't exist
+#[derive(Message)]
+#[rtype(result = "Result<Arbitrary, ErrorCode>")]
+pub struct GetRoomExport {
+    pub room_name: String,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct EndRoom {
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+/// Lower every raised object of a room at once
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct ClearRaised {
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+/// Close every poll of a room which is not closed yet
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct CloseAllPolls {
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct Kick {
+    pub object: usize,
+    pub ban: bool,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct Unban {
+    pub object: String,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+/// Join an additional room with an already connected session
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct JoinRoom {
+    pub user_id: usize,
+    pub user_name: String,
+    pub room_name: String,
+    pub spectator: bool,
+}
+
+/// Leave a room without disconnecting the session
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct LeaveRoom {
+    pub user_id: usize,
+    pub room_name: String,
+}
+
+/// Change the name of a user in all joined rooms
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct RenameUser {
+    pub user_id: usize,
+    pub user_name: String,
 }
 
 /// Send message to specific room
@@ -54,12 +443,21 @@ pub struct Room {
     raised: Vec<Raised>,
     polls: Vec<Poll>,
     connected: HashMap<usize, User>,
+    banned: HashMap<String, std::time::Instant>, // HashMap<user_name, banned_until>
+    last_activity: std::time::Instant,
+    /// Admin sessions receiving every broadcast of the room, without being one of its users
+    observers: HashSet<usize>,
+    /// When the last poll has been created in the room, `None` if there was none yet
+    last_poll_created: Option<std::time::Instant>,
+    pending_elevations: HashMap<usize, usize>, // HashMap<user_id, requester_id>
 }
 
 #[derive(Clone, Serialize)]
 pub struct User {
     name: String,
     elevated: bool,
+    muted: bool,
+    spectator: bool,
 }
 
 impl Default for Room {
@@ -68,6 +466,11 @@ impl Default for Room {
             raised: Vec::new(),
             polls: Vec::new(),
             connected: HashMap::new(),
+            banned: HashMap::new(),
+            last_activity: std::time::Instant::now(),
+            observers: HashSet::new(),
+            last_poll_created: None,
+            pending_elevations: HashMap::new(),
         }
     }
 }
@@ -75,6 +478,68 @@ impl Default for Room {
 impl Room {
     fn remove_user(&mut self, user_id: &usize) {
         self.raised.retain(|elem| &elem.owner_id != user_id);
+        self.pending_elevations
+            .retain(|pending_id, requester_id| pending_id != user_id && requester_id != user_id);
+    }
+
+    /// Remove all votes of a user in open polls
+    ///
+    /// Votes in closed polls are kept as part of the final result. Polls and poll-options created
+    /// by the user are kept as well, so the room's polls and other users' votes stay intact.
+    ///
+    /// Returns the removed votes as `(poll_title, poll_option_title)`
+    fn remove_user_votes(&mut self, user_id: &usize) -> Vec<(String, String)> {
+        let mut removed_votes = Vec::new();
+        for poll in self.polls.iter_mut() {
+            if poll.state != PollState::Closed {
+                if let Some(poll_option_title) = poll.votes.remove(user_id) {
+                    removed_votes.push((poll.title.clone(), poll_option_title));
+                }
+            }
+        }
+        removed_votes
+    }
+
+    /// Number of polls not closed yet, drafts included
+    ///
+    /// Each of them is replayed to every joining user.
+    fn open_polls(&self) -> usize {
+        self.polls
+            .iter()
+            .filter(|poll| poll.state != PollState::Closed)
+            .count()
+    }
+
+    /// If the last poll has been created too recently to create another one
+    ///
+    /// # Arguments
+    /// * `poll_cooldown` - Minimum time between two polls, unlimited if `None`
+    fn in_poll_cooldown(&self, poll_cooldown: Option<Duration>) -> bool {
+        match (poll_cooldown, self.last_poll_created) {
+            (Some(poll_cooldown), Some(last_poll_created)) => {
+                last_poll_created.elapsed() < poll_cooldown
+            }
+            _ => false,
+        }
+    }
+
+    /// The users of the room a user may see
+    ///
+    /// With hidden participants, non-elevated users only see the elevated users and themselves.
+    ///
+    /// # Arguments
+    /// * `user_id` - The user receiving the list
+    /// * `hide_participants` - If participants are hidden from non-elevated users
+    fn participants_for(&self, user_id: usize, hide_participants: bool) -> HashMap<usize, User> {
+        if !hide_participants || self.is_elevated(&user_id).unwrap_or(false) {
+            return self.connected.clone();
+        }
+
+        self.connected
+            .iter()
+            .filter(|(id, user)| user.elevated || **id == user_id)
+            .map(|(id, user)| (*id, user.clone()))
+            .collect()
     }
 
     fn is_elevated(&self, user_id: &usize) -> Result<bool, &'static str> {
@@ -92,6 +557,78 @@ impl Room {
             Some(connected) => connected.elevated = elevated,
         }
     }
+
+    fn is_muted(&self, user_id: &usize) -> bool {
+        match self.connected.get(user_id) {
+            None => false,
+            Some(user) => user.muted,
+        }
+    }
+
+    fn is_spectator(&self, user_id: &usize) -> bool {
+        match self.connected.get(user_id) {
+            None => false,
+            Some(user) => user.spectator,
+        }
+    }
+
+    /// Get raised objects enriched with their owner's current elevation
+    fn raised_snapshot(&self) -> Vec<messages::outbound::RaisedFormat> {
+        self.raised
+            .iter()
+            .map(|raised| messages::outbound::RaisedFormat {
+                object: raised.object.clone(),
+                owner_id: raised.owner_id,
+                owner_name: raised.owner_name.clone(),
+                elevated: self.is_elevated(&raised.owner_id).unwrap_or(false),
+            })
+            .collect()
+    }
+
+    /// Check if a name is currently banned, dropping the ban if it is expired
+    fn is_banned(&mut self, user_name: &str) -> bool {
+        match self.banned.get(user_name) {
+            None => false,
+            Some(banned_until) => {
+                if *banned_until > std::time::Instant::now() {
+                    true
+                } else {
+                    self.banned.remove(user_name);
+                    false
+                }
+            }
+        }
+    }
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct PollOptionRename {
+    pub owner_id: usize,
+    pub room_name: String,
+    pub poll_title: String,
+    pub old_title: String,
+    pub new_title: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct PollOpen {
+    pub sender_id: usize,
+    pub room_name: String,
+    pub poll_title: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+/// Send the current tally of a poll only to the elevated requester, the poll stays unchanged
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct PollStatus {
+    pub sender_id: usize,
+    pub room_name: String,
+    pub poll_title: String,
+    pub msg_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -101,6 +638,7 @@ pub struct PollCloseHelper {
     pub sender_name: String,
     pub room_name: String,
     pub poll_title: String,
+    pub msg_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -111,6 +649,7 @@ pub struct PollVoteHelper {
     pub room_name: String,
     pub poll_title: String,
     pub option_title: String,
+    pub msg_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -121,6 +660,11 @@ pub struct PollOption {
     pub owner_name: String,
     pub room_name: String,
     pub poll_title: String,
+    /// Position within the poll, assigned by the server when the option is added
+    pub order: usize,
+    /// ID to acknowledge adding the option with, not kept with the option
+    #[serde(skip)]
+    pub msg_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -132,7 +676,27 @@ pub struct Poll {
     pub room_name: String,
     pub options: Vec<PollOption>,
     pub votes: HashMap<usize, String>, // HashMap<user_id, option_title>
-    pub closed: bool,
+    pub state: PollState,
+    /// Seconds after the first vote until votes can't be changed anymore, never locked if `None`
+    pub lock_after_first_vote_secs: Option<u64>,
+    pub voting_locked: bool,
+    /// When the poll has been created, used to report how long it was running
+    #[serde(skip)]
+    pub created: std::time::Instant,
+    /// ID to acknowledge creating the poll with, not kept with the poll
+    #[serde(skip)]
+    pub msg_id: Option<Arbitrary>,
+}
+
+/// Create a poll with options on behalf of the system user, e.g. through the HTTP API
+///
+/// Fails with an error code if the room doesn't exist or the poll can't be created
+#[derive(Message)]
+#[rtype(result = "Result<(), ErrorCode>")]
+pub struct CreatePoll {
+    pub room_name: String,
+    pub title: String,
+    pub options: Vec<String>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -142,6 +706,17 @@ pub struct Raise {
     pub owner_id: usize,
     pub owner_name: String,
     pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct RaiseMany {
+    pub objects: Vec<Arbitrary>,
+    pub owner_id: usize,
+    pub owner_name: String,
+    pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -165,6 +740,7 @@ pub struct Lower {
     pub owner_id: usize,
     pub owner_name: String,
     pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone, Debug)]
@@ -174,6 +750,8 @@ pub struct Instant {
     pub owner_id: usize,
     pub owner_name: String,
     pub room_name: String,
+    pub msg_id: Option<Arbitrary>,
+    pub delay: Option<Duration>,
 }
 
 /// Join room, if room does not exists create new one.
@@ -181,66 +759,252 @@ pub struct Instant {
 #[rtype(result = "()")]
 pub struct Join {
     pub addr: Recipient<Message>,
+    /// Used to stop the session, e.g. on kick
+    pub stop: Recipient<Stop>,
     /// Client id
     pub user_id: usize,
     pub user_name: String,
     /// Room name
     pub room_name: String,
+    /// Capabilities negotiated with the client
+    pub capabilities: HashSet<String>,
+    /// If the user only observes the room
+    pub spectator: bool,
+    /// If the session is a hidden admin observer, see `Room::observers`
+    pub admin: bool,
 }
 
-/// `WebSocketServer` manages web socket rooms and responsible for coordinating web socket
-/// session. implementation is super primitive
-pub struct WebSocketServer {
-    sessions: HashMap<usize, Recipient<Message>>,
-    rooms: HashMap<String, Room>,
+/// Add the room's name to an outbound message
+///
+/// Sessions can be in multiple rooms, so clients need the room to demultiplex messages.
+///
+/// # Arguments
+///
+/// * `room` - a string slice with the name of the room the message belongs to
+/// * `message` - a string slice that holds the JSON message
+fn with_room(room: &str, message: &str) -> String {
+    // outbound messages are serialized objects without a `room`, so the key is spliced in front
+    // instead of parsing and serializing the message again
+    let room = Arbitrary::String(room.to_string());
+    match message.strip_prefix('{') {
+        Some(fields) if fields.trim_start().starts_with('}') => format!("{{\"room\":{}}}", room),
+        Some(fields) => format!("{{\"room\":{},{}", room, fields),
+        None => message.to_owned(),
+    }
 }
 
-impl Default for WebSocketServer {
-    fn default() -> WebSocketServer {
-        let rooms = HashMap::new(); // mut?!
+/// An outbound message as sent to the sessions of a broadcast
+///
+/// The message is converted for sessions with the `camelcase` capability at most once, however
+/// many of them receive it.
+struct Frame {
+    text: Arc<str>,
+    camel_case: RefCell<Option<Arc<str>>>,
+}
 
-        WebSocketServer {
-            sessions: HashMap::new(),
-            rooms,
+impl Frame {
+    fn new(text: String) -> Frame {
+        Frame {
+            text: Arc::from(text),
+            camel_case: RefCell::new(None),
         }
     }
-}
 
-impl WebSocketServer {
-    /// send a message to some users in a room
-    ///
-    /// expect of the user given in the argument `skip_id`
+    /// The message in the field naming a session negotiated
+    ///
+    /// # Arguments
+    ///
+    /// * `camel_case` - if the session negotiated the `camelcase` capability
+    fn text(&self, camel_case: bool) -> Arc<str> {
+        if !camel_case {
+            return self.text.clone();
+        }
+        self.camel_case
+            .borrow_mut()
+            .get_or_insert_with(|| Arc::from(messages::outbound::to_camel_case(&self.text)))
+            .clone()
+    }
+}
+
+/// Keys outbound messages use for themselves, objects sent by clients must not contain them
+const RESERVED_KEYS: [&str; 2] = ["type", "room"];
+
+/// Check if a client supplied object contains a reserved key at any depth
+///
+/// Objects are echoed verbatim inside outbound messages, so a nested `type` could be mistaken
+/// for a message of its own by naive clients.
+///
+/// # Arguments
+///
+/// * `object` - the raised or instant object sent by a client
+fn contains_reserved_key(object: &Arbitrary) -> bool {
+    match object {
+        Arbitrary::Object(map) => map.iter().any(|(key, value)| {
+            RESERVED_KEYS.contains(&key.as_str()) || contains_reserved_key(value)
+        }),
+        Arbitrary::Array(values) => values.iter().any(contains_reserved_key),
+        _ => false,
+    }
+}
+
+/// `WebSocketServer` manages web socket rooms and responsible for coordinating web socket
+/// session. implementation is super primitive
+pub struct WebSocketServer {
+    sessions: HashMap<usize, Recipient<Message>>,
+    stops: HashMap<usize, Recipient<Stop>>,
+    memberships: HashMap<usize, HashSet<String>>, // HashMap<user_id, joined room_names>
+    capabilities: HashMap<usize, HashSet<String>>, // HashMap<user_id, negotiated capabilities>
+    rooms: HashMap<String, Room>,
+    scheduled_instants: HashMap<usize, Vec<(std::time::Instant, SpawnHandle)>>, // HashMap<user_id, (due, handle)>
+    events: Vec<Box<dyn EventSink>>,
+    dropped_messages: HashMap<usize, usize>, // HashMap<user_id, messages dropped in a row>
+    replaced: HashMap<usize, usize>, // HashMap<user_id, disconnects of replaced sessions to ignore>
+    config: Config,
+}
+
+impl Default for WebSocketServer {
+    fn default() -> WebSocketServer {
+        WebSocketServer::new(Config::default())
+    }
+}
+
+impl WebSocketServer {
+    pub fn new(config: Config) -> WebSocketServer {
+        let rooms = HashMap::new(); // mut?!
+
+        WebSocketServer {
+            sessions: HashMap::new(),
+            stops: HashMap::new(),
+            memberships: HashMap::new(),
+            capabilities: HashMap::new(),
+            rooms,
+            scheduled_instants: HashMap::new(),
+            events: Vec::new(),
+            dropped_messages: HashMap::new(),
+            replaced: HashMap::new(),
+            config,
+        }
+    }
+
+    /// Report lifecycle events to an `EventSink`, in addition to already added sinks
+    ///
+    /// # Arguments
+    /// * `events` - The sink receiving the events
+    pub fn with_event_sink(mut self, events: Box<dyn EventSink>) -> WebSocketServer {
+        self.events.push(events);
+        self
+    }
+}
+
+impl WebSocketServer {
+    /// send a message to some users in a room
+    ///
+    /// expect of the user given in the argument `skip_id`
     ///
     /// # Arguments
     ///
     /// * `room` - a string slice with the name of the room where the message has to be send
     /// * `message` - a string slice that holds the message to be send
-    /// * `skip_id` - the user id of the user that should not receive the message
-    fn send_message_skip_user(&self, room: &str, message: &str, skip_id: usize) {
+    /// * `skip_id` - the user id of the user that should not receive the message, all users
+    ///   receive it if `None`
+    fn send_message_skip_user(&self, room: &str, message: &str, skip_id: Option<usize>) {
+        let message = Frame::new(with_room(room, message));
         if let Some(room) = self.rooms.get(room) {
             let sessions = &room.connected;
             for (id, _) in sessions {
-                if *id != skip_id {
+                if Some(*id) != skip_id {
                     if let Some(addr) = self.sessions.get(id) {
-                        let _ = addr.do_send(Message(message.to_owned()));
+                        let _ = addr.do_send(self.message_for(&message, *id));
                     }
                 }
             }
+            self.send_message_observers(room, &message);
         } else {
             println!("No room '{}' found", room);
         }
     }
 
+    /// the user to skip when broadcasting a user's action
+    ///
+    /// # Arguments
+    ///
+    /// * `echo` - the kind of broadcast
+    /// * `sender_id` - the user whose action is broadcasted
+    fn echo_skip(&self, echo: Echo, sender_id: usize) -> Option<usize> {
+        if self.config.echo.contains(&echo) {
+            None
+        } else {
+            Some(sender_id)
+        }
+    }
+
     /// send a message to all users in a room
     ///
-    /// This function uses `the send_message_skip_user()-function` with the `skip_user-argument` 0.
+    /// This function uses `the send_message_skip_user()-function` without a user to skip.
     ///
     /// # Arguments
     ///
     /// * `room` - a string slice with the name of the room where the message has to be send
     /// * `message` - a string slice that holds the message to be send
     fn send_message_all(&mut self, room: &str, message: &str) {
-        self.send_message_skip_user(room, message, 0);
+        self.send_message_skip_user(room, message, None);
+    }
+
+    /// send a non-critical message to all users in a room
+    ///
+    /// Sessions with a full mailbox miss the message instead of queueing it. Sessions missing more
+    /// than `MAX_DROPPED_MESSAGES` messages in a row are disconnected as slow consumers.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the message has to be send
+    /// * `message` - a string slice that holds the message to be send
+    /// * `skip_id` - the user id of the user that should not receive the message, all users
+    ///   receive it if `None`
+    fn send_message_all_droppable(&mut self, room: &str, message: &str, skip_id: Option<usize>) {
+        let message = Frame::new(with_room(room, message));
+        let user_ids: Vec<usize> = match self.rooms.get(room) {
+            None => {
+                println!("No room '{}' found", room);
+                return;
+            }
+            Some(room) => room
+                .connected
+                .keys()
+                .filter(|user_id| Some(**user_id) != skip_id)
+                .chain(room.observers.iter())
+                .cloned()
+                .collect(),
+        };
+
+        for user_id in user_ids {
+            let result = match self.sessions.get(&user_id) {
+                None => continue,
+                Some(addr) => addr.try_send(self.message_for(&message, user_id)),
+            };
+
+            match result {
+                Err(SendError::Full(_)) => {
+                    let dropped = self.dropped_messages.entry(user_id).or_insert(0);
+                    *dropped += 1;
+                    if *dropped > MAX_DROPPED_MESSAGES {
+                        self.dropped_messages.remove(&user_id);
+                        self.send_error_session(
+                            "slow_consumer",
+                            "You have been disconnected, you can't keep up with the messages",
+                            user_id,
+                        );
+                        if let Some(stop) = self.stops.get(&user_id) {
+                            let _ = stop.do_send(Stop(StopReason::SlowConsumer));
+                        }
+                        println!("Disconnecting slow consumer {}", user_id);
+                    }
+                }
+                Ok(_) | Err(_) => {
+                    self.dropped_messages.remove(&user_id);
+                }
+            }
+        }
     }
 
     /// send a message to a specific users in a room
@@ -251,12 +1015,13 @@ impl WebSocketServer {
     /// * `message` - a string slice that holds the message to be send
     /// * `user_id` - the user id of the user that should receive the message
     fn send_message_user(&self, room: &str, message: &str, user_id: usize) {
+        let message = Frame::new(with_room(room, message));
         if let Some(room) = self.rooms.get(room) {
             let sessions = &room.connected;
             for (id, _) in sessions {
                 if id == &user_id {
                     if let Some(addr) = self.sessions.get(id) {
-                        let _ = addr.do_send(Message(message.to_owned()));
+                        let _ = addr.do_send(self.message_for(&message, *id));
                     }
                     break;
                 }
@@ -266,6 +1031,33 @@ impl WebSocketServer {
         }
     }
 
+    /// send several messages to a specific user in a room
+    ///
+    /// Users with the `batch` capability receive all messages within a single `batch` message.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the messages have to be send
+    /// * `frames` - the messages to be send, in order
+    /// * `user_id` - the user id of the user that should receive the messages
+    fn send_messages_user(&self, room: &str, frames: Vec<String>, user_id: usize) {
+        if !self.has_capability(user_id, messages::outbound::CAPABILITY_BATCH) {
+            for frame in frames {
+                self.send_message_user(room, &frame, user_id);
+            }
+            return;
+        }
+
+        let batch_txt = messages::outbound::to_frame(&messages::outbound::Batch {
+            r#type: messages::outbound::Types::Batch,
+            object: frames
+                .iter()
+                .filter_map(|frame| serde_json::from_str(&with_room(room, frame)).ok())
+                .collect(),
+        });
+        self.send_message_user(room, &batch_txt, user_id);
+    }
+
     /// send a message to all elevated users in a room
     ///
     /// This function loops threw all users in the given room and sends the given message to every user that has `elevated` set to `true`.
@@ -275,35 +1067,49 @@ impl WebSocketServer {
     /// * `room` - a string slice with the name of the room where the message has to be send
     /// * `message` - a string slice that holds the message to be send
     fn send_message_all_elevated(&self, room: &str, message: &str) {
+        let message = Frame::new(with_room(room, message));
         if let Some(room) = self.rooms.get(room) {
             let sessions = &room.connected;
             for (id, user) in sessions {
                 if user.elevated {
                     if let Some(addr) = self.sessions.get(id) {
-                        let _ = addr.do_send(Message(message.to_owned()));
+                        let _ = addr.do_send(self.message_for(&message, *id));
                     }
                 }
             }
+            self.send_message_observers(room, &message);
         } else {
             println!("No room '{}' found", room);
         }
     }
 
-    /// send a message to all non-elevated users in a room
-    ///
-    /// This function loops threw all users in the given room and sends the given message to every user that has `elevated` set to `false`.
+    /// send a message to all elevated users in a room which negotiated a capability
     ///
     /// # Arguments
     ///
     /// * `room` - a string slice with the name of the room where the message has to be send
     /// * `message` - a string slice that holds the message to be send
-    fn send_message_all_not_elevated(&self, room: &str, message: &str) {
+    /// * `capability` - a string slice with the capability the users must have negotiated
+    fn send_message_all_elevated_with_capability(
+        &self,
+        room: &str,
+        message: &str,
+        capability: &str,
+    ) {
+        let message = Frame::new(with_room(room, message));
         if let Some(room) = self.rooms.get(room) {
             let sessions = &room.connected;
             for (id, user) in sessions {
-                if !user.elevated {
+                if user.elevated && self.has_capability(*id, capability) {
+                    if let Some(addr) = self.sessions.get(id) {
+                        let _ = addr.do_send(self.message_for(&message, *id));
+                    }
+                }
+            }
+            for id in &room.observers {
+                if self.has_capability(*id, capability) {
                     if let Some(addr) = self.sessions.get(id) {
-                        let _ = addr.do_send(Message(message.to_owned()));
+                        let _ = addr.do_send(self.message_for(&message, *id));
                     }
                 }
             }
@@ -312,827 +1118,7119 @@ impl WebSocketServer {
         }
     }
 
-    /// send an error message to a specific users in a room
+    /// send a message to all users and observers in a room which did not negotiate a capability
     ///
-    /// This function loops threw all users in the given room and sends the given message to every user that has `elevated` set to `false`.
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the message has to be send
+    /// * `message` - a string slice that holds the message to be send
+    /// * `capability` - a string slice with the capability the receivers must not have negotiated
+    fn send_message_all_without_capability(&self, room: &str, message: &str, capability: &str) {
+        let message = Frame::new(with_room(room, message));
+        if let Some(room) = self.rooms.get(room) {
+            for id in room.connected.keys().chain(room.observers.iter()) {
+                if !self.has_capability(*id, capability) {
+                    if let Some(addr) = self.sessions.get(id) {
+                        let _ = addr.do_send(self.message_for(&message, *id));
+                    }
+                }
+            }
+        } else {
+            println!("No room '{}' found", room);
+        }
+    }
+
+    /// send a message about a user joining, leaving or renaming to the users who may see it
+    ///
+    /// With hidden participants only elevated users and observers receive it.
     ///
     /// # Arguments
     ///
     /// * `room` - a string slice with the name of the room where the message has to be send
-    /// * `error_code` - a string slice with a short error name
-    /// * `error_description` - a string slice with a longer description what went wrong
-    /// * `user_id` - the user id of the user that should receive the message
-    fn send_error_user(
-        &self,
-        room: &str,
-        error_code: &str,
-        error_description: &str,
-        user_id: usize,
-    ) {
-        let error_message = json!(messages::outbound::Error {
-            r#type: messages::outbound::Types::Error,
-            object: error_code.to_string(),
-            description: error_description.to_string(),
-        })
-        .to_string();
-        self.send_message_user(room, &error_message, user_id);
+    /// * `message` - a string slice that holds the message to be send
+    /// * `skip_id` - the user id of the user that should not receive the message
+    fn send_participant_change(&self, room: &str, message: &str, skip_id: Option<usize>) {
+        if !self.config.hide_participants {
+            self.send_message_skip_user(room, message, skip_id);
+            return;
+        }
+
+        let message = Frame::new(with_room(room, message));
+        if let Some(room) = self.rooms.get(room) {
+            for (id, user) in &room.connected {
+                if user.elevated && Some(*id) != skip_id {
+                    if let Some(addr) = self.sessions.get(id) {
+                        let _ = addr.do_send(self.message_for(&message, *id));
+                    }
+                }
+            }
+            self.send_message_observers(room, &message);
+        } else {
+            println!("No room '{}' found", room);
+        }
     }
-}
 
-/// Make actor from `WebSocketServer`
-impl Actor for WebSocketServer {
-    /// We are going to use simple Context, we just need ability to communicate
-    /// with other actors.
-    type Context = Context<Self>;
-}
+    /// send the current state of every room with users to all of them
+    ///
+    /// Clients which missed a message get back in sync, so every client receives it, regardless
+    /// of the `delta` capability.
+    fn send_snapshots(&self) {
+        for (room_name, room) in &self.rooms {
+            if !room.connected.is_empty() {
+                self.send_snapshot(room_name, false);
+            }
+        }
+    }
 
-/// Handler for Disconnect message.
-impl Handler<Disconnect> for WebSocketServer {
-    type Result = ();
+    /// send the current state of a room to its users
+    ///
+    /// With hidden participants every user gets the participants it may see.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room
+    /// * `skip_delta` - if users with the `delta` capability are skipped
+    fn send_snapshot(&self, room_name: &str, skip_delta: bool) {
+        let room = match self.rooms.get(room_name) {
+            Some(room) => room,
+            None => return,
+        };
 
-    fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
-        let mut rooms_leaving: Vec<String> = Vec::new();
+        if !self.config.hide_participants {
+            let txt = messages::outbound::to_frame(&messages::outbound::All {
+                r#type: messages::outbound::Types::All,
+                raised: room.raised_snapshot(),
+                joined: room.connected.clone(),
+            });
+            if skip_delta {
+                self.send_message_all_without_capability(
+                    room_name,
+                    txt.as_str(),
+                    messages::outbound::CAPABILITY_DELTA,
+                );
+            } else {
+                self.send_message_skip_user(room_name, txt.as_str(), None);
+            }
+            return;
+        }
 
-        // remove address
-        if self.sessions.remove(&msg.id).is_some() {
-            // remove session from rooms
-            for (room_name, room) in &mut self.rooms {
-                if room.connected.remove_entry(&msg.id).is_some() {
-                    rooms_leaving.push(room_name.to_owned());
-                    room.remove_user(&msg.id);
-                    break;
-                }
+        let raised = room.raised_snapshot();
+        for id in room.connected.keys() {
+            if skip_delta && self.has_capability(*id, messages::outbound::CAPABILITY_DELTA) {
+                continue;
             }
+            let txt = messages::outbound::to_frame(&messages::outbound::All {
+                r#type: messages::outbound::Types::All,
+                raised: raised.clone(),
+                joined: room.participants_for(*id, true),
+            });
+            self.send_message_user(room_name, &txt, *id);
+        }
 
-            for room_name in rooms_leaving {
-                let room = self
-                    .rooms
-                    .entry(room_name.clone())
-                    .or_insert(Room::default());
-
-                let txt = json!(messages::outbound::All {
-                    r#type: messages::outbound::Types::All,
-                    raised: room.raised.clone(),
-                    joined: room.connected.clone(),
-                })
-                .to_string();
-
-                self.send_message_all(&room_name, txt.as_str());
-
-                let room = self
-                    .rooms
-                    .entry(room_name.clone())
-                    .or_insert(Room::default());
-
-                // get username
-                let user_id = msg.id;
-
-                let mut messages_to_send_to_elevated: Vec<String> = Vec::new();
-                let mut messages_to_send_to_not_elevated: Vec<String> = Vec::new();
-
-                for i in 0..room.polls.clone().len() {
-                    let poll = room.polls[i].clone();
-                    if !poll.closed {
-                        for (id, poll_option_title) in poll.votes {
-                            if id == msg.id {
-                                // delete vote
-                                room.polls[i].votes.remove(&id);
-
-                                // send poll option message to clients
-                                let elevated_txt = json!(messages::outbound::VoteDelete {
-                                    r#type: messages::outbound::Types::VoteDelete,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: poll_option_title.clone(),
-                                    userid: user_id,
-                                })
-                                .to_string();
-                                let not_elevated_txt = json!(messages::outbound::VoteDelete {
-                                    r#type: messages::outbound::Types::VoteDelete,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: poll_option_title.clone(),
-                                    userid: 0,
-                                })
-                                .to_string();
-
-                                messages_to_send_to_elevated.push(elevated_txt);
-                                messages_to_send_to_not_elevated.push(not_elevated_txt);
-                            }
-                        }
-                    }
-                }
+        let txt = messages::outbound::to_frame(&messages::outbound::All {
+            r#type: messages::outbound::Types::All,
+            raised,
+            joined: room.connected.clone(),
+        });
+        self.send_message_observers(room, &Frame::new(with_room(room_name, &txt)));
+    }
 
-                for message_to_send_to_elevated in messages_to_send_to_elevated {
-                    self.send_message_all_elevated(&room_name, &message_to_send_to_elevated);
-                }
+    /// send a message to all admin sessions observing a room
+    ///
+    /// Observers see the room like an elevated user, so they get every message sent to all users
+    /// or to elevated users.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - the room whose observers should receive the message
+    /// * `message` - the message to be send, already carrying the room's name
+    fn send_message_observers(&self, room: &Room, message: &Frame) {
+        for id in &room.observers {
+            if let Some(addr) = self.sessions.get(id) {
+                let _ = addr.do_send(self.message_for(&message, *id));
+            }
+        }
+    }
+
+    /// check if a session negotiated a capability
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - the user id of the session
+    /// * `capability` - a string slice with the capability
+    fn has_capability(&self, user_id: usize, capability: &str) -> bool {
+        self.capabilities
+            .get(&user_id)
+            .map_or(false, |capabilities| capabilities.contains(capability))
+    }
 
-                for message_to_send_to_not_elevated in messages_to_send_to_not_elevated {
-                    self.send_message_all_not_elevated(
-                        &room_name,
-                        &message_to_send_to_not_elevated,
-                    );
+    /// send a message to all non-elevated users in a room
+    ///
+    /// This function loops threw all users in the given room and sends the given message to every user that has `elevated` set to `false`.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the message has to be send
+    /// * `message` - a string slice that holds the message to be send
+    fn send_message_all_not_elevated(&self, room: &str, message: &str) {
+        let message = Frame::new(with_room(room, message));
+        if let Some(room) = self.rooms.get(room) {
+            let sessions = &room.connected;
+            for (id, user) in sessions {
+                if !user.elevated {
+                    if let Some(addr) = self.sessions.get(id) {
+                        let _ = addr.do_send(self.message_for(&message, *id));
+                    }
                 }
             }
+        } else {
+            println!("No room '{}' found", room);
         }
     }
-}
 
-/// Handler for Message message.
-impl Handler<ClientMessage> for WebSocketServer {
-    type Result = ();
+    /// mark a room as active, creating it if it does not exist
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room that has been active
+    fn touch_room(&mut self, room: &str) {
+        let room = self.rooms.entry(room.to_owned()).or_insert(Room::default());
+        room.last_activity = std::time::Instant::now();
+    }
 
-    fn handle(&mut self, msg: ClientMessage, _: &mut Context<Self>) {
-        self.send_message_skip_user(&msg.room, msg.msg.as_str(), msg.id);
+    /// refuse a message for a room the user is not in with a `not_in_room` error
+    ///
+    /// Returns `true` if the message has to be refused. Checked before anything else, so messages
+    /// for foreign rooms neither create rooms nor change their state. The error and the `ack` are
+    /// sent to the session directly, as messages to a room only reach its members.
+    ///
+    /// # Arguments
+    /// * `room_name` - The room the message targets
+    /// * `user_id` - The user who sent the message
+    /// * `msg_id` - The `msg_id` of the message, acknowledged with the error if set
+    fn refuse_non_member(
+        &self,
+        room_name: &str,
+        user_id: usize,
+        msg_id: &Option<Arbitrary>,
+    ) -> bool {
+        let is_member = self
+            .memberships
+            .get(&user_id)
+            .map_or(false, |room_names| room_names.contains(room_name));
+
+        if !is_member {
+            let error_message = messages::outbound::to_frame(&messages::outbound::Error {
+                r#type: messages::outbound::Types::Error,
+                object: "not_in_room".to_string(),
+                description: "Refusing, you are not in this room".to_string(),
+            });
+            self.send_message_session(&with_room(room_name, &error_message), user_id);
+
+            if let Some(msg_id) = msg_id {
+                let ack_message = messages::outbound::to_frame(&messages::outbound::Ack {
+                    r#type: messages::outbound::Types::Ack,
+                    object: msg_id.clone(),
+                    success: false,
+                    error: Some("not_in_room".to_string()),
+                });
+                self.send_message_session(&with_room(room_name, &ack_message), user_id);
+            }
+            println!(
+                "Refusing message of user {} for room '{}'",
+                user_id, room_name
+            );
+        }
+        !is_member
     }
-}
 
-/// Join room, send disconnect message to old room
-/// send join message to new room
-impl Handler<Join> for WebSocketServer {
-    type Result = ();
+    /// refuse a message of a spectator with a `spectator_forbidden` error
+    ///
+    /// Returns `true` if the user is a spectator and the message has to be refused
+    ///
+    /// # Arguments
+    /// * `room_name` - The room the message targets
+    /// * `user_id` - The user who sent the message
+    fn refuse_spectator(&self, room_name: &str, user_id: usize) -> bool {
+        let spectator = self
+            .rooms
+            .get(room_name)
+            .map_or(false, |room| room.is_spectator(&user_id));
 
-    fn handle(&mut self, msg: Join, _: &mut Context<Self>) {
-        let Join {
-            addr,
-            user_id,
-            user_name,
-            room_name,
-        } = msg;
+        if spectator {
+            self.send_error_user(
+                room_name,
+                "spectator_forbidden",
+                "Refusing, spectators can only observe the room",
+                user_id,
+            );
+            println!("Refusing message of spectator");
+        }
+        spectator
+    }
 
-        self.sessions.insert(user_id, addr);
+    /// check a room name against the configured room name pattern
+    fn is_valid_room_name(&self, room_name: &str) -> bool {
+        match &self.config.room_name_pattern {
+            None => true,
+            Some(pattern) => pattern.is_match(room_name),
+        }
+    }
 
-        let room = self
+    /// close all rooms which have been idle longer than the configured timeout
+    ///
+    /// Every user in an idle room is warned with a `roomclosing` message before the room is
+    /// removed, see [remove_room](#method.remove_room).
+    fn close_idle_rooms(&mut self) {
+        let timeout = match self.config.room_idle_timeout {
+            None => return,
+            Some(timeout) => timeout,
+        };
+
+        let idle_rooms: Vec<String> = self
             .rooms
-            .entry(room_name.clone())
-            .or_insert(Room::default());
+            .iter()
+            .filter(|(_, room)| room.last_activity.elapsed() > timeout)
+            .map(|(room_name, _)| room_name.clone())
+            .collect();
+
+        for room_name in idle_rooms {
+            let txt = messages::outbound::to_frame(&messages::outbound::RoomClosing {
+                r#type: messages::outbound::Types::RoomClosing,
+                object: room_name.clone(),
+            });
+            self.send_message_all(&room_name, &txt);
+            self.remove_room(&room_name);
+            println!("Closed idle room '{}'", room_name);
+        }
+    }
 
-        let elevated = if room.connected.len() > 0 {
-            false
-        } else {
-            true
-        };
+    /// remove users from rooms whose session is gone
+    ///
+    /// Users are removed like leaving regularly, so the remaining users get an updated `all`.
+    fn reap_orphaned_users(&mut self) {
+        let orphans: Vec<(usize, String)> = self
+            .rooms
+            .iter()
+            .flat_map(|(room_name, room)| {
+                room.connected
+                    .keys()
+                    .filter(|user_id| !self.sessions.contains_key(user_id))
+                    .map(move |user_id| (*user_id, room_name.clone()))
+            })
+            .collect();
 
-        room.connected.insert(
-            user_id,
-            User {
-                name: user_name.clone(),
-                elevated,
-            },
-        );
+        for (user_id, room_name) in orphans {
+            println!("Reaping orphaned user {} in room '{}'", user_id, room_name);
+            self.leave_room(user_id, &room_name);
+        }
+    }
 
-        let msg = json!(messages::outbound::User {
-            r#type: messages::outbound::Types::User,
-            object: messages::outbound::UserFormat {
-                id: user_id,
-                name: user_name,
-                elevated
+    /// Remove a room from state after all of its users left it
+    ///
+    /// Every user of the room gets a `roomended` message. Sessions which joined further rooms
+    /// keep running, all other sessions of the room are stopped.
+    ///
+    /// # Arguments
+    /// * `room_name` - The room to remove
+    fn remove_room(&mut self, room_name: &str) {
+        let (user_ids, observers) = match self.rooms.get(room_name) {
+            None => return,
+            Some(room) => (
+                room.connected.keys().copied().collect::<Vec<usize>>(),
+                room.observers.clone(),
+            ),
+        };
+
+        let txt = messages::outbound::to_frame(&messages::outbound::RoomEnded {
+            r#type: messages::outbound::Types::RoomEnded,
+            object: room_name.to_string(),
+        });
+        self.send_message_all(room_name, &txt);
+
+        let mut stopping: Vec<usize> = observers.into_iter().collect();
+        for user_id in user_ids {
+            self.leave_room(user_id, room_name);
+            let in_other_rooms = self
+                .memberships
+                .get(&user_id)
+                .map_or(false, |room_names| !room_names.is_empty());
+            if !in_other_rooms {
+                stopping.push(user_id);
             }
-        })
-        .to_string();
-        self.send_message_skip_user(&room_name, msg.as_str(), user_id);
+        }
+        self.rooms.remove(room_name);
 
-        let room = self
-            .rooms
-            .entry(room_name.clone())
-            .or_insert(Room::default());
+        // stopping the sessions leads to regular `Disconnect`s
+        for id in stopping {
+            if let Some(stop) = self.stops.get(&id) {
+                let _ = stop.do_send(Stop(StopReason::RoomEnded));
+            }
+        }
+    }
 
-        let msg = json!(messages::outbound::All {
-            r#type: messages::outbound::Types::All,
-            raised: room.raised.clone(),
-            joined: room.connected.clone()
+    /// send a message to a specific session regardless of its rooms
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - a string slice that holds the message to be send
+    /// * `user_id` - the user id of the session that should receive the message
+    fn send_message_session(&self, message: &str, user_id: usize) {
+        if let Some(addr) = self.sessions.get(&user_id) {
+            let _ = addr.do_send(self.message_for(&Frame::new(message.to_owned()), user_id));
+        }
+    }
+
+    /// the message sent to a session for a frame, in the field naming the session negotiated
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - the frame to be send
+    /// * `user_id` - the user id of the session
+    fn message_for(&self, frame: &Frame, user_id: usize) -> Message {
+        Message(frame.text(self.has_capability(user_id, messages::outbound::CAPABILITY_CAMEL_CASE)))
+    }
+
+    /// send an error message to a specific session regardless of its rooms
+    ///
+    /// # Arguments
+    ///
+    /// * `error_code` - a string slice with a short error name
+    /// * `error_description` - a string slice with a longer description what went wrong
+    /// * `user_id` - the user id of the session that should receive the message
+    fn send_error_session(&self, error_code: &str, error_description: &str, user_id: usize) {
+        let error_message = messages::outbound::to_frame(&messages::outbound::Error {
+            r#type: messages::outbound::Types::Error,
+            object: error_code.to_string(),
+            description: error_description.to_string(),
+        });
+        self.send_message_session(&error_message, user_id);
+    }
+
+    /// validate and normalize a poll or poll-option title
+    ///
+    /// Titles are trimmed, so the same title matches when creating, voting and closing. The length
+    /// is counted in characters, not bytes, so multi-byte characters are never split or miscounted.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - a string slice with the title as sent by the client
+    fn validate_title(&self, title: &str) -> Result<String, ErrorCode> {
+        let title = title.trim();
+        if title.is_empty()
+            || title.chars().count() > self.config.max_title_length
+            || title.chars().any(char::is_control)
+        {
+            Err("title_invalid")
+        } else {
+            Ok(title.to_string())
+        }
+    }
+
+    /// send the error for a title rejected by `validate_title()` to a specific user in a room
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the message has to be send
+    /// * `error_code` - the error code returned by `validate_title()`
+    /// * `user_id` - the user id of the user that should receive the message
+    fn send_title_error_user(&self, room: &str, error_code: ErrorCode, user_id: usize) {
+        let description = format!(
+            "Titles must not be empty, longer than {} characters or contain control characters",
+            self.config.max_title_length
+        );
+        self.send_error_user(room, error_code, &description, user_id);
+    }
+
+    /// build a vote frame without the identity of the voting user
+    ///
+    /// Sent to users that are not elevated, votes are anonymous to them.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_title` - the title of the poll
+    /// * `option_title` - the title of the voted poll-option
+    fn anon_vote_frame(poll_title: &str, option_title: &str) -> String {
+        Self::identified_vote_frame(poll_title, option_title, "", ANONYMOUS_USER_ID)
+    }
+
+    /// build a vote frame including the identity of the voting user
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_title` - the title of the poll
+    /// * `option_title` - the title of the voted poll-option
+    /// * `user_name` - the name of the voting user
+    /// * `user_id` - the id of the voting user
+    fn identified_vote_frame(
+        poll_title: &str,
+        option_title: &str,
+        user_name: &str,
+        user_id: usize,
+    ) -> String {
+        messages::outbound::to_frame(&messages::outbound::Vote {
+            r#type: messages::outbound::Types::Vote,
+            pollobject: poll_title.to_string(),
+            polloptionobject: option_title.to_string(),
+            username: user_name.to_string(),
+            userid: user_id,
         })
-        .to_string();
+    }
 
-        self.send_message_user(&room_name, msg.as_str(), user_id);
+    /// build a vote-delete frame without the identity of the voting user
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_title` - the title of the poll
+    /// * `option_title` - the title of the poll-option the vote is removed from
+    fn anon_vote_delete_frame(poll_title: &str, option_title: &str) -> String {
+        Self::identified_vote_delete_frame(poll_title, option_title, ANONYMOUS_USER_ID)
+    }
 
-        let msg = json!(messages::outbound::PermissionChange {
-            r#type: messages::outbound::Types::SelfStatus,
-            object: user_id,
+    /// build a vote-delete frame including the id of the voting user
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_title` - the title of the poll
+    /// * `option_title` - the title of the poll-option the vote is removed from
+    /// * `user_id` - the id of the voting user
+    fn identified_vote_delete_frame(
+        poll_title: &str,
+        option_title: &str,
+        user_id: usize,
+    ) -> String {
+        messages::outbound::to_frame(&messages::outbound::VoteDelete {
+            r#type: messages::outbound::Types::VoteDelete,
+            pollobject: poll_title.to_string(),
+            polloptionobject: option_title.to_string(),
+            userid: user_id,
         })
-        .to_string();
+    }
 
-        self.send_message_user(&room_name, msg.as_str(), user_id);
+    /// send an acknowledgement to a specific user in a room
+    ///
+    /// Nothing is sent if the user did not attach a `msg_id` to the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the message has to be send
+    /// * `user_id` - the user id of the user that should receive the acknowledgement
+    /// * `msg_id` - the `msg_id` of the acknowledged message
+    /// * `result` - `Ok` on success or `Err` with the error code on failure
+    fn send_ack(
+        &self,
+        room: &str,
+        user_id: usize,
+        msg_id: &Option<Arbitrary>,
+        result: Result<(), &str>,
+    ) {
+        if let Some(msg_id) = msg_id {
+            let ack_message = messages::outbound::to_frame(&messages::outbound::Ack {
+                r#type: messages::outbound::Types::Ack,
+                object: msg_id.clone(),
+                success: result.is_ok(),
+                error: result.err().map(|error_code| error_code.to_string()),
+            });
+            self.send_message_user(room, &ack_message, user_id);
+        }
+    }
 
-        let room = self
-            .rooms
-            .entry(room_name.clone())
-            .or_insert(Room::default());
+    /// send an error message to a specific users in a room
+    ///
+    /// This function loops threw all users in the given room and sends the given message to every user that has `elevated` set to `false`.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the message has to be send
+    /// * `error_code` - a string slice with a short error name
+    /// * `error_description` - a string slice with a longer description what went wrong
+    /// * `user_id` - the user id of the user that should receive the message
+    fn send_error_user(
+        &self,
+        room: &str,
+        error_code: &str,
+        error_description: &str,
+        user_id: usize,
+    ) {
+        let error_message = messages::outbound::to_frame(&messages::outbound::Error {
+            r#type: messages::outbound::Types::Error,
+            object: error_code.to_string(),
+            description: error_description.to_string(),
+        });
+        self.send_message_user(room, &error_message, user_id);
+    }
+}
 
-        // send polls
-        for poll in room.polls.clone() {
-            if !poll.closed {
-                let poll_txt = json!(messages::outbound::Poll {
-                    r#type: messages::outbound::Types::Poll,
-                    object: poll.title.clone(),
-                })
-                .to_string();
-                self.send_message_user(&room_name, &poll_txt, user_id);
+/// Make actor from `WebSocketServer`
+impl Actor for WebSocketServer {
+    /// We are going to use simple Context, we just need ability to communicate
+    /// with other actors.
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        // sessions refuse their clients' requests instead of queueing them beyond the capacity
+        ctx.set_mailbox_capacity(self.config.mailbox_capacity);
+
+        if self.config.room_idle_timeout.is_some() {
+            ctx.run_interval(ROOM_SWEEP_INTERVAL, |act, _| act.close_idle_rooms());
+        }
+        ctx.run_interval(ORPHAN_REAP_INTERVAL, |act, _| act.reap_orphaned_users());
+        if let Some(snapshot_interval) = self.config.snapshot_interval {
+            ctx.run_interval(snapshot_interval, |act, _| act.send_snapshots());
+        }
+    }
+}
+
+/// Handler for Disconnect message.
+impl Handler<Disconnect> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, ctx: &mut Context<Self>) {
+        // the disconnect of a replaced session must not remove the session replacing it
+        if let Some(pending) = self.replaced.get_mut(&msg.id) {
+            *pending -= 1;
+            if *pending == 0 {
+                self.replaced.remove(&msg.id);
+            }
+            return;
+        }
+
+        self.stops.remove(&msg.id);
+        self.capabilities.remove(&msg.id);
+        self.dropped_messages.remove(&msg.id);
+        for room in self.rooms.values_mut() {
+            room.observers.remove(&msg.id);
+        }
+
+        // cancel instants the user scheduled but which are not due yet
+        for (_, handle) in self.scheduled_instants.remove(&msg.id).unwrap_or_default() {
+            ctx.cancel_future(handle);
+        }
+
+        // remove address
+        if self.sessions.remove(&msg.id).is_some() {
+            // remove session from all joined rooms
+            let rooms_leaving = self.memberships.remove(&msg.id).unwrap_or_default();
+            for room_name in rooms_leaving {
+                self.leave_room(msg.id, &room_name);
+            }
+        }
+    }
+}
+
+/// Handler for Activity message.
+impl Handler<Activity> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Activity, _: &mut Context<Self>) {
+        // keep all joined rooms from being closed as idle
+        if let Some(room_names) = self.memberships.get(&msg.id) {
+            for room_name in room_names {
+                if let Some(room) = self.rooms.get_mut(room_name) {
+                    room.last_activity = std::time::Instant::now();
+                }
+            }
+        }
+    }
+}
+
+/// Handler for Message message.
+impl Handler<ClientMessage> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ClientMessage, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room, msg.id, &None) {
+            return;
+        }
+
+        let skip_id = self.echo_skip(Echo::ClientMessage, msg.id);
+        self.send_message_skip_user(&msg.room, msg.msg.as_str(), skip_id);
+    }
+}
+
+/// Join room, send disconnect message to old room
+/// send join message to new room
+impl Handler<Join> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Join, ctx: &mut Context<Self>) {
+        let Join {
+            addr,
+            stop,
+            user_id,
+            user_name,
+            room_name,
+            capabilities,
+            spectator,
+            admin,
+        } = msg;
+
+        if self.sessions.contains_key(&user_id) {
+            self.replace_session(user_id, ctx);
+        }
+
+        self.sessions.insert(user_id, addr);
+        self.stops.insert(user_id, stop);
+
+        if !self.is_valid_room_name(&room_name) {
+            self.send_error_session(
+                "invalid_room_name",
+                "Refusing to join, the room name is not allowed",
+                user_id,
+            );
+            println!("Refusing to join, invalid room name '{}'", room_name);
+            if let Some(stop) = self.stops.get(&user_id) {
+                let _ = stop.do_send(Stop(StopReason::Refused));
+            }
+            return;
+        }
+
+        self.touch_room(&room_name);
+
+        let mut agreed_capabilities: Vec<String> = capabilities.iter().cloned().collect();
+        agreed_capabilities.sort();
+        self.capabilities.insert(user_id, capabilities);
+
+        let msg = messages::outbound::to_frame(&messages::outbound::Hello {
+            r#type: messages::outbound::Types::Hello,
+            version: messages::outbound::PROTOCOL_VERSION,
+            capabilities: agreed_capabilities,
+        });
+        self.send_message_session(msg.as_str(), user_id);
+
+        if admin {
+            self.observe_room(user_id, &room_name);
+            return;
+        }
+
+        if self
+            .join_room(user_id, user_name, room_name, spectator)
+            .is_err()
+        {
+            if let Some(stop) = self.stops.get(&user_id) {
+                let _ = stop.do_send(Stop(StopReason::Refused));
+            }
+        }
+    }
+}
+
+impl WebSocketServer {
+    /// Close the session registered for an id before another session joins with it
+    ///
+    /// The replaced session is told why and stopped, it leaves all rooms so the joining session
+    /// takes over cleanly. Its disconnect is ignored and the instants it scheduled are cancelled.
+    ///
+    /// # Arguments
+    /// * `user_id` - The id another session joins with
+    /// * `ctx` - The context of the server, running the scheduled instants
+    fn replace_session(&mut self, user_id: usize, ctx: &mut Context<Self>) {
+        println!(
+            "Session {} joined again, replacing the earlier session",
+            user_id
+        );
+        self.send_error_session(
+            "replaced",
+            "Another session joined with your id, closing this one",
+            user_id,
+        );
+        if let Some(stop) = self.stops.remove(&user_id) {
+            // a session that is gone already won't disconnect anymore
+            if stop.do_send(Stop(StopReason::Replaced)).is_ok() {
+                *self.replaced.entry(user_id).or_insert(0) += 1;
+            }
+        }
+
+        self.dropped_messages.remove(&user_id);
+        for room in self.rooms.values_mut() {
+            room.observers.remove(&user_id);
+        }
+        for (_, handle) in self.scheduled_instants.remove(&user_id).unwrap_or_default() {
+            ctx.cancel_future(handle);
+        }
+        let rooms_leaving = self.memberships.remove(&user_id).unwrap_or_default();
+        for room_name in rooms_leaving {
+            self.leave_room(user_id, &room_name);
+        }
+        self.sessions.remove(&user_id);
+    }
+}
+
+impl Handler<JoinRoom> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: JoinRoom, _: &mut Context<Self>) {
+        if !self.is_valid_room_name(&msg.room_name) {
+            self.send_error_session(
+                "invalid_room_name",
+                "Refusing to join, the room name is not allowed",
+                msg.user_id,
+            );
+            println!("Refusing to join, invalid room name '{}'", msg.room_name);
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let already_joined = self
+            .memberships
+            .get(&msg.user_id)
+            .map_or(false, |rooms| rooms.contains(&msg.room_name));
+
+        if already_joined {
+            self.send_error_session(
+                "already_in_room",
+                "Refusing to join, already in this room",
+                msg.user_id,
+            );
+            println!("Refusing to join, already in this room");
+            return;
+        }
+
+        let _ = self.join_room(msg.user_id, msg.user_name, msg.room_name, msg.spectator);
+    }
+}
+
+impl Handler<LeaveRoom> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: LeaveRoom, _: &mut Context<Self>) {
+        let joined = self
+            .memberships
+            .get(&msg.user_id)
+            .map_or(false, |rooms| rooms.contains(&msg.room_name));
+
+        if !joined {
+            self.send_error_session(
+                "not_in_room",
+                "Refusing to leave, not in this room",
+                msg.user_id,
+            );
+            println!("Refusing to leave, not in this room");
+            return;
+        }
+
+        self.leave_room(msg.user_id, &msg.room_name);
+    }
+}
+
+impl Handler<RenameUser> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RenameUser, _: &mut Context<Self>) {
+        let room_names: Vec<String> = match self.memberships.get(&msg.user_id) {
+            Some(room_names) => room_names.iter().cloned().collect(),
+            None => return,
+        };
+
+        for room_name in room_names {
+            let room = match self.rooms.get_mut(&room_name) {
+                Some(room) => room,
+                None => continue,
+            };
+            let old_name = match room.connected.get_mut(&msg.user_id) {
+                Some(user) => std::mem::replace(&mut user.name, msg.user_name.clone()),
+                None => continue,
+            };
+            // raised objects show their owner's current name
+            for raised in room.raised.iter_mut() {
+                if raised.owner_id == msg.user_id {
+                    raised.owner_name = msg.user_name.clone();
+                }
+            }
+
+            let renamed = messages::outbound::to_frame(&messages::outbound::UserRenamed {
+                r#type: messages::outbound::Types::UserRenamed,
+                object: msg.user_id,
+                oldname: old_name.clone(),
+                newname: msg.user_name.clone(),
+            });
+            self.send_participant_change(&room_name, &renamed, None);
+            println!(
+                "User {} renamed from '{}' to '{}' in room '{}'",
+                msg.user_id, old_name, msg.user_name, room_name
+            );
+        }
+    }
+}
+
+impl WebSocketServer {
+    /// Adds a connected session to a room and sends it the room's current state
+    ///
+    /// # Arguments
+    /// * `user_id` - The session joining the room. Must already be registered in `sessions`.
+    /// * `user_name` - The name of the user within the room
+    /// * `room_name` - The room to join, created if it does not exist
+    fn join_room(
+        &mut self,
+        user_id: usize,
+        user_name: String,
+        room_name: String,
+        spectator: bool,
+    ) -> Result<(), &'static str> {
+        let room = self
+            .rooms
+            .entry(room_name.clone())
+            .or_insert(Room::default());
+
+        if room.is_banned(&user_name) {
+            self.send_error_session("banned", "You are banned from this room", user_id);
+            println!("Refusing to join, user is banned");
+            return Err("banned");
+        }
+
+        let elevated = self.config.admin_names.contains(&user_name)
+            || match self.config.elevation_mode {
+                ElevationMode::First => {
+                    !spectator && room.connected.values().all(|user| user.spectator)
+                }
+                ElevationMode::None => false,
+                ElevationMode::All => true,
+            };
+
+        room.connected.insert(
+            user_id,
+            User {
+                name: user_name.clone(),
+                elevated,
+                muted: false,
+                spectator,
+            },
+        );
+
+        self.memberships
+            .entry(user_id)
+            .or_insert(HashSet::new())
+            .insert(room_name.clone());
+
+        for events in &self.events {
+            events.user_joined(&room_name, user_id, &user_name);
+        }
+
+        let msg = messages::outbound::to_frame(&messages::outbound::User {
+            r#type: messages::outbound::Types::User,
+            object: messages::outbound::UserFormat {
+                id: user_id,
+                name: user_name,
+                elevated,
+            },
+        });
+        self.send_participant_change(&room_name, msg.as_str(), Some(user_id));
+
+        let room = self
+            .rooms
+            .entry(room_name.clone())
+            .or_insert(Room::default());
+
+        // replay the room's state to the joining user
+        let mut replay: Vec<String> = Vec::new();
+
+        replay.push(messages::outbound::to_frame(&messages::outbound::All {
+            r#type: messages::outbound::Types::All,
+            raised: room.raised_snapshot(),
+            joined: room.participants_for(user_id, self.config.hide_participants),
+        }));
+
+        replay.push(messages::outbound::to_frame(
+            &messages::outbound::PermissionChange {
+                r#type: messages::outbound::Types::SelfStatus,
+                object: user_id,
+            },
+        ));
+
+        replay.extend(Self::poll_replay(room));
+
+        self.send_messages_user(&room_name, replay, user_id);
+
+        Ok(())
+    }
+
+    /// Adds an admin session as hidden observer of a room and sends it the room's current state
+    ///
+    /// # Arguments
+    /// * `user_id` - The admin session. Must already be registered in `sessions`.
+    /// * `room_name` - The room to observe, created if it does not exist
+    fn observe_room(&mut self, user_id: usize, room_name: &str) {
+        let room = self
+            .rooms
+            .entry(room_name.to_owned())
+            .or_insert(Room::default());
+        room.observers.insert(user_id);
+
+        let mut replay: Vec<String> = Vec::new();
+        replay.push(messages::outbound::to_frame(&messages::outbound::All {
+            r#type: messages::outbound::Types::All,
+            raised: room.raised_snapshot(),
+            joined: room.connected.clone(),
+        }));
+        replay.extend(Self::poll_replay(room));
+
+        for frame in replay {
+            self.send_message_session(&with_room(room_name, &frame), user_id);
+        }
+        println!("Admin session {} observes room '{}'", user_id, room_name);
+    }
+
+    /// Build the frames replaying a room's polls, their options and votes
+    ///
+    /// Closed polls are skipped, votes are anonymous.
+    ///
+    /// # Arguments
+    /// * `room` - The room whose polls are replayed
+    fn poll_replay(room: &Room) -> Vec<String> {
+        let mut replay: Vec<String> = Vec::new();
+
+        for poll in room.polls.clone() {
+            if poll.state != PollState::Closed {
+                let poll_txt = messages::outbound::to_frame(&messages::outbound::Poll {
+                    r#type: messages::outbound::Types::Poll,
+                    object: poll.title.clone(),
+                    state: poll.state,
+                });
+                replay.push(poll_txt);
+
+                // send options for poll
+                for option in poll.options.clone() {
+                    let option_txt =
+                        messages::outbound::to_frame(&messages::outbound::PollOption {
+                            r#type: messages::outbound::Types::PollOption,
+                            pollobject: poll.title.clone(),
+                            polloptionobject: option.title.clone(),
+                            order: option.order,
+                        });
+                    replay.push(option_txt);
+                }
+
+                // send votes for poll
+                for (_, option_title) in poll.votes.clone() {
+                    replay.push(Self::anon_vote_frame(&poll.title, &option_title));
+                }
+            }
+        }
+
+        replay
+    }
+
+    /// Build the final results of a poll
+    ///
+    /// Contains the votes per option in the order the options were added, the total number of votes
+    /// and how long the poll has been running. The quorum compares the users who voted with the
+    /// participants of the room who may vote, spectators can't vote and don't count.
+    ///
+    /// Ties are broken on the server so all clients agree: the ranking orders the options by
+    /// votes, options with the same number of votes by the order they were added in. All options
+    /// with the most votes are winners, there are none without votes.
+    ///
+    /// # Arguments
+    /// * `room_name` - The room of the poll
+    /// * `room` - The room of the poll, to count its participants
+    /// * `poll` - The poll to build the results of
+    fn poll_results(room_name: &str, room: &Room, poll: &Poll) -> Arbitrary {
+        let tally: Vec<(&PollOption, usize)> = poll
+            .options
+            .iter()
+            .map(|option| {
+                let votes = poll
+                    .votes
+                    .values()
+                    .filter(|option_title| *option_title == &option.title)
+                    .count();
+                (option, votes)
+            })
+            .collect();
+
+        let options: Vec<Arbitrary> = tally
+            .iter()
+            .map(|(option, votes)| {
+                json!({
+                    "polloptionobject": option.title,
+                    "votes": votes,
+                })
+            })
+            .collect();
+
+        let mut ranking = tally.clone();
+        ranking.sort_by_key(|(option, votes)| (std::cmp::Reverse(*votes), option.order));
+        let most_votes = ranking.first().map_or(0, |(_, votes)| *votes);
+        let winners: Vec<&str> = ranking
+            .iter()
+            .filter(|(_, votes)| most_votes > 0 && *votes == most_votes)
+            .map(|(option, _)| option.title.as_str())
+            .collect();
+        let ranking: Vec<&str> = ranking
+            .iter()
+            .map(|(option, _)| option.title.as_str())
+            .collect();
+        let eligible = room
+            .connected
+            .values()
+            .filter(|user| !user.spectator)
+            .count();
+
+        json!({
+            "room": room_name,
+            "pollobject": poll.title,
+            "options": options,
+            "ranking": ranking,
+            "winners": winners,
+            "votes": poll.votes.len(),
+            "quorum": {
+                "voters": poll.votes.len(),
+                "eligible": eligible,
+            },
+            "duration_secs": poll.created.elapsed().as_secs(),
+        })
+    }
+
+    /// Removes a user from a room and informs the remaining users
+    ///
+    /// # Arguments
+    /// * `user_id` - The user leaving the room
+    /// * `room_name` - The room to leave
+    fn leave_room(&mut self, user_id: usize, room_name: &str) {
+        if let Some(rooms) = self.memberships.get_mut(&user_id) {
+            rooms.remove(room_name);
+        }
+
+        let room = match self.rooms.get_mut(room_name) {
+            None => return,
+            Some(room) => room,
+        };
+
+        let user = match room.connected.remove(&user_id) {
+            None => return,
+            Some(user) => user,
+        };
+        room.remove_user(&user_id);
+
+        for events in &self.events {
+            events.user_left(room_name, user_id, &user.name);
+        }
+
+        let left_txt = messages::outbound::to_frame(&messages::outbound::User {
+            r#type: messages::outbound::Types::UserLeft,
+            object: messages::outbound::UserFormat {
+                id: user_id,
+                name: user.name,
+                elevated: user.elevated,
+            },
+        });
+
+        // clients with the `delta` capability apply the `userleft` message to their own state
+        self.send_participant_change(room_name, left_txt.as_str(), None);
+        self.send_snapshot(room_name, true);
+
+        let room = self
+            .rooms
+            .entry(room_name.to_owned())
+            .or_insert(Room::default());
+
+        let mut messages_to_send_to_elevated: Vec<String> = Vec::new();
+        let mut messages_to_send_to_not_elevated: Vec<String> = Vec::new();
+
+        for (poll_title, poll_option_title) in room.remove_user_votes(&user_id) {
+            // send poll option message to clients
+            let elevated_txt =
+                Self::identified_vote_delete_frame(&poll_title, &poll_option_title, user_id);
+            let not_elevated_txt = Self::anon_vote_delete_frame(&poll_title, &poll_option_title);
+
+            messages_to_send_to_elevated.push(elevated_txt);
+            messages_to_send_to_not_elevated.push(not_elevated_txt);
+        }
+
+        for message_to_send_to_elevated in messages_to_send_to_elevated {
+            self.send_message_all_elevated(room_name, &message_to_send_to_elevated);
+        }
+
+        for message_to_send_to_not_elevated in messages_to_send_to_not_elevated {
+            self.send_message_all_not_elevated(room_name, &message_to_send_to_not_elevated);
+        }
+
+        if self.config.close_polls_on_owner_leave {
+            let owned_polls: Vec<usize> = match self.rooms.get(room_name) {
+                None => Vec::new(),
+                Some(room) => room
+                    .polls
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, poll)| poll.owner_id == user_id && poll.state != PollState::Closed)
+                    .map(|(poll_index, _)| poll_index)
+                    .collect(),
+            };
+            for poll_index in owned_polls {
+                self.close_poll(room_name, poll_index);
+            }
+        }
+    }
+}
+
+impl WebSocketServer {
+    /// Raise an object for a user and inform all users of the room
+    ///
+    /// Errors are sent to the user, acknowledging is up to the caller.
+    ///
+    /// # Arguments
+    /// * `room_name` - The room the object is raised in
+    /// * `owner_id` - The user raising the object
+    /// * `owner_name` - The name of the user raising the object
+    /// * `object` - The raised object
+    fn raise(
+        &mut self,
+        room_name: &str,
+        owner_id: usize,
+        owner_name: String,
+        object: Arbitrary,
+    ) -> Result<(), ErrorCode> {
+        if self.refuse_spectator(room_name, owner_id) {
+            return Err("spectator_forbidden");
+        }
+
+        if object.is_null() {
+            self.send_error_user(
+                room_name,
+                "missing_object",
+                "Refusing to raise, the object is missing",
+                owner_id,
+            );
+            println!("Refusing to raise, object is missing");
+            return Err("missing_object");
+        }
+
+        if contains_reserved_key(&object) {
+            self.send_error_user(
+                room_name,
+                "invalid_object",
+                "Refusing to raise, the object must not contain the keys 'type' or 'room'",
+                owner_id,
+            );
+            println!("Refusing to raise, object contains a reserved key");
+            return Err("invalid_object");
+        }
+
+        let muted = self.rooms.get(room_name).unwrap().is_muted(&owner_id);
+
+        if muted {
+            self.send_error_user(
+                room_name,
+                "muted",
+                "Refusing to raise, you are muted",
+                owner_id,
+            );
+            println!("Refusing to raise, user is muted");
+            return Err("muted");
+        }
+
+        let elevated = self
+            .rooms
+            .get(room_name)
+            .unwrap()
+            .is_elevated(&owner_id)
+            .unwrap_or(false);
+
+        if self.config.raise_requires_elevation && !elevated {
+            self.send_error_user(
+                room_name,
+                "no_permission",
+                "You do not have permission to raise (because you're not elevated)",
+                owner_id,
+            );
+            println!("User does not have permission to raise (not elevated)");
+            return Err("no_permission");
+        }
+
+        let mut check_raised = self.rooms.get(room_name).unwrap().raised.clone();
+        check_raised.retain(|elem| elem.object == object && elem.owner_id == owner_id);
+
+        if check_raised.len() > 0 {
+            self.send_error_user(
+                room_name,
+                "already_raised",
+                "Refusing to raise, already raised",
+                owner_id,
+            );
+            println!("Refusing to raise, already raised");
+            return Err("already_raised");
+        }
+
+        if let Some(max_object_raises) = self.config.max_object_raises {
+            let object_raises = self
+                .rooms
+                .get(room_name)
+                .unwrap()
+                .raised
+                .iter()
+                .filter(|elem| elem.object == object)
+                .count();
+
+            if object_raises >= max_object_raises {
+                self.send_error_user(
+                    room_name,
+                    "object_raise_limit",
+                    "Refusing to raise, this object has been raised too often",
+                    owner_id,
+                );
+                println!("Refusing to raise, object raise limit reached");
+                return Err("object_raise_limit");
+            }
+        }
+
+        // update the state before broadcasting, so a snapshot never misses a broadcasted raise
+        let room = self
+            .rooms
+            .entry(room_name.to_owned())
+            .or_insert(Room::default());
+        room.raised.push(Raised {
+            object: object.clone(),
+            owner_id,
+            owner_name: owner_name.clone(),
+        });
+
+        let txt = messages::outbound::to_frame(&messages::outbound::OwnedObject {
+            r#type: messages::outbound::Types::Raised,
+            owner_id,
+            owner_name,
+            object,
+            elevated: elevated,
+        });
+        self.send_message_skip_user(room_name, &txt, self.echo_skip(Echo::Raise, owner_id));
+
+        Ok(())
+    }
+}
+
+impl Handler<Raise> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Raise, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let result = self.raise(&msg.room_name, msg.owner_id, msg.owner_name, msg.object);
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, result);
+    }
+}
+
+impl Handler<RaiseMany> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RaiseMany, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        // raise every valid object, each failed object is reported with its own error
+        let mut failed = Vec::new();
+        for (index, object) in msg.objects.into_iter().enumerate() {
+            let raised = self.raise(
+                &msg.room_name,
+                msg.owner_id,
+                msg.owner_name.clone(),
+                object.clone(),
+            );
+            if let Err(error_code) = raised {
+                failed.push(messages::outbound::FailedRaise {
+                    index,
+                    object,
+                    error: error_code.to_string(),
+                });
+            }
+        }
+
+        // the ack lists which objects failed, the error frames alone don't tell
+        if let Some(msg_id) = msg.msg_id {
+            let ack_message = messages::outbound::to_frame(&messages::outbound::RaiseManyAck {
+                r#type: messages::outbound::Types::Ack,
+                object: msg_id,
+                success: failed.is_empty(),
+                error: failed.first().map(|failure| failure.error.clone()),
+                failed,
+            });
+            self.send_message_user(&msg.room_name, &ack_message, msg.owner_id);
+        }
+    }
+}
+
+impl Handler<Lower> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Lower, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let equiv_clone = msg.clone();
+        let room = self
+            .rooms
+            .entry(msg.room_name.clone())
+            .or_insert(Room::default());
+
+        let mut check_raised = room.raised.clone();
+        check_raised.retain(|elem| &elem.object == &msg.object && &elem.owner_id == &msg.owner_id);
+
+        if check_raised.len() == 0 {
+            // list what the user did raise, so the client can recover
+            let error_message = messages::outbound::to_frame(&messages::outbound::NotRaised {
+                r#type: messages::outbound::Types::Error,
+                object: "not_raised".to_string(),
+                description: "Refusing to lower, is not raised".to_string(),
+                raised: room
+                    .raised
+                    .iter()
+                    .filter(|raised| raised.owner_id == msg.owner_id)
+                    .map(|raised| raised.object.clone())
+                    .collect(),
+            });
+            self.send_message_user(&msg.room_name, &error_message, msg.owner_id);
+            self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Err("not_raised"));
+            println!("Refusing to lower, is not raised");
+            return;
+        }
+
+        let raised_equivalent = Raised {
+            object: json!(equiv_clone.object),
+            owner_id: equiv_clone.owner_id,
+            owner_name: equiv_clone.owner_name,
+        };
+
+        // update the state before broadcasting, so a snapshot never holds a broadcasted lower
+        room.raised.retain(|elem| elem != &raised_equivalent);
+
+        let elevated = self
+            .rooms
+            .get(msg.room_name.as_str())
+            .unwrap()
+            .is_elevated(&msg.owner_id)
+            .unwrap_or(false);
+
+        let txt = messages::outbound::to_frame(&messages::outbound::OwnedObject {
+            r#type: messages::outbound::Types::Lower,
+            owner_id: msg.owner_id,
+            owner_name: msg.owner_name,
+            object: msg.object,
+            elevated: elevated,
+        });
+        let skip_id = self.echo_skip(Echo::Lower, msg.owner_id);
+        self.send_message_skip_user(&msg.room_name, &txt, skip_id);
+
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Ok(()));
+    }
+}
+
+impl Handler<Instant> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, mut msg: Instant, ctx: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        if self.refuse_spectator(&msg.room_name, msg.owner_id) {
+            self.send_ack(
+                &msg.room_name,
+                msg.owner_id,
+                &msg.msg_id,
+                Err("spectator_forbidden"),
+            );
+            return;
+        }
+
+        let muted = self
+            .rooms
+            .get(msg.room_name.as_str())
+            .unwrap()
+            .is_muted(&msg.owner_id);
+
+        if muted {
+            self.send_error_user(
+                &msg.room_name,
+                "muted",
+                "Refusing to send instant, you are muted",
+                msg.owner_id,
+            );
+            self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Err("muted"));
+            println!("Refusing to send instant, user is muted");
+            return;
+        }
+
+        if msg.object.is_null() {
+            self.send_error_user(
+                &msg.room_name,
+                "missing_object",
+                "Refusing to send instant, the object is missing",
+                msg.owner_id,
+            );
+            self.send_ack(
+                &msg.room_name,
+                msg.owner_id,
+                &msg.msg_id,
+                Err("missing_object"),
+            );
+            println!("Refusing to send instant, object is missing");
+            return;
+        }
+
+        if contains_reserved_key(&msg.object) {
+            self.send_error_user(
+                &msg.room_name,
+                "invalid_object",
+                "Refusing to send instant, the object must not contain the keys 'type' or 'room'",
+                msg.owner_id,
+            );
+            self.send_ack(
+                &msg.room_name,
+                msg.owner_id,
+                &msg.msg_id,
+                Err("invalid_object"),
+            );
+            println!("Refusing to send instant, object contains a reserved key");
+            return;
+        }
+
+        match msg.delay.take() {
+            Some(delay) if delay > MAX_INSTANT_DELAY => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "invalid_delay",
+                    "Refusing to schedule instant, delay is too long",
+                    msg.owner_id,
+                );
+                self.send_ack(
+                    &msg.room_name,
+                    msg.owner_id,
+                    &msg.msg_id,
+                    Err("invalid_delay"),
+                );
+                println!("Refusing to schedule instant, delay is too long");
+                return;
+            }
+            Some(delay) if delay > Duration::from_secs(0) => {
+                // acknowledge scheduling, the delayed instant is handled again without delay
+                let owner_id = msg.owner_id;
+                let room_name = msg.room_name.clone();
+                let msg_id = msg.msg_id.take();
+                let handle = ctx.run_later(delay, move |act, ctx| {
+                    // the user may have left the room in the meantime
+                    let is_member = act
+                        .memberships
+                        .get(&msg.owner_id)
+                        .map_or(false, |room_names| room_names.contains(&msg.room_name));
+                    if is_member {
+                        Handler::<Instant>::handle(act, msg, ctx);
+                    }
+                });
+
+                let now = std::time::Instant::now();
+                let scheduled = self.scheduled_instants.entry(owner_id).or_default();
+                scheduled.retain(|(due, _)| *due > now);
+                scheduled.push((now + delay, handle));
+
+                self.send_ack(&room_name, owner_id, &msg_id, Ok(()));
+                return;
+            }
+            Some(_) | None => (),
+        }
+
+        let elevated = self
+            .rooms
+            .get(msg.room_name.as_str())
+            .unwrap()
+            .is_elevated(&msg.owner_id)
+            .unwrap_or(false);
+
+        let txt = messages::outbound::to_frame(&messages::outbound::OwnedObject {
+            r#type: messages::outbound::Types::Instant,
+            owner_id: msg.owner_id,
+            owner_name: msg.owner_name,
+            object: msg.object,
+            elevated: elevated,
+        });
+
+        // instants are fleeting, slow sessions rather miss them than fall further behind
+        let skip_id = self.echo_skip(Echo::Instant, msg.owner_id);
+        self.send_message_all_droppable(&msg.room_name, &txt, skip_id);
+
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Ok(()));
+    }
+}
+
+impl Handler<Poll> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, mut poll: Poll, _: &mut Context<Self>) {
+        if self.refuse_non_member(&poll.room_name, poll.owner_id, &poll.msg_id) {
+            return;
+        }
+
+        self.touch_room(&poll.room_name);
+
+        if self.refuse_spectator(&poll.room_name, poll.owner_id) {
+            self.send_ack(
+                &poll.room_name,
+                poll.owner_id,
+                &poll.msg_id,
+                Err("spectator_forbidden"),
+            );
+            return;
+        }
+
+        // validate title
+        poll.title = match self.validate_title(&poll.title) {
+            Ok(title) => title,
+            Err(error_code) => {
+                self.send_title_error_user(&poll.room_name, error_code, poll.owner_id);
+                self.send_ack(
+                    &poll.room_name,
+                    poll.owner_id,
+                    &poll.msg_id,
+                    Err(error_code),
+                );
+                println!("Refusing to create poll, title is invalid");
+                return;
+            }
+        };
+
+        // get room
+        let room = self
+            .rooms
+            .entry(poll.room_name.clone())
+            .or_insert(Room::default());
+
+        // the session id identifies the user, the supplied name is not trusted
+        if !room.is_elevated(&poll.owner_id).unwrap_or(false) {
+            self.send_error_user(
+                &poll.room_name,
+                "no_permission",
+                "You do not have permission to create polls (because you're not elevated)",
+                poll.owner_id,
+            );
+            self.send_ack(
+                &poll.room_name,
+                poll.owner_id,
+                &poll.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to create polls (not elevated)");
+            return;
+        }
+
+        // check if poll already exists
+        let mut poll_exists = room.polls.clone();
+        poll_exists.retain(|elem| &elem.title == &poll.title);
+
+        if poll_exists.len() > 0 {
+            self.send_error_user(
+                &poll.room_name,
+                "poll_already_exists",
+                "A poll with that title already exists",
+                poll.owner_id,
+            );
+            self.send_ack(
+                &poll.room_name,
+                poll.owner_id,
+                &poll.msg_id,
+                Err("poll_already_exists"),
+            );
+            println!("A poll with that title already exists");
+            return;
+        }
+
+        // check if room has too many open polls
+        if let Some(max_open_polls) = self.config.max_open_polls {
+            if room.open_polls() >= max_open_polls {
+                self.send_error_user(
+                    &poll.room_name,
+                    "too_many_polls",
+                    "Sorry, this room has too many open polls",
+                    poll.owner_id,
+                );
+                self.send_ack(
+                    &poll.room_name,
+                    poll.owner_id,
+                    &poll.msg_id,
+                    Err("too_many_polls"),
+                );
+                println!("Room has too many open polls");
+                return;
+            }
+        }
+
+        if room.in_poll_cooldown(self.config.poll_cooldown) {
+            self.send_error_user(
+                &poll.room_name,
+                "poll_cooldown",
+                "Sorry, a poll has been created in this room too recently",
+                poll.owner_id,
+            );
+            self.send_ack(
+                &poll.room_name,
+                poll.owner_id,
+                &poll.msg_id,
+                Err("poll_cooldown"),
+            );
+            println!("Refusing to create poll, room is in cooldown");
+            return;
+        }
+
+        // clone later needed values
+        let poll_title = poll.title.clone();
+        let poll_state = poll.state;
+        let room_name = poll.room_name.clone();
+        let owner_id = poll.owner_id;
+        let msg_id = poll.msg_id.take();
+
+        // add poll to room
+        room.polls.push(poll);
+        room.last_poll_created = Some(std::time::Instant::now());
+
+        for events in &self.events {
+            events.poll_created(&room_name, &poll_title);
+        }
+
+        // send poll message to clients
+        let poll_txt = messages::outbound::to_frame(&messages::outbound::Poll {
+            r#type: messages::outbound::Types::Poll,
+            object: poll_title.clone(),
+            state: poll_state,
+        });
+        self.send_message_all(&room_name, &poll_txt);
+
+        self.send_ack(&room_name, owner_id, &msg_id, Ok(()));
+    }
+}
+
+impl Handler<CreatePoll> for WebSocketServer {
+    type Result = Result<(), ErrorCode>;
+
+    fn handle(&mut self, msg: CreatePoll, _: &mut Context<Self>) -> Self::Result {
+        if !self.rooms.contains_key(&msg.room_name) {
+            return Err("room_does_not_exist");
+        }
+
+        let max_open_polls = self.config.max_open_polls;
+        let max_poll_options = self.config.max_poll_options;
+        let poll_cooldown = self.config.poll_cooldown;
+
+        let title = self.validate_title(&msg.title)?;
+        let mut option_titles: Vec<String> = Vec::new();
+        for option_title in &msg.options {
+            let option_title = self.validate_title(option_title)?;
+            if !option_titles.contains(&option_title) {
+                option_titles.push(option_title);
+            }
+        }
+
+        let room = match self.rooms.get_mut(&msg.room_name) {
+            None => return Err("room_does_not_exist"),
+            Some(room) => room,
+        };
+
+        if room.polls.iter().any(|poll| poll.title == title) {
+            return Err("poll_already_exists");
+        }
+
+        if let Some(max_open_polls) = max_open_polls {
+            if room.open_polls() >= max_open_polls {
+                return Err("too_many_polls");
+            }
+        }
+
+        if let Some(max_poll_options) = max_poll_options {
+            if option_titles.len() > max_poll_options {
+                return Err("too_many_options");
+            }
+        }
+
+        if room.in_poll_cooldown(poll_cooldown) {
+            return Err("poll_cooldown");
+        }
+
+        room.last_activity = std::time::Instant::now();
+        room.last_poll_created = Some(std::time::Instant::now());
+        room.polls.push(Poll {
+            title: title.clone(),
+            owner_id: SYSTEM_USER_ID,
+            owner_name: SYSTEM_USER_NAME.to_string(),
+            room_name: msg.room_name.clone(),
+            options: option_titles
+                .iter()
+                .enumerate()
+                .map(|(order, option_title)| PollOption {
+                    title: option_title.clone(),
+                    owner_id: SYSTEM_USER_ID,
+                    owner_name: SYSTEM_USER_NAME.to_string(),
+                    room_name: msg.room_name.clone(),
+                    poll_title: title.clone(),
+                    order,
+                    msg_id: None,
+                })
+                .collect(),
+            votes: HashMap::new(),
+            state: PollState::Open,
+            lock_after_first_vote_secs: None,
+            voting_locked: false,
+            created: std::time::Instant::now(),
+            msg_id: None,
+        });
+
+        for events in &self.events {
+            events.poll_created(&msg.room_name, &title);
+        }
+
+        // send poll and poll option messages to clients
+        let poll_txt = messages::outbound::to_frame(&messages::outbound::Poll {
+            r#type: messages::outbound::Types::Poll,
+            object: title.clone(),
+            state: PollState::Open,
+        });
+        self.send_message_all(&msg.room_name, &poll_txt);
+
+        for (order, option_title) in option_titles.into_iter().enumerate() {
+            let option_txt = messages::outbound::to_frame(&messages::outbound::PollOption {
+                r#type: messages::outbound::Types::PollOption,
+                pollobject: title.clone(),
+                polloptionobject: option_title,
+                order,
+            });
+            self.send_message_all(&msg.room_name, &option_txt);
+        }
+
+        Ok(())
+    }
+}
+
+impl Handler<PollOption> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, mut poll_option: PollOption, _: &mut Context<Self>) {
+        if self.refuse_non_member(
+            &poll_option.room_name,
+            poll_option.owner_id,
+            &poll_option.msg_id,
+        ) {
+            return;
+        }
+
+        self.touch_room(&poll_option.room_name);
+
+        // validate titles
+        let titles = self
+            .validate_title(&poll_option.poll_title)
+            .and_then(|poll_title| Ok((poll_title, self.validate_title(&poll_option.title)?)));
+        match titles {
+            Ok((poll_title, title)) => {
+                poll_option.poll_title = poll_title;
+                poll_option.title = title;
+            }
+            Err(error_code) => {
+                self.send_title_error_user(
+                    &poll_option.room_name,
+                    error_code,
+                    poll_option.owner_id,
+                );
+                self.send_ack(
+                    &poll_option.room_name,
+                    poll_option.owner_id,
+                    &poll_option.msg_id,
+                    Err(error_code),
+                );
+                println!("Refusing to add poll option, title is invalid");
+                return;
+            }
+        }
+
+        let max_poll_options = self.config.max_poll_options;
+
+        // get room
+        let room = self
+            .rooms
+            .entry(poll_option.room_name.clone())
+            .or_insert(Room::default());
+
+        // the session id identifies the user, the supplied name is not trusted
+        if !room.is_elevated(&poll_option.owner_id).unwrap_or(false) {
+            self.send_error_user(
+                &poll_option.room_name,
+                "no_permission",
+                "You do not have permission to add poll options (because you're not elevated)",
+                poll_option.owner_id,
+            );
+            self.send_ack(
+                &poll_option.room_name,
+                poll_option.owner_id,
+                &poll_option.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to add poll options (not elevated)");
+            return;
+        }
+
+        // check if poll exists
+        let mut poll_exists = room.polls.clone();
+        poll_exists.retain(|poll| poll.title == poll_option.poll_title);
+
+        if poll_exists.len() == 0 {
+            self.send_error_user(
+                &poll_option.room_name,
+                "poll_does_not_exist",
+                "A poll with that title doesn't exist",
+                poll_option.owner_id,
+            );
+            self.send_ack(
+                &poll_option.room_name,
+                poll_option.owner_id,
+                &poll_option.msg_id,
+                Err("poll_does_not_exist"),
+            );
+            println!("A poll with that title doesn't exist");
+            return;
+        }
+
+        // get poll
+        let poll_index = room
+            .polls
+            .iter()
+            .position(|poll| poll.title == poll_option.poll_title)
+            .unwrap();
+        let poll = room.polls.get_mut(poll_index).unwrap();
+
+        // check if poll is closed
+        if poll.state == PollState::Closed {
+            self.send_error_user(
+                &poll_option.room_name,
+                "poll_closed",
+                "Sorry, the poll is already closed",
+                poll_option.owner_id,
+            );
+            self.send_ack(
+                &poll_option.room_name,
+                poll_option.owner_id,
+                &poll_option.msg_id,
+                Err("poll_closed"),
+            );
+            println!("Poll is already closed");
+            return;
+        }
+
+        // check if poll has too many options
+        if let Some(max_poll_options) = max_poll_options {
+            if poll.options.len() >= max_poll_options {
+                self.send_error_user(
+                    &poll_option.room_name,
+                    "too_many_options",
+                    "Sorry, the poll has too many options",
+                    poll_option.owner_id,
+                );
+                self.send_ack(
+                    &poll_option.room_name,
+                    poll_option.owner_id,
+                    &poll_option.msg_id,
+                    Err("too_many_options"),
+                );
+                println!("Poll has too many options");
+                return;
+            }
+        }
+
+        // check if poll_option already exists
+        let mut poll_option_exists = poll.options.clone();
+        poll_option_exists
+            .retain(|existing_poll_option| existing_poll_option.title == poll_option.title);
+
+        if poll_option_exists.len() > 0 {
+            self.send_error_user(
+                &poll_option.room_name,
+                "poll_option_already_exists",
+                "A poll-option with that title in this poll does already exist",
+                poll_option.owner_id,
+            );
+            self.send_ack(
+                &poll_option.room_name,
+                poll_option.owner_id,
+                &poll_option.msg_id,
+                Err("poll_option_already_exists"),
+            );
+            println!("A poll-option with that title in this poll does already exist");
+            return;
+        }
+
+        // clone later needed values
+        let poll_option_title = poll_option.title.clone();
+        let room_name = poll_option.room_name.clone();
+        let owner_id = poll_option.owner_id;
+        let msg_id = poll_option.msg_id.take();
+
+        // add poll_option to poll, after all existing options
+        let order = poll.options.len();
+        poll_option.order = order;
+        poll.options.push(poll_option);
+
+        // send poll option message to clients
+        let txt = messages::outbound::to_frame(&messages::outbound::PollOption {
+            r#type: messages::outbound::Types::PollOption,
+            pollobject: poll.title.clone(),
+            polloptionobject: poll_option_title.clone(),
+            order,
+        });
+        self.send_message_all(&room_name, &txt);
+
+        self.send_ack(&room_name, owner_id, &msg_id, Ok(()));
+    }
+}
+
+impl Handler<PollVoteHelper> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, mut vote: PollVoteHelper, ctx: &mut Context<Self>) {
+        if self.refuse_non_member(&vote.room_name, vote.owner_id, &vote.msg_id) {
+            return;
+        }
+
+        self.touch_room(&vote.room_name);
+
+        // validate titles to match the validated poll and poll-option titles
+        let titles = self
+            .validate_title(&vote.poll_title)
+            .and_then(|poll_title| Ok((poll_title, self.validate_title(&vote.option_title)?)));
+        match titles {
+            Ok((poll_title, option_title)) => {
+                vote.poll_title = poll_title;
+                vote.option_title = option_title;
+            }
+            Err(error_code) => {
+                self.send_title_error_user(&vote.room_name, error_code, vote.owner_id);
+                self.send_ack(
+                    &vote.room_name,
+                    vote.owner_id,
+                    &vote.msg_id,
+                    Err(error_code),
+                );
+                println!("Refusing to vote, title is invalid");
+                return;
+            }
+        }
+
+        if self.refuse_spectator(&vote.room_name, vote.owner_id) {
+            self.send_ack(
+                &vote.room_name,
+                vote.owner_id,
+                &vote.msg_id,
+                Err("spectator_forbidden"),
+            );
+            return;
+        }
+
+        let max_votes_per_user = self.config.max_votes_per_user;
+        let room = self
+            .rooms
+            .entry(vote.room_name.clone())
+            .or_insert(Room::default());
+
+        // count the other open polls the user votes in
+        let other_votes = room
+            .polls
+            .iter()
+            .filter(|poll| {
+                poll.state != PollState::Closed
+                    && poll.title != vote.poll_title
+                    && poll.votes.contains_key(&vote.owner_id)
+            })
+            .count();
+
+        // check if poll exists
+        let mut poll_exists = room.polls.clone();
+        poll_exists.retain(|elem| &elem.title == &vote.poll_title);
+
+        if poll_exists.len() == 0 {
+            self.send_error_user(
+                &vote.room_name,
+                "poll_does_not_exist",
+                "A poll with that title doesn't exist",
+                vote.owner_id,
+            );
+            self.send_ack(
+                &vote.room_name,
+                vote.owner_id,
+                &vote.msg_id,
+                Err("poll_does_not_exist"),
+            );
+            println!("A poll with that title doesn't exist");
+            return;
+        }
+
+        // get poll
+        let poll_index = room
+            .polls
+            .iter()
+            .position(|poll| poll.title == vote.poll_title)
+            .unwrap();
+        let poll = room.polls.get_mut(poll_index).unwrap();
+
+        // check if poll is closed
+        if poll.state == PollState::Closed {
+            self.send_error_user(
+                &vote.room_name,
+                "poll_closed",
+                "Sorry, the poll is already closed",
+                vote.owner_id,
+            );
+            self.send_ack(
+                &vote.room_name,
+                vote.owner_id,
+                &vote.msg_id,
+                Err("poll_closed"),
+            );
+            println!("Poll is already closed!");
+            return;
+        }
+
+        // check if poll is still a draft
+        if poll.state == PollState::Draft {
+            self.send_error_user(
+                &vote.room_name,
+                "poll_draft",
+                "Sorry, the poll is not open for votes yet",
+                vote.owner_id,
+            );
+            self.send_ack(
+                &vote.room_name,
+                vote.owner_id,
+                &vote.msg_id,
+                Err("poll_draft"),
+            );
+            println!("Poll is not open yet!");
+            return;
+        }
+
+        // check if poll_option exists
+        let mut poll_option_exists = poll.options.clone();
+        poll_option_exists
+            .retain(|existing_poll_option| existing_poll_option.title == vote.option_title);
+
+        if poll_option_exists.len() == 0 {
+            self.send_error_user(
+                &vote.room_name,
+                "poll_option_does_not_exist",
+                "A poll-option with that title in this poll doesn't exist",
+                vote.owner_id,
+            );
+            self.send_ack(
+                &vote.room_name,
+                vote.owner_id,
+                &vote.msg_id,
+                Err("poll_option_does_not_exist"),
+            );
+            println!("Poll-Option with that title in this poll doesn't exist");
+            return;
+        }
+
+        // check if vote changes are locked
+        if poll.voting_locked && poll.votes.contains_key(&vote.owner_id) {
+            self.send_error_user(
+                &vote.room_name,
+                "voting_locked",
+                "Sorry, votes in this poll can't be changed anymore",
+                vote.owner_id,
+            );
+            self.send_ack(
+                &vote.room_name,
+                vote.owner_id,
+                &vote.msg_id,
+                Err("voting_locked"),
+            );
+            println!("Votes in this poll are locked!");
+            return;
+        }
+
+        // check if user votes in too many polls, changing a vote is always possible
+        if let Some(max_votes_per_user) = max_votes_per_user {
+            if !poll.votes.contains_key(&vote.owner_id) && other_votes >= max_votes_per_user {
+                self.send_error_user(
+                    &vote.room_name,
+                    "vote_limit_reached",
+                    "Sorry, you already vote in too many polls of this room",
+                    vote.owner_id,
+                );
+                self.send_ack(
+                    &vote.room_name,
+                    vote.owner_id,
+                    &vote.msg_id,
+                    Err("vote_limit_reached"),
+                );
+                println!("User votes in too many polls!");
+                return;
+            }
+        }
+
+        // start the lock window with the first vote
+        if poll.votes.is_empty() {
+            if let Some(lock_after_first_vote_secs) = poll.lock_after_first_vote_secs {
+                let room_name = vote.room_name.clone();
+                let poll_title = poll.title.clone();
+                ctx.run_later(
+                    Duration::from_secs(lock_after_first_vote_secs),
+                    move |act, _| {
+                        if let Some(room) = act.rooms.get_mut(&room_name) {
+                            for poll in room.polls.iter_mut() {
+                                if poll.title == poll_title {
+                                    poll.voting_locked = true;
+                                }
+                            }
+                        }
+                    },
+                );
+            }
+        }
+
+        // check if user has already voted
+        let mut remove_vote = false;
+        let mut remove_vote_option_title = "".to_string();
+
+        if poll.votes.contains_key(&vote.owner_id) {
+            println!(
+                "User has already votes in this poll, removing existing vote and adding new vote."
+            );
+
+            // send delete vote message to clients
+            for (userid, poll_option_title) in poll.votes.clone() {
+                if userid == vote.owner_id {
+                    remove_vote = true;
+                    remove_vote_option_title = poll_option_title.to_string().clone();
+                    break;
+                }
+            }
+
+            // remove existing vote
+            poll.votes.remove(&vote.owner_id);
+        }
+
+        // clone later needed values
+        let poll_option_title = vote.option_title.clone();
+        let poll_title = poll.title.clone();
+
+        // add vote to poll
+        poll.votes.insert(vote.owner_id, vote.option_title);
+
+        // inform other users if one vote has to be removed
+        if remove_vote {
+            let elevated_txt = Self::identified_vote_delete_frame(
+                &poll_title,
+                &remove_vote_option_title,
+                vote.owner_id,
+            );
+            let not_elevated_txt =
+                Self::anon_vote_delete_frame(&poll_title, &remove_vote_option_title);
+
+            self.send_message_all_elevated(&vote.room_name, &elevated_txt);
+            self.send_message_all_not_elevated(&vote.room_name, &not_elevated_txt);
+
+            // inform elevated users about the vote change in a single frame
+            let vote_changed_txt = messages::outbound::to_frame(&messages::outbound::VoteChanged {
+                r#type: messages::outbound::Types::VoteChanged,
+                pollobject: poll_title.clone(),
+                oldpolloptionobject: remove_vote_option_title.clone(),
+                newpolloptionobject: poll_option_title.clone(),
+                username: vote.owner_name.clone(),
+                userid: vote.owner_id,
+            });
+            self.send_message_all_elevated_with_capability(
+                &vote.room_name,
+                &vote_changed_txt,
+                messages::outbound::CAPABILITY_VOTE_CHANGED,
+            );
+        }
+
+        // send poll option message to clients
+        let elevated_txt = Self::identified_vote_frame(
+            &poll_title,
+            &poll_option_title,
+            &vote.owner_name,
+            vote.owner_id,
+        );
+        let not_elevated_txt = Self::anon_vote_frame(&poll_title, &poll_option_title);
+
+        self.send_message_all_elevated(&vote.room_name, &elevated_txt);
+        self.send_message_all_not_elevated(&vote.room_name, &not_elevated_txt);
+
+        // confirm the voter's current choice privately, the broadcast may be anonymous
+        let receipt_txt = messages::outbound::to_frame(&messages::outbound::VoteReceipt {
+            r#type: messages::outbound::Types::VoteReceipt,
+            pollobject: poll_title,
+            polloptionobject: Some(poll_option_title),
+        });
+        self.send_message_user(&vote.room_name, &receipt_txt, vote.owner_id);
+
+        self.send_ack(&vote.room_name, vote.owner_id, &vote.msg_id, Ok(()));
+    }
+}
+
+impl Handler<PollOptionRename> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, mut rename: PollOptionRename, _: &mut Context<Self>) {
+        if self.refuse_non_member(&rename.room_name, rename.owner_id, &rename.msg_id) {
+            return;
+        }
+
+        self.touch_room(&rename.room_name);
+
+        // validate titles
+        let titles = self
+            .validate_title(&rename.poll_title)
+            .and_then(|poll_title| {
+                Ok((
+                    poll_title,
+                    self.validate_title(&rename.old_title)?,
+                    self.validate_title(&rename.new_title)?,
+                ))
+            });
+        match titles {
+            Ok((poll_title, old_title, new_title)) => {
+                rename.poll_title = poll_title;
+                rename.old_title = old_title;
+                rename.new_title = new_title;
+            }
+            Err(error_code) => {
+                self.send_title_error_user(&rename.room_name, error_code, rename.owner_id);
+                self.send_ack(
+                    &rename.room_name,
+                    rename.owner_id,
+                    &rename.msg_id,
+                    Err(error_code),
+                );
+                println!("Refusing to rename poll option, title is invalid");
+                return;
+            }
+        }
+
+        let room = self
+            .rooms
+            .entry(rename.room_name.clone())
+            .or_insert(Room::default());
+
+        if !room.is_elevated(&rename.owner_id).unwrap_or(false) {
+            self.send_error_user(
+                &rename.room_name,
+                "no_permission",
+                "You do not have permission to rename poll options (because you're not elevated)",
+                rename.owner_id,
+            );
+            self.send_ack(
+                &rename.room_name,
+                rename.owner_id,
+                &rename.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to rename poll options (not elevated)");
+            return;
+        }
+
+        let result = match room
+            .polls
+            .iter_mut()
+            .find(|poll| poll.title == rename.poll_title)
+        {
+            None => Err("poll_does_not_exist"),
+            Some(poll) if poll.state == PollState::Closed => Err("poll_closed"),
+            Some(poll)
+                if !poll
+                    .options
+                    .iter()
+                    .any(|option| option.title == rename.old_title) =>
+            {
+                Err("poll_option_does_not_exist")
+            }
+            Some(poll)
+                if poll
+                    .options
+                    .iter()
+                    .any(|option| option.title == rename.new_title) =>
+            {
+                Err("poll_option_already_exists")
+            }
+            Some(poll) => {
+                for option in poll.options.iter_mut() {
+                    if option.title == rename.old_title {
+                        option.title = rename.new_title.clone();
+                    }
+                }
+
+                // keep the votes of the renamed option
+                for option_title in poll.votes.values_mut() {
+                    if *option_title == rename.old_title {
+                        *option_title = rename.new_title.clone();
+                    }
+                }
+                Ok(())
+            }
+        };
+
+        if let Err(error_code) = result {
+            let error_description = match error_code {
+                "poll_does_not_exist" => "A poll with that title doesn't exist",
+                "poll_closed" => "Sorry, the poll is already closed",
+                "poll_option_does_not_exist" => {
+                    "A poll-option with that title in this poll doesn't exist"
+                }
+                _ => "A poll-option with that title in this poll does already exist",
+            };
+            self.send_error_user(
+                &rename.room_name,
+                error_code,
+                error_description,
+                rename.owner_id,
+            );
+            self.send_ack(
+                &rename.room_name,
+                rename.owner_id,
+                &rename.msg_id,
+                Err(error_code),
+            );
+            println!("Refusing to rename poll option: {}", error_code);
+            return;
+        }
+
+        let txt = messages::outbound::to_frame(&messages::outbound::PollOptionRenamed {
+            r#type: messages::outbound::Types::PollOptionRenamed,
+            pollobject: rename.poll_title,
+            oldpolloptionobject: rename.old_title,
+            newpolloptionobject: rename.new_title,
+        });
+        self.send_message_all(&rename.room_name, &txt);
+
+        self.send_ack(&rename.room_name, rename.owner_id, &rename.msg_id, Ok(()));
+    }
+}
+
+impl Handler<PollOpen> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, mut open: PollOpen, _: &mut Context<Self>) {
+        if self.refuse_non_member(&open.room_name, open.sender_id, &open.msg_id) {
+            return;
+        }
+
+        self.touch_room(&open.room_name);
+
+        // validate title to match the validated poll titles
+        open.poll_title = match self.validate_title(&open.poll_title) {
+            Ok(poll_title) => poll_title,
+            Err(error_code) => {
+                self.send_title_error_user(&open.room_name, error_code, open.sender_id);
+                self.send_ack(
+                    &open.room_name,
+                    open.sender_id,
+                    &open.msg_id,
+                    Err(error_code),
+                );
+                println!("Refusing to open poll, title is invalid");
+                return;
+            }
+        };
+
+        let room = self
+            .rooms
+            .entry(open.room_name.clone())
+            .or_insert(Room::default());
+
+        if !room.is_elevated(&open.sender_id).unwrap_or(false) {
+            self.send_error_user(
+                &open.room_name,
+                "no_permission",
+                "You do not have permission to open polls (because you're not elevated)",
+                open.sender_id,
+            );
+            self.send_ack(
+                &open.room_name,
+                open.sender_id,
+                &open.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to open polls (not elevated)");
+            return;
+        }
+
+        let error_code = match room
+            .polls
+            .iter_mut()
+            .find(|poll| poll.title == open.poll_title)
+        {
+            None => Some("poll_does_not_exist"),
+            Some(poll) if poll.state != PollState::Draft => Some("poll_not_draft"),
+            Some(poll) => {
+                poll.state = PollState::Open;
+                None
+            }
+        };
+
+        if let Some(error_code) = error_code {
+            self.send_error_user(
+                &open.room_name,
+                error_code,
+                "Sorry, there is no draft poll with that title",
+                open.sender_id,
+            );
+            self.send_ack(
+                &open.room_name,
+                open.sender_id,
+                &open.msg_id,
+                Err(error_code),
+            );
+            println!("Refusing to open poll: {}", error_code);
+            return;
+        }
+
+        let txt = messages::outbound::to_frame(&messages::outbound::PollClose {
+            r#type: messages::outbound::Types::PollOpen,
+            object: open.poll_title,
+        });
+        self.send_message_all(&open.room_name, &txt);
+
+        self.send_ack(&open.room_name, open.sender_id, &open.msg_id, Ok(()));
+    }
+}
+
+impl Handler<PollCloseHelper> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, mut close: PollCloseHelper, _: &mut Context<Self>) {
+        if self.refuse_non_member(&close.room_name, close.sender_id, &close.msg_id) {
+            return;
+        }
+
+        self.touch_room(&close.room_name);
+
+        // validate title to match the validated poll titles
+        close.poll_title = match self.validate_title(&close.poll_title) {
+            Ok(poll_title) => poll_title,
+            Err(error_code) => {
+                self.send_title_error_user(&close.room_name, error_code, close.sender_id);
+                self.send_ack(
+                    &close.room_name,
+                    close.sender_id,
+                    &close.msg_id,
+                    Err(error_code),
+                );
+                println!("Refusing to close poll, title is invalid");
+                return;
+            }
+        };
+
+        // get room
+        let room = self
+            .rooms
+            .entry(close.room_name.clone())
+            .or_insert(Room::default());
+
+        if !room.is_elevated(&close.sender_id).unwrap_or(false) {
+            self.send_error_user(
+                &close.room_name,
+                "no_permission",
+                "You do not have permission to close polls (because you're not elevated)",
+                close.sender_id,
+            );
+            self.send_ack(
+                &close.room_name,
+                close.sender_id,
+                &close.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to close polls (not elevated)");
+            return;
+        }
+
+        // check if poll exists
+        let mut poll_exists = room.polls.clone();
+        poll_exists.retain(|elem| &elem.title == &close.poll_title);
+
+        if poll_exists.len() == 0 {
+            self.send_error_user(
+                &close.room_name,
+                "poll_does_not_exist",
+                "A poll with that title doesn't exist",
+                close.sender_id,
+            );
+            self.send_ack(
+                &close.room_name,
+                close.sender_id,
+                &close.msg_id,
+                Err("poll_does_not_exist"),
+            );
+            println!("Poll with that title doesn't exist!");
+            return;
+        }
+
+        // get poll
+        let poll_index = room
+            .polls
+            .iter()
+            .position(|poll| poll.title == close.poll_title)
+            .unwrap();
+        let poll = room.polls.get_mut(poll_index).unwrap();
+
+        // check if poll is closed
+        if poll.state == PollState::Closed {
+            self.send_error_user(
+                &close.room_name,
+                "poll_closed",
+                "Sorry, the poll is already closed",
+                close.sender_id,
+            );
+            self.send_ack(
+                &close.room_name,
+                close.sender_id,
+                &close.msg_id,
+                Err("poll_closed"),
+            );
+            println!("Poll is already closed!");
+            return;
+        }
+
+        self.close_poll(&close.room_name, poll_index);
+        self.send_ack(&close.room_name, close.sender_id, &close.msg_id, Ok(()));
+    }
+}
+
+impl Handler<ClearRaised> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ClearRaised, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let room = match self.rooms.get_mut(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.is_elevated(&msg.owner_id).unwrap_or(false) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to clear raised objects (because you're not elevated)",
+                msg.owner_id,
+            );
+            self.send_ack(
+                &msg.room_name,
+                msg.owner_id,
+                &msg.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to clear raised objects (not elevated)");
+            return;
+        }
+
+        room.raised.clear();
+
+        // a single message instead of a `lower` per object, clients drop all raised objects
+        let txt = messages::outbound::to_frame(&messages::outbound::RaisedCleared {
+            r#type: messages::outbound::Types::RaisedCleared,
+            object: msg.owner_id,
+        });
+        self.send_message_all(&msg.room_name, &txt);
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Ok(()));
+    }
+}
+
+impl Handler<GetRoomExport> for WebSocketServer {
+    type Result = Result<Arbitrary, ErrorCode>;
+
+    fn handle(&mut self, msg: GetRoomExport, _: &mut Context<Self>) -> Self::Result {
+        let room = match self.rooms.get(&msg.room_name) {
+            None => return Err("room_does_not_exist"),
+            Some(room) => room,
+        };
+
+        // tallies only, the votes map holds the identities
+        let polls: Vec<Arbitrary> = room
+            .polls
+            .iter()
+            .map(|poll| {
+                let mut results = Self::poll_results(&msg.room_name, room, poll);
+                results["state"] = json!(poll.state);
+                results
+            })
+            .collect();
+
+        Ok(json!({
+            "room": msg.room_name,
+            "polls": polls,
+        }))
+    }
+}
+
+impl Handler<PollStatus> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: PollStatus, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.sender_id, &msg.msg_id) {
+            return;
+        }
+
+        let room = match self.rooms.get(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.is_elevated(&msg.sender_id).unwrap_or(false) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to see poll results (because you're not elevated)",
+                msg.sender_id,
+            );
+            self.send_ack(
+                &msg.room_name,
+                msg.sender_id,
+                &msg.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to see poll results (not elevated)");
+            return;
+        }
+
+        let status = match room.polls.iter().find(|poll| poll.title == msg.poll_title) {
+            None => Err("poll_does_not_exist"),
+            Some(poll) if poll.state == PollState::Closed => Err("poll_closed"),
+            Some(poll) => {
+                let mut results = Self::poll_results(&msg.room_name, room, poll);
+                results["state"] = json!(poll.state);
+                Ok(results)
+            }
+        };
+
+        match status {
+            Ok(results) => {
+                let txt = messages::outbound::to_frame(&messages::outbound::PollStatus {
+                    r#type: messages::outbound::Types::PollStatus,
+                    object: results,
+                });
+                self.send_message_user(&msg.room_name, &txt, msg.sender_id);
+                self.send_ack(&msg.room_name, msg.sender_id, &msg.msg_id, Ok(()));
+            }
+            Err(error_code) => {
+                self.send_error_user(
+                    &msg.room_name,
+                    error_code,
+                    "Sorry, there is no open poll with that title",
+                    msg.sender_id,
+                );
+                self.send_ack(&msg.room_name, msg.sender_id, &msg.msg_id, Err(error_code));
+            }
+        }
+    }
+}
+
+impl Handler<CloseAllPolls> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseAllPolls, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let room = match self.rooms.get(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.is_elevated(&msg.owner_id).unwrap_or(false) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to close polls (because you're not elevated)",
+                msg.owner_id,
+            );
+            self.send_ack(
+                &msg.room_name,
+                msg.owner_id,
+                &msg.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to close all polls (not elevated)");
+            return;
+        }
+
+        let poll_indices: Vec<usize> = room
+            .polls
+            .iter()
+            .enumerate()
+            .filter(|(_, poll)| poll.state != PollState::Closed)
+            .map(|(poll_index, _)| poll_index)
+            .collect();
+
+        // publish the results of every poll, the meeting is over
+        for poll_index in poll_indices {
+            if let Some(results) = self.close_poll(&msg.room_name, poll_index) {
+                let txt = messages::outbound::to_frame(&messages::outbound::PollResults {
+                    r#type: messages::outbound::Types::PollResults,
+                    object: results,
+                });
+                self.send_message_all(&msg.room_name, &txt);
+            }
+        }
+
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Ok(()));
+    }
+}
+
+impl WebSocketServer {
+    /// Close a poll and inform the room's users and the event sinks
+    ///
+    /// Returns the final results of the poll, `None` if the room or poll does not exist.
+    ///
+    /// # Arguments
+    /// * `room_name` - The room of the poll
+    /// * `poll_index` - The index of the poll within the room's polls
+    fn close_poll(&mut self, room_name: &str, poll_index: usize) -> Option<Arbitrary> {
+        let room = self.rooms.get_mut(room_name)?;
+        room.polls.get_mut(poll_index)?.state = PollState::Closed;
+        let poll = &room.polls[poll_index];
+        let results = Self::poll_results(room_name, room, poll);
+        let poll_title = poll.title.clone();
+
+        let txt = messages::outbound::to_frame(&messages::outbound::PollClose {
+            r#type: messages::outbound::Types::PollClose,
+            object: poll_title.clone(),
+        });
+        self.send_message_all(room_name, &txt);
+
+        for events in &self.events {
+            events.poll_closed(room_name, &poll_title);
+            events.poll_results(room_name, &results);
+        }
+
+        Some(results)
+    }
+}
+
+impl WebSocketServer {
+    /// Handles managing priligiges on request
+    ///
+    /// # Arguments
+    /// * `room_name` - The room in which the user's priviliges should be changed
+    /// * `requested_id` - The user who requests the change. Elevated priviliges needed.
+    /// * `user_id` - The user whose priviliges should be changed.
+    /// * `elevated` - If the user should have elevated priviliges or not.
+    ///
+    /// Fails with `no_permission` if the requester is not elevated, `user_does_not_exist` if the
+    /// user is not in the room and `already_in_state` if the user already has the priviliges.
+    fn process_priviliges(
+        &mut self,
+        room_name: &String,
+        requester_id: usize,
+        user_id: usize,
+        elevated: bool,
+    ) -> Result<(), ErrorCode> {
+        let room = self.rooms.get_mut(room_name).ok_or("no_permission")?;
+        if !room.is_elevated(&requester_id).unwrap_or(false) {
+            return Err("no_permission");
+        }
+        match room.is_elevated(&user_id) {
+            Err(_) => return Err("user_does_not_exist"),
+            Ok(is_elevated) if is_elevated == elevated => return Err("already_in_state"),
+            Ok(_) => (),
+        }
+
+        room.set_elevated(&user_id, elevated);
+
+        // elevations requested by a receded user aren't backed by an elevated user anymore
+        if !elevated {
+            room.pending_elevations
+                .retain(|_, requester_id| *requester_id != user_id);
+        }
+
+        // resend votes (with user_id and user_name) for open polls
+        let room_imut = room.clone();
+        for poll in room_imut.polls.clone() {
+            if poll.state != PollState::Closed {
+                // send votes for poll
+                for (userid, option_title) in poll.votes.clone() {
+                    let user = match room_imut.connected.get(&userid) {
+                        Some(user) => user,
+                        None => continue,
+                    };
+
+                    if elevated {
+                        let del_vote_txt = Self::anon_vote_delete_frame(&poll.title, &option_title);
+                        self.send_message_user(&room_name, &del_vote_txt, user_id);
+
+                        let vote_txt = Self::identified_vote_frame(
+                            &poll.title,
+                            &option_title,
+                            &user.name,
+                            userid,
+                        );
+                        self.send_message_user(&room_name, &vote_txt, user_id);
+                    } else {
+                        let del_vote_txt =
+                            Self::identified_vote_delete_frame(&poll.title, &option_title, userid);
+                        self.send_message_user(&room_name, &del_vote_txt, user_id);
+
+                        let vote_txt = Self::anon_vote_frame(&poll.title, &option_title);
+                        self.send_message_user(&room_name, &vote_txt, user_id);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// send the error for a change rejected by `process_priviliges()` to the requester
+    ///
+    /// # Arguments
+    /// * `room_name` - The room in which the user's priviliges should have been changed
+    /// * `error_code` - The error code returned by `process_priviliges()`
+    /// * `requester_id` - The user who requested the change
+    fn send_privilege_error_user(
+        &self,
+        room_name: &str,
+        error_code: ErrorCode,
+        requester_id: usize,
+    ) {
+        let error_description = match error_code {
+            "no_permission" => {
+                "You do not have permission to change priviliges (because you're not elevated)"
+            }
+            "user_does_not_exist" => "A user with that id doesn't exist in this room",
+            "no_pending_elevation" => "There is no elevation of that user to confirm",
+            "same_moderator" => "The elevation has to be confirmed by another elevated user",
+            _ => "The user already has these priviliges",
+        };
+        self.send_error_user(room_name, error_code, error_description, requester_id);
+        println!("Refusing to change priviliges: {}", error_code);
+    }
+}
+
+impl WebSocketServer {
+    /// Record an elevation waiting for the confirmation of a second elevated user
+    ///
+    /// The other elevated users are notified. Returns if the elevation is pending, the only
+    /// elevated user of a room has nobody to confirm it and elevates right away.
+    ///
+    /// # Arguments
+    /// * `room_name` - The room in which the user should be elevated
+    /// * `requester_id` - The user who requests the elevation. Elevated priviliges needed.
+    /// * `user_id` - The user who should be elevated
+    fn request_elevation(
+        &mut self,
+        room_name: &str,
+        requester_id: usize,
+        user_id: usize,
+    ) -> Result<bool, ErrorCode> {
+        let room = self.rooms.get_mut(room_name).ok_or("no_permission")?;
+        if !room.is_elevated(&requester_id).unwrap_or(false) {
+            return Err("no_permission");
+        }
+        match room.is_elevated(&user_id) {
+            Err(_) => return Err("user_does_not_exist"),
+            Ok(true) => return Err("already_in_state"),
+            Ok(false) => (),
+        }
+
+        let moderators = room.connected.values().filter(|user| user.elevated).count();
+        if moderators <= 1 {
+            return Ok(false);
+        }
+
+        room.pending_elevations.insert(user_id, requester_id);
+        let txt = messages::outbound::to_frame(&messages::outbound::ElevationPending {
+            r#type: messages::outbound::Types::ElevationPending,
+            object: user_id,
+            requester: requester_id,
+        });
+        self.send_message_all_elevated(room_name, &txt);
+        Ok(true)
+    }
+}
+
+impl Handler<Elevate> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Elevate, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        if self.config.confirm_elevation {
+            match self.request_elevation(&msg.room_name, msg.owner_id, msg.object) {
+                Err(error_code) => {
+                    self.send_privilege_error_user(&msg.room_name, error_code, msg.owner_id);
+                    self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Err(error_code));
+                    return;
+                }
+                Ok(true) => {
+                    self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Ok(()));
+                    return;
+                }
+                Ok(false) => (),
+            }
+        }
+
+        let result = self.process_priviliges(&msg.room_name, msg.owner_id, msg.object, true);
+        match result {
+            Err(error_code) => {
+                self.send_privilege_error_user(&msg.room_name, error_code, msg.owner_id)
+            }
+            Ok(_) => {
+                let txt = messages::outbound::to_frame(&messages::outbound::PermissionChange {
+                    r#type: messages::outbound::Types::Elevated,
+                    object: msg.object,
+                });
+                self.send_message_all(&msg.room_name, &txt);
+            }
+        }
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, result);
+    }
+}
+
+impl Handler<ConfirmElevate> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ConfirmElevate, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let pending = match self.rooms.get(&msg.room_name) {
+            None => return,
+            Some(room) if !room.is_elevated(&msg.owner_id).unwrap_or(false) => Err("no_permission"),
+            Some(room) => match room.pending_elevations.get(&msg.object) {
+                None => Err("no_pending_elevation"),
+                Some(requester_id) if *requester_id == msg.owner_id => Err("same_moderator"),
+                Some(_) => Ok(()),
+            },
+        };
+
+        let result = pending.and_then(|_| {
+            if let Some(room) = self.rooms.get_mut(&msg.room_name) {
+                room.pending_elevations.remove(&msg.object);
+            }
+            self.process_priviliges(&msg.room_name, msg.owner_id, msg.object, true)
+        });
+
+        match result {
+            Err(error_code) => {
+                self.send_privilege_error_user(&msg.room_name, error_code, msg.owner_id)
+            }
+            Ok(_) => {
+                let txt = messages::outbound::to_frame(&messages::outbound::PermissionChange {
+                    r#type: messages::outbound::Types::Elevated,
+                    object: msg.object,
+                });
+                self.send_message_all(&msg.room_name, &txt);
+            }
+        }
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, result);
+    }
+}
+
+impl Handler<Recede> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Recede, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let result = self.process_priviliges(&msg.room_name, msg.owner_id, msg.object, false);
+        match result {
+            Err(error_code) => {
+                self.send_privilege_error_user(&msg.room_name, error_code, msg.owner_id)
+            }
+            Ok(_) => {
+                let txt = messages::outbound::to_frame(&messages::outbound::PermissionChange {
+                    r#type: messages::outbound::Types::Receded,
+                    object: msg.object,
+                });
+                self.send_message_all(&msg.room_name, &txt);
+            }
+        }
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, result);
+    }
+}
+
+impl Handler<TransferElevation> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: TransferElevation, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let room = self.rooms.get(&msg.room_name).unwrap();
+
+        if !room.is_elevated(&msg.owner_id).unwrap_or(false) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to transfer elevation (because you're not elevated)",
+                msg.owner_id,
+            );
+            self.send_ack(
+                &msg.room_name,
+                msg.owner_id,
+                &msg.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to transfer elevation (not elevated)");
+            return;
+        }
+
+        match room.is_elevated(&msg.object) {
+            Err(_) => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "user_does_not_exist",
+                    "A user with that id doesn't exist in this room",
+                    msg.owner_id,
+                );
+                self.send_ack(
+                    &msg.room_name,
+                    msg.owner_id,
+                    &msg.msg_id,
+                    Err("user_does_not_exist"),
+                );
+                println!("User to transfer elevation to doesn't exist");
+                return;
+            }
+            Ok(true) => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "already_elevated",
+                    "Can't transfer elevation, the user is already elevated",
+                    msg.owner_id,
+                );
+                self.send_ack(
+                    &msg.room_name,
+                    msg.owner_id,
+                    &msg.msg_id,
+                    Err("already_elevated"),
+                );
+                println!("User to transfer elevation to is already elevated");
+                return;
+            }
+            Ok(false) => (),
+        }
+
+        // elevate first, so the room is never left without an elevated user
+        let _ = self.process_priviliges(&msg.room_name, msg.owner_id, msg.object, true);
+        let _ = self.process_priviliges(&msg.room_name, msg.owner_id, msg.owner_id, false);
+
+        let elevated_txt = messages::outbound::to_frame(&messages::outbound::PermissionChange {
+            r#type: messages::outbound::Types::Elevated,
+            object: msg.object,
+        });
+        self.send_message_all(&msg.room_name, &elevated_txt);
+
+        let receded_txt = messages::outbound::to_frame(&messages::outbound::PermissionChange {
+            r#type: messages::outbound::Types::Receded,
+            object: msg.owner_id,
+        });
+        self.send_message_all(&msg.room_name, &receded_txt);
+
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Ok(()));
+    }
+}
+
+impl WebSocketServer {
+    /// Resolves a user's name to the user's ID within a room
+    ///
+    /// # Arguments
+    /// * `room_name` - The room to search the user in
+    /// * `user_name` - The name of the user
+    fn resolve_user_name(&self, room_name: &str, user_name: &str) -> Result<usize, ErrorCode> {
+        let room = self.rooms.get(room_name).ok_or("user_does_not_exist")?;
+        let mut user_ids = room
+            .connected
+            .iter()
+            .filter(|(_, user)| user.name == user_name)
+            .map(|(id, _)| *id);
+
+        match (user_ids.next(), user_ids.next()) {
+            (Some(user_id), None) => Ok(user_id),
+            (Some(_), Some(_)) => Err("ambiguous_name"),
+            (None, _) => Err("user_does_not_exist"),
+        }
+    }
+}
+
+impl Handler<ElevateByName> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ElevateByName, ctx: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        match self.resolve_user_name(&msg.room_name, &msg.object) {
+            Err(error_code) => {
+                self.send_error_user(
+                    &msg.room_name,
+                    error_code,
+                    "Can't elevate, no single user with that name in this room",
+                    msg.owner_id,
+                );
+                self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Err(error_code));
+                println!("Can't elevate by name: {}", error_code);
+            }
+            Ok(user_id) => Handler::<Elevate>::handle(
+                self,
+                Elevate {
+                    object: user_id,
+                    owner_id: msg.owner_id,
+                    room_name: msg.room_name,
+                    msg_id: msg.msg_id,
+                },
+                ctx,
+            ),
+        }
+    }
+}
+
+impl Handler<RecedeByName> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RecedeByName, ctx: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        match self.resolve_user_name(&msg.room_name, &msg.object) {
+            Err(error_code) => {
+                self.send_error_user(
+                    &msg.room_name,
+                    error_code,
+                    "Can't recede, no single user with that name in this room",
+                    msg.owner_id,
+                );
+                self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Err(error_code));
+                println!("Can't recede by name: {}", error_code);
+            }
+            Ok(user_id) => Handler::<Recede>::handle(
+                self,
+                Recede {
+                    object: user_id,
+                    owner_id: msg.owner_id,
+                    room_name: msg.room_name,
+                    msg_id: msg.msg_id,
+                },
+                ctx,
+            ),
+        }
+    }
+}
+
+impl Handler<ListPolls> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ListPolls, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.user_id, &None) {
+            return;
+        }
+
+        let replay = match self.rooms.get(&msg.room_name) {
+            Some(room) => Self::poll_replay(room),
+            None => return,
+        };
+
+        self.send_messages_user(&msg.room_name, replay, msg.user_id);
+    }
+}
+
+impl Handler<MyRaised> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: MyRaised, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.user_id, &None) {
+            return;
+        }
+
+        let raised = match self.rooms.get(&msg.room_name) {
+            Some(room) => room
+                .raised_snapshot()
+                .into_iter()
+                .filter(|raised| raised.owner_id == msg.user_id)
+                .collect(),
+            None => return,
+        };
+
+        let txt = messages::outbound::to_frame(&messages::outbound::MyRaised {
+            r#type: messages::outbound::Types::MyRaised,
+            object: raised,
+        });
+        self.send_message_user(&msg.room_name, &txt, msg.user_id);
+    }
+}
+
+impl Handler<Roster> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Roster, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.user_id, &None) {
+            return;
+        }
+
+        let room = match self.rooms.get(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.is_elevated(&msg.user_id).unwrap_or(false) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to see the roster (because you're not elevated)",
+                msg.user_id,
+            );
+            println!("User does not have permission to see the roster (not elevated)");
+            return;
+        }
+
+        // unlike `all`, never redacted by hidden participants
+        let txt = messages::outbound::to_frame(&messages::outbound::Roster {
+            r#type: messages::outbound::Types::Roster,
+            object: room.connected.clone(),
+        });
+        self.send_message_user(&msg.room_name, &txt, msg.user_id);
+    }
+}
+
+impl Handler<RaisedCount> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: RaisedCount, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.user_id, &None) {
+            return;
+        }
+
+        let room = match self.rooms.get(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        // count per object in the order the objects have been raised first
+        let mut objects: Vec<messages::outbound::ObjectCount> = Vec::new();
+        for raised in &room.raised {
+            match objects
+                .iter_mut()
+                .find(|counted| counted.object == raised.object)
+            {
+                Some(counted) => counted.count += 1,
+                None => objects.push(messages::outbound::ObjectCount {
+                    object: raised.object.clone(),
+                    count: 1,
+                }),
+            }
+        }
+
+        let txt = messages::outbound::to_frame(&messages::outbound::RaisedCount {
+            r#type: messages::outbound::Types::RaisedCount,
+            object: room.raised.len(),
+            objects,
+        });
+        self.send_message_user(&msg.room_name, &txt, msg.user_id);
+    }
+}
+
+impl Handler<MaintenanceNotice> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: MaintenanceNotice, _: &mut Context<Self>) {
+        let txt = messages::outbound::to_frame(&messages::outbound::MaintenanceNotice {
+            r#type: messages::outbound::Types::MaintenanceNotice,
+            object: msg.enabled,
+        });
+        let room_names: Vec<String> = self.rooms.keys().cloned().collect();
+        for room_name in room_names {
+            self.send_message_all(&room_name, &txt);
+        }
+    }
+}
+
+impl Handler<EndRoom> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: EndRoom, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        let is_elevated = self
+            .rooms
+            .get(&msg.room_name)
+            .and_then(|room| room.is_elevated(&msg.owner_id).ok())
+            .unwrap_or(false);
+
+        if !is_elevated {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to end the room (because you're not elevated)",
+                msg.owner_id,
+            );
+            self.send_ack(
+                &msg.room_name,
+                msg.owner_id,
+                &msg.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to end the room (not elevated)");
+            return;
+        }
+
+        // acknowledge first, the room is gone afterwards
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Ok(()));
+        self.remove_room(&msg.room_name);
+        println!("Room '{}' ended by user {}", msg.room_name, msg.owner_id);
+    }
+}
+
+impl Handler<Kick> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Kick, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let ban_duration = self.config.ban_duration;
+        let room = self
+            .rooms
+            .entry(msg.room_name.clone())
+            .or_insert(Room::default());
+
+        if !room.is_elevated(&msg.owner_id).unwrap_or(false) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to kick users (because you're not elevated)",
+                msg.owner_id,
+            );
+            self.send_ack(
+                &msg.room_name,
+                msg.owner_id,
+                &msg.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to kick users (not elevated)");
+            return;
+        }
+
+        let user_name = match room.connected.get(&msg.object) {
+            None => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "user_does_not_exist",
+                    "A user with that id doesn't exist in this room",
+                    msg.owner_id,
+                );
+                self.send_ack(
+                    &msg.room_name,
+                    msg.owner_id,
+                    &msg.msg_id,
+                    Err("user_does_not_exist"),
+                );
+                println!("User to kick doesn't exist");
+                return;
+            }
+            Some(user) => user.name.clone(),
+        };
+
+        if msg.ban {
+            room.banned
+                .insert(user_name, std::time::Instant::now() + ban_duration);
+        }
+
+        self.send_error_user(
+            &msg.room_name,
+            "kicked",
+            "You have been kicked from this room",
+            msg.object,
+        );
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Ok(()));
+
+        // stopping the session leads to a regular `Disconnect`
+        if let Some(stop) = self.stops.get(&msg.object) {
+            let _ = stop.do_send(Stop(StopReason::Kicked));
+        }
+    }
+}
+
+impl Handler<Unban> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unban, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let room = self
+            .rooms
+            .entry(msg.room_name.clone())
+            .or_insert(Room::default());
+
+        if !room.is_elevated(&msg.owner_id).unwrap_or(false) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to unban users (because you're not elevated)",
+                msg.owner_id,
+            );
+            self.send_ack(
+                &msg.room_name,
+                msg.owner_id,
+                &msg.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to unban users (not elevated)");
+            return;
+        }
+
+        if room.banned.remove(&msg.object).is_none() {
+            self.send_error_user(
+                &msg.room_name,
+                "not_banned",
+                "A user with that name isn't banned",
+                msg.owner_id,
+            );
+            self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Err("not_banned"));
+            println!("Refusing to unban, is not banned");
+            return;
+        }
+
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Ok(()));
+    }
+}
+
+impl WebSocketServer {
+    /// Handles muting and unmuting users on request
+    ///
+    /// # Arguments
+    /// * `room_name` - The room in which the user should be (un)muted
+    /// * `requester_id` - The user who requests the change. Elevated priviliges needed.
+    /// * `user_id` - The user who should be (un)muted.
+    /// * `muted` - If the user should be muted or not.
+    fn process_mute(
+        &mut self,
+        room_name: &String,
+        requester_id: usize,
+        user_id: usize,
+        muted: bool,
+    ) -> Result<(), ErrorCode> {
+        let room = self.rooms.get_mut(room_name).ok_or("no_permission")?;
+        if !room.is_elevated(&requester_id).unwrap_or(false) {
+            return Err("no_permission");
+        }
+        match room.connected.get_mut(&user_id) {
+            None => Err("user_does_not_exist"),
+            Some(user) => {
+                user.muted = muted;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Handler<Mute> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Mute, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let result = self.process_mute(&msg.room_name, msg.owner_id, msg.object, true);
+        if result.is_ok() {
+            let txt = messages::outbound::to_frame(&messages::outbound::PermissionChange {
+                r#type: messages::outbound::Types::Muted,
+                object: msg.object,
+            });
+            self.send_message_all(&msg.room_name, &txt);
+        }
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, result);
+    }
+}
+
+impl Handler<Unmute> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unmute, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let result = self.process_mute(&msg.room_name, msg.owner_id, msg.object, false);
+        if result.is_ok() {
+            let txt = messages::outbound::to_frame(&messages::outbound::PermissionChange {
+                r#type: messages::outbound::Types::Unmuted,
+                object: msg.object,
+            });
+            self.send_message_all(&msg.room_name, &txt);
+        }
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, result);
+    }
+}
+
+impl Handler<SetSpectator> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetSpectator, _: &mut Context<Self>) {
+        if self.refuse_non_member(&msg.room_name, msg.owner_id, &msg.msg_id) {
+            return;
+        }
+
+        self.touch_room(&msg.room_name);
+
+        let room = self.rooms.get_mut(&msg.room_name).unwrap();
+
+        if !room.is_elevated(&msg.owner_id).unwrap_or(false) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to change spectators (because you're not elevated)",
+                msg.owner_id,
+            );
+            self.send_ack(
+                &msg.room_name,
+                msg.owner_id,
+                &msg.msg_id,
+                Err("no_permission"),
+            );
+            println!("User does not have permission to change spectators (not elevated)");
+            return;
+        }
+
+        match room.connected.get_mut(&msg.object) {
+            None => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "user_does_not_exist",
+                    "A user with that id doesn't exist in this room",
+                    msg.owner_id,
+                );
+                self.send_ack(
+                    &msg.room_name,
+                    msg.owner_id,
+                    &msg.msg_id,
+                    Err("user_does_not_exist"),
+                );
+                println!("User to change spectator role of doesn't exist");
+                return;
+            }
+            Some(user) => user.spectator = msg.spectator,
+        }
+
+        let txt = messages::outbound::to_frame(&messages::outbound::PermissionChange {
+            r#type: if msg.spectator {
+                messages::outbound::Types::Spectator
+            } else {
+                messages::outbound::Types::Participant
+            },
+            object: msg.object,
+        });
+        self.send_message_all(&msg.room_name, &txt);
+
+        self.send_ack(&msg.room_name, msg.owner_id, &msg.msg_id, Ok(()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Stand-in for a `WsWebSocketSession`, recording every message the server sends it
+    struct MockSession {
+        id: usize,
+        server: Addr<WebSocketServer>,
+        frames: Arc<Mutex<Vec<String>>>,
+        stopped: Arc<Mutex<bool>>,
+    }
+
+    impl Actor for MockSession {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<Message> for MockSession {
+        type Result = ();
+
+        fn handle(&mut self, msg: Message, _: &mut Context<Self>) {
+            self.frames.lock().unwrap().push(msg.0.to_string());
+        }
+    }
+
+    /// Like a real session, a stopped mock session disconnects from the server once
+    impl Handler<Stop> for MockSession {
+        type Result = ();
+
+        fn handle(&mut self, _: Stop, _: &mut Context<Self>) {
+            let mut stopped = self.stopped.lock().unwrap();
+            if !*stopped {
+                *stopped = true;
+                self.server.do_send(Disconnect { id: self.id });
+            }
+        }
+    }
+
+    /// Stops the session like a failed heartbeat, without the server asking for it
+    #[derive(Message)]
+    #[rtype(result = "()")]
+    struct TimeOut;
+
+    impl Handler<TimeOut> for MockSession {
+        type Result = ();
+
+        fn handle(&mut self, _: TimeOut, ctx: &mut Context<Self>) {
+            Handler::<Stop>::handle(self, Stop(StopReason::Timeout), ctx);
+        }
+    }
+
+    /// Does nothing, answering it proves every earlier message has been handled
+    #[derive(Message)]
+    #[rtype(result = "()")]
+    struct Flush;
+
+    impl Handler<Flush> for MockSession {
+        type Result = ();
+
+        fn handle(&mut self, _: Flush, _: &mut Context<Self>) {}
+    }
+
+    /// A mock session connected to a `WebSocketServer`
+    struct MockClient {
+        id: usize,
+        name: String,
+        server: Addr<WebSocketServer>,
+        addr: Addr<MockSession>,
+        frames: Arc<Mutex<Vec<String>>>,
+        stopped: Arc<Mutex<bool>>,
+    }
+
+    impl MockClient {
+        /// All messages received so far, once the server and the session handled everything
+        async fn frames(&self) -> Vec<Arbitrary> {
+            self.flush().await;
+            self.frames
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|frame| serde_json::from_str(frame).unwrap())
+                .collect()
+        }
+
+        /// All messages of a type received so far
+        async fn frames_of_type(&self, r#type: &str) -> Vec<Arbitrary> {
+            self.frames()
+                .await
+                .into_iter()
+                .filter(|frame| frame["type"] == r#type)
+                .collect()
+        }
+
+        /// All messages of a type received so far, exactly as sent
+        async fn texts_of_type(&self, r#type: &str) -> Vec<String> {
+            self.flush().await;
+            self.frames
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|frame| serde_json::from_str::<Arbitrary>(frame).unwrap()["type"] == r#type)
+                .cloned()
+                .collect()
+        }
+
+        /// All error codes received so far
+        async fn errors(&self) -> Vec<String> {
+            self.frames_of_type("error")
+                .await
+                .iter()
+                .map(|error| error["object"].as_str().unwrap().to_string())
+                .collect()
+        }
+
+        /// Forget all messages received so far
+        async fn clear(&self) {
+            self.flush().await;
+            self.frames.lock().unwrap().clear();
+        }
+
+        /// If the server stopped the session
+        async fn is_stopped(&self) -> bool {
+            self.flush().await;
+            *self.stopped.lock().unwrap()
+        }
+
+        /// Wait until the server and the session handled all messages sent so far
+        async fn flush(&self) {
+            // the server sends to sessions while handling, so flush it first
+            self.server.send(Flush).await.unwrap();
+            self.addr.send(Flush).await.unwrap();
+        }
+    }
+
+    impl Handler<Flush> for WebSocketServer {
+        type Result = ();
+
+        fn handle(&mut self, _: Flush, _: &mut Context<Self>) {}
+    }
+
+    /// Close idle rooms now instead of waiting for the sweep interval
+    #[derive(Message)]
+    #[rtype(result = "()")]
+    struct SweepIdleRooms;
+
+    impl Handler<SweepIdleRooms> for WebSocketServer {
+        type Result = ();
+
+        fn handle(&mut self, _: SweepIdleRooms, _: &mut Context<Self>) {
+            self.close_idle_rooms();
+        }
+    }
+
+    /// Start a `WebSocketServer`
+    fn start_server(config: Config) -> Addr<WebSocketServer> {
+        WebSocketServer::new(config).start()
+    }
+
+    /// Connect a mock session to the server and join a room
+    async fn join(
+        server: &Addr<WebSocketServer>,
+        room_name: &str,
+        user_id: usize,
+        user_name: &str,
+    ) -> MockClient {
+        join_with(server, room_name, user_id, user_name, &[], false).await
+    }
+
+    /// Connect a mock session with capabilities, as participant or spectator, and join a room
+    async fn join_with(
+        server: &Addr<WebSocketServer>,
+        room_name: &str,
+        user_id: usize,
+        user_name: &str,
+        capabilities: &[&str],
+        spectator: bool,
+    ) -> MockClient {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let stopped = Arc::new(Mutex::new(false));
+        let addr = MockSession {
+            id: user_id,
+            server: server.clone(),
+            frames: frames.clone(),
+            stopped: stopped.clone(),
+        }
+        .start();
+
+        server
+            .send(Join {
+                addr: addr.clone().recipient(),
+                stop: addr.clone().recipient(),
+                user_id,
+                user_name: user_name.to_string(),
+                room_name: room_name.to_string(),
+                capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+                spectator,
+                admin: false,
+            })
+            .await
+            .unwrap();
+
+        MockClient {
+            id: user_id,
+            name: user_name.to_string(),
+            server: server.clone(),
+            addr,
+            frames,
+            stopped,
+        }
+    }
+
+    /// Build a `Raise` of a user
+    fn raise(client: &MockClient, room_name: &str, object: Arbitrary) -> Raise {
+        Raise {
+            object,
+            owner_id: client.id,
+            owner_name: client.name.clone(),
+            room_name: room_name.to_string(),
+            msg_id: None,
+        }
+    }
+
+    /// Build an open `Poll` of a user
+    fn poll(client: &MockClient, room_name: &str, title: &str) -> Poll {
+        Poll {
+            title: title.to_string(),
+            owner_id: client.id,
+            owner_name: client.name.clone(),
+            room_name: room_name.to_string(),
+            options: Vec::new(),
+            votes: HashMap::new(),
+            state: PollState::Open,
+            lock_after_first_vote_secs: None,
+            voting_locked: false,
+            created: std::time::Instant::now(),
+            msg_id: None,
+        }
+    }
+
+    /// Build a `PollOption` of a user
+    fn poll_option(
+        client: &MockClient,
+        room_name: &str,
+        poll_title: &str,
+        title: &str,
+    ) -> PollOption {
+        PollOption {
+            title: title.to_string(),
+            owner_id: client.id,
+            owner_name: client.name.clone(),
+            room_name: room_name.to_string(),
+            poll_title: poll_title.to_string(),
+            order: 0,
+            msg_id: None,
+        }
+    }
+
+    /// Build a vote of a user
+    fn vote(
+        client: &MockClient,
+        room_name: &str,
+        poll_title: &str,
+        option_title: &str,
+    ) -> PollVoteHelper {
+        PollVoteHelper {
+            owner_id: client.id,
+            owner_name: client.name.clone(),
+            room_name: room_name.to_string(),
+            poll_title: poll_title.to_string(),
+            option_title: option_title.to_string(),
+            msg_id: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn raise_is_broadcasted_to_the_room() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join(&server, "other", 3, "carol").await;
+
+        server
+            .send(raise(&bob, "room", json!("hand")))
+            .await
+            .unwrap();
+
+        for client in &[&alice, &bob] {
+            let raised = client.frames_of_type("raised").await;
+            assert_eq!(raised.len(), 1);
+            assert_eq!(raised[0]["object"], "hand");
+            assert_eq!(raised[0]["owner_id"], 2);
+            assert_eq!(raised[0]["room"], "room");
+        }
+        assert!(carol.frames_of_type("raised").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn session_receives_events_of_all_joined_rooms() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "a", 1, "alice").await;
+        let bob = join(&server, "b", 2, "bob").await;
+        server
+            .send(JoinRoom {
+                user_id: alice.id,
+                user_name: alice.name.clone(),
+                room_name: "b".to_string(),
+                spectator: false,
+            })
+            .await
+            .unwrap();
+
+        server
+            .send(raise(&alice, "a", json!("first")))
+            .await
+            .unwrap();
+        server
+            .send(raise(&bob, "b", json!("second")))
+            .await
+            .unwrap();
+
+        let raised = alice.frames_of_type("raised").await;
+        assert_eq!(raised.len(), 2);
+        assert_eq!(raised[0]["room"], "a");
+        assert_eq!(raised[0]["object"], "first");
+        assert_eq!(raised[1]["room"], "b");
+        assert_eq!(raised[1]["object"], "second");
+        assert_eq!(bob.frames_of_type("raised").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn non_member_cannot_act_in_a_foreign_room() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "a", 1, "alice").await;
+        let mallory = join(&server, "b", 2, "mallory").await;
+        server.send(poll(&alice, "a", "lunch")).await.unwrap();
+        server
+            .send(poll_option(&alice, "a", "lunch", "pizza"))
+            .await
+            .unwrap();
+
+        let mut foreign_vote = vote(&mallory, "a", "lunch", "pizza");
+        foreign_vote.msg_id = Some(json!(7));
+        server.send(foreign_vote).await.unwrap();
+
+        assert_eq!(mallory.errors().await, vec!["not_in_room"]);
+        let acks = mallory.frames_of_type("ack").await;
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks[0]["object"], 7);
+        assert_eq!(acks[0]["success"], false);
+        assert!(alice.frames_of_type("vote").await.is_empty());
+
+        // elevating in the room must not trip over a foreign vote
+        let bob = join(&server, "a", 3, "bob").await;
+        server
+            .send(Elevate {
+                object: bob.id,
+                owner_id: alice.id,
+                room_name: "a".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob.frames_of_type("elevated").await.len(), 1);
+    }
+
+    /// Build an `Elevate` of a user by another user
+    fn elevate(requester: &MockClient, room_name: &str, user: &MockClient) -> Elevate {
+        Elevate {
+            object: user.id,
+            owner_id: requester.id,
+            room_name: room_name.to_string(),
+            msg_id: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn elevating_an_elevated_user_reports_already_in_state() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server.send(elevate(&alice, "room", &bob)).await.unwrap();
+        alice.clear().await;
+
+        server.send(elevate(&alice, "room", &bob)).await.unwrap();
+
+        assert_eq!(alice.errors().await, vec!["already_in_state"]);
+        assert!(alice.frames_of_type("elevated").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn elevating_without_elevation_reports_no_permission() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join(&server, "room", 3, "carol").await;
+
+        server.send(elevate(&bob, "room", &carol)).await.unwrap();
+
+        assert_eq!(bob.errors().await, vec!["no_permission"]);
+        assert!(alice.frames_of_type("elevated").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn votes_are_limited_per_user() {
+        let mut config = Config::default();
+        config.max_votes_per_user = Some(2);
+        let server = start_server(config);
+        let alice = join(&server, "room", 1, "alice").await;
+        for title in &["one", "two", "three"] {
+            server.send(poll(&alice, "room", title)).await.unwrap();
+            server
+                .send(poll_option(&alice, "room", title, "yes"))
+                .await
+                .unwrap();
+            server
+                .send(poll_option(&alice, "room", title, "no"))
+                .await
+                .unwrap();
+        }
+
+        server
+            .send(vote(&alice, "room", "one", "yes"))
+            .await
+            .unwrap();
+        server
+            .send(vote(&alice, "room", "two", "yes"))
+            .await
+            .unwrap();
+        assert!(alice.errors().await.is_empty());
+
+        server
+            .send(vote(&alice, "room", "three", "yes"))
+            .await
+            .unwrap();
+        assert_eq!(alice.errors().await, vec!["vote_limit_reached"]);
+
+        // changing an existing vote is not limited
+        server
+            .send(vote(&alice, "room", "two", "no"))
+            .await
+            .unwrap();
+        assert_eq!(alice.errors().await, vec!["vote_limit_reached"]);
+    }
+
+    #[actix_rt::test]
+    async fn admin_observer_sees_identified_votes_but_is_no_user() {
+        let server = start_server(Config::default());
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let stopped = Arc::new(Mutex::new(false));
+        let admin_addr = MockSession {
+            id: 100,
+            server: server.clone(),
+            frames: frames.clone(),
+            stopped: stopped.clone(),
+        }
+        .start();
+        server
+            .send(Join {
+                addr: admin_addr.clone().recipient(),
+                stop: admin_addr.clone().recipient(),
+                user_id: 100,
+                user_name: "admin".to_string(),
+                room_name: "room".to_string(),
+                capabilities: HashSet::new(),
+                spectator: true,
+                admin: true,
+            })
+            .await
+            .unwrap();
+        let admin = MockClient {
+            id: 100,
+            name: "admin".to_string(),
+            server: server.clone(),
+            addr: admin_addr,
+            frames,
+            stopped,
+        };
+
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        server
+            .send(poll_option(&alice, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+        server
+            .send(vote(&bob, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+
+        let votes = admin.frames_of_type("vote").await;
+        assert_eq!(votes.len(), 1);
+        assert_eq!(votes[0]["username"], "bob");
+        assert_eq!(votes[0]["userid"], 2);
+
+        // the first user joining is alone in the room
+        let all = alice.frames_of_type("all").await;
+        assert_eq!(all[0]["joined"].as_object().unwrap().len(), 1);
+        assert!(all[0]["joined"].get("100").is_none());
+
+        // observers can't take part
+        server
+            .send(raise(&admin, "room", json!("hand")))
+            .await
+            .unwrap();
+        assert_eq!(admin.errors().await, vec!["not_in_room"]);
+    }
+
+    #[actix_rt::test]
+    async fn objects_with_reserved_keys_are_rejected() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+
+        let crafted = json!({"topic": {"type": "kick", "object": 2}});
+        server.send(raise(&alice, "room", crafted)).await.unwrap();
+        assert_eq!(alice.errors().await, vec!["invalid_object"]);
+        assert!(bob.frames_of_type("raise").await.is_empty());
+
+        server
+            .send(raise(&alice, "room", json!({"topic": "lunch"})))
+            .await
+            .unwrap();
+        assert_eq!(bob.frames_of_type("raise").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn renamed_user_keeps_poll_permissions() {
+        let server = start_server(Config::default());
+        let mut alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+
+        server
+            .send(RenameUser {
+                user_id: alice.id,
+                user_name: "alicia".to_string(),
+            })
+            .await
+            .unwrap();
+        alice.name = "alicia".to_string();
+
+        let renamed = bob.frames_of_type("userrenamed").await;
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0]["object"], 1);
+        assert_eq!(renamed[0]["oldname"], "alice");
+        assert_eq!(renamed[0]["newname"], "alicia");
+
+        let carol = join(&server, "room", 3, "carol").await;
+        let all = carol.frames_of_type("all").await;
+        assert_eq!(all[0]["joined"]["1"]["name"], "alicia");
+
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        assert!(alice.errors().await.is_empty());
+        assert_eq!(bob.frames_of_type("poll").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn poll_permissions_ignore_the_supplied_name() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+
+        let mut created = poll(&alice, "room", "lunch");
+        created.owner_name = "someone else".to_string();
+        server.send(created).await.unwrap();
+        let mut option = poll_option(&alice, "room", "lunch", "pizza");
+        option.owner_name = "someone else".to_string();
+        server.send(option).await.unwrap();
+
+        assert!(alice.errors().await.is_empty());
+        assert_eq!(bob.frames_of_type("polloption").await.len(), 1);
+
+        // bob's id is not elevated, not even with the elevated user's name
+        let mut spoofed = poll(&bob, "room", "dinner");
+        spoofed.owner_name = "alice".to_string();
+        server.send(spoofed).await.unwrap();
+        assert_eq!(bob.errors().await, vec!["no_permission"]);
+    }
+
+    #[actix_rt::test]
+    async fn delta_clients_get_no_snapshots_on_membership_changes() {
+        let server = start_server(Config::default());
+        let alice = join_with(&server, "room", 1, "alice", &["delta"], false).await;
+        let legacy = join(&server, "room", 2, "bob").await;
+        alice.clear().await;
+        legacy.clear().await;
+
+        let carol = join(&server, "room", 3, "carol").await;
+        server.send(Disconnect { id: carol.id }).await.unwrap();
+
+        assert_eq!(alice.frames_of_type("user").await.len(), 1);
+        assert_eq!(alice.frames_of_type("userleft").await.len(), 1);
+        assert!(alice.frames_of_type("all").await.is_empty());
+
+        assert_eq!(legacy.frames_of_type("userleft").await.len(), 1);
+        assert_eq!(legacy.frames_of_type("all").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn open_polls_are_limited_per_room() {
+        let server = start_server(Config {
+            max_open_polls: Some(2),
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+
+        server.send(poll(&alice, "room", "first")).await.unwrap();
+        server.send(poll(&alice, "room", "second")).await.unwrap();
+        assert!(alice.errors().await.is_empty());
+
+        server.send(poll(&alice, "room", "third")).await.unwrap();
+        assert_eq!(alice.errors().await, vec!["too_many_polls"]);
+        assert_eq!(alice.frames_of_type("poll").await.len(), 2);
+
+        // a new user gets only the open polls replayed
+        let bob = join(&server, "room", 2, "bob").await;
+        assert_eq!(bob.frames_of_type("poll").await.len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn any_object_but_null_can_be_raised() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+
+        server.send(raise(&alice, "room", json!(42))).await.unwrap();
+        server
+            .send(raise(
+                &alice,
+                "room",
+                json!({"topic": "lunch", "votes": [1, 2]}),
+            ))
+            .await
+            .unwrap();
+        assert!(alice.errors().await.is_empty());
+
+        let raised = bob.frames_of_type("raised").await;
+        assert_eq!(raised.len(), 2);
+        assert_eq!(raised[0]["object"], json!(42));
+        assert_eq!(raised[1]["object"]["votes"], json!([1, 2]));
+
+        server
+            .send(raise(&alice, "room", Arbitrary::Null))
+            .await
+            .unwrap();
+        assert_eq!(alice.errors().await, vec!["missing_object"]);
+        assert_eq!(bob.frames_of_type("raised").await.len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn lowering_an_unraised_object_lists_the_raised_ones() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+
+        server
+            .send(raise(&alice, "room", json!("hand")))
+            .await
+            .unwrap();
+        server.send(raise(&alice, "room", json!(42))).await.unwrap();
+        server
+            .send(raise(&bob, "room", json!("coffee")))
+            .await
+            .unwrap();
+
+        server
+            .send(Lower {
+                object: json!("hnad"),
+                owner_id: alice.id,
+                owner_name: alice.name.clone(),
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+
+        let errors = alice.frames_of_type("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "not_raised");
+        assert_eq!(errors[0]["raised"], json!(["hand", 42]));
+    }
+
+    #[actix_rt::test]
+    async fn admin_names_are_elevated_regardless_of_join_order() {
+        let mut config = Config::default();
+        config.admin_names.insert("carol".to_string());
+        let server = start_server(config);
+        let alice = join(&server, "room", 1, "alice").await;
+        join(&server, "room", 2, "bob").await;
+        join(&server, "room", 3, "carol").await;
+
+        let joined = alice.frames_of_type("user").await;
+        assert_eq!(joined.len(), 2);
+        assert_eq!(joined[0]["object"]["elevated"], false);
+        assert_eq!(joined[1]["object"]["name"], "carol");
+        assert_eq!(joined[1]["object"]["elevated"], true);
+    }
+
+    #[actix_rt::test]
+    async fn broadcasts_reach_a_user_with_id_zero() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let zero = join(&server, "room", 0, "zero").await;
+
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+
+        assert_eq!(zero.frames_of_type("poll").await.len(), 1);
+        assert_eq!(alice.frames_of_type("poll").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn poll_options_replay_in_creation_order() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        for title in &["pizza", "curry", "salad"] {
+            server
+                .send(poll_option(&alice, "room", "lunch", title))
+                .await
+                .unwrap();
+        }
+
+        let bob = join(&server, "room", 2, "bob").await;
+        let options = bob.frames_of_type("polloption").await;
+        let replayed: Vec<(&str, u64)> = options
+            .iter()
+            .map(|option| {
+                (
+                    option["polloptionobject"].as_str().unwrap(),
+                    option["order"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(replayed, vec![("pizza", 0), ("curry", 1), ("salad", 2)]);
+    }
+
+    #[actix_rt::test]
+    async fn closing_all_polls_publishes_their_results() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        for title in &["lunch", "dinner"] {
+            server.send(poll(&alice, "room", title)).await.unwrap();
+            server
+                .send(poll_option(&alice, "room", title, "pizza"))
+                .await
+                .unwrap();
+        }
+        server
+            .send(vote(&bob, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+
+        let close_all = CloseAllPolls {
+            owner_id: bob.id,
+            room_name: "room".to_string(),
+            msg_id: None,
+        };
+        server.send(close_all.clone()).await.unwrap();
+        assert_eq!(bob.errors().await, vec!["no_permission"]);
+
+        server
+            .send(CloseAllPolls {
+                owner_id: alice.id,
+                ..close_all
+            })
+            .await
+            .unwrap();
+
+        let closed: Vec<Arbitrary> = bob
+            .frames_of_type("pollclose")
+            .await
+            .iter()
+            .map(|close| close["object"].clone())
+            .collect();
+        assert_eq!(closed, vec![json!("lunch"), json!("dinner")]);
+
+        let results = bob.frames_of_type("pollresults").await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["object"]["pollobject"], "lunch");
+        assert_eq!(results[0]["object"]["votes"], 1);
+        assert_eq!(results[1]["object"]["pollobject"], "dinner");
+        assert_eq!(results[1]["object"]["votes"], 0);
+    }
+
+    #[actix_rt::test]
+    async fn room_export_contains_tallies_but_no_voters() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        server
+            .send(poll_option(&alice, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+        server
+            .send(poll_option(&alice, "room", "lunch", "curry"))
+            .await
+            .unwrap();
+        server
+            .send(vote(&alice, "room", "lunch", "curry"))
+            .await
+            .unwrap();
+        server
+            .send(vote(&bob, "room", "lunch", "curry"))
+            .await
+            .unwrap();
+        server.send(poll(&alice, "room", "dinner")).await.unwrap();
+
+        let export = server
+            .send(GetRoomExport {
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(export["room"], "room");
+        let polls = export["polls"].as_array().unwrap();
+        assert_eq!(polls.len(), 2);
+        assert_eq!(polls[0]["pollobject"], "lunch");
+        assert_eq!(polls[0]["state"], "open");
+        assert_eq!(polls[0]["votes"], 2);
+        assert_eq!(polls[0]["options"][1]["polloptionobject"], "curry");
+        assert_eq!(polls[0]["options"][1]["votes"], 2);
+        assert_eq!(polls[1]["pollobject"], "dinner");
+        assert!(!export.to_string().contains("alice"));
+        assert!(!export.to_string().contains("bob"));
+
+        let missing = server
+            .send(GetRoomExport {
+                room_name: "elsewhere".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(missing, Err("room_does_not_exist"));
+    }
+
+    #[actix_rt::test]
+    async fn messages_for_a_foreign_room_are_rejected() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "a", 1, "alice").await;
+        let mallory = join(&server, "b", 2, "mallory").await;
+        server.send(poll(&alice, "a", "lunch")).await.unwrap();
+
+        server
+            .send(raise(&mallory, "a", json!("hand")))
+            .await
+            .unwrap();
+        server.send(poll(&mallory, "a", "dinner")).await.unwrap();
+        server
+            .send(ListPolls {
+                user_id: mallory.id,
+                room_name: "a".to_string(),
+            })
+            .await
+            .unwrap();
+        server
+            .send(ClientMessage {
+                id: mallory.id,
+                msg: "{\"type\": \"instant\"}".to_string(),
+                room: "a".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(mallory.errors().await, vec!["not_in_room"; 4]);
+        assert!(mallory.frames_of_type("poll").await.is_empty());
+        assert!(alice.frames_of_type("raised").await.is_empty());
+        assert!(alice.frames_of_type("instant").await.is_empty());
+        assert_eq!(alice.frames_of_type("poll").await.len(), 1);
+
+        // a message for an unknown room does not create it
+        server
+            .send(raise(&mallory, "c", json!("hand")))
+            .await
+            .unwrap();
+        let export = server
+            .send(GetRoomExport {
+                room_name: "c".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(export, Err("room_does_not_exist"));
+    }
+
+    #[actix_rt::test]
+    async fn hidden_participants_are_only_listed_to_elevated_users() {
+        let server = start_server(Config {
+            hide_participants: true,
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join(&server, "room", 3, "carol").await;
+
+        let all = carol.frames_of_type("all").await;
+        let mut joined: Vec<&String> = all[0]["joined"].as_object().unwrap().keys().collect();
+        joined.sort();
+        assert_eq!(joined, vec!["1", "3"]);
+        assert!(bob.frames_of_type("user").await.is_empty());
+
+        let joins = alice.frames_of_type("user").await;
+        assert_eq!(joins.len(), 2);
+
+        server.send(Disconnect { id: carol.id }).await.unwrap();
+        let all = bob.frames_of_type("all").await;
+        let joined = all.last().unwrap()["joined"].as_object().unwrap();
+        assert_eq!(joined.len(), 2);
+        assert!(joined.get("1").is_some() && joined.get("2").is_some());
+        let all = alice.frames_of_type("all").await;
+        assert_eq!(all.last().unwrap()["joined"].as_object().unwrap().len(), 2);
+        assert!(bob.frames_of_type("userleft").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn snapshots_are_sent_periodically() {
+        let server = start_server(Config {
+            snapshot_interval: Some(Duration::from_millis(20)),
+            ..Config::default()
+        });
+        let alice = join_with(&server, "room", 1, "alice", &["delta"], false).await;
+        server
+            .send(raise(&alice, "room", json!("hand")))
+            .await
+            .unwrap();
+        alice.clear().await;
+
+        actix_rt::time::delay_for(Duration::from_millis(110)).await;
+
+        let snapshots = alice.frames_of_type("all").await;
+        assert!(snapshots.len() >= 2);
+        assert_eq!(snapshots[0]["raised"][0]["object"], "hand");
+    }
+
+    #[actix_rt::test]
+    async fn clearing_raised_objects_lowers_everything() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(raise(&alice, "room", json!("hand")))
+            .await
+            .unwrap();
+        server
+            .send(raise(&bob, "room", json!("hand")))
+            .await
+            .unwrap();
+        server.send(raise(&bob, "room", json!(42))).await.unwrap();
+
+        let clear = ClearRaised {
+            owner_id: bob.id,
+            room_name: "room".to_string(),
+            msg_id: None,
+        };
+        server.send(clear.clone()).await.unwrap();
+        assert_eq!(bob.errors().await, vec!["no_permission"]);
+
+        server
+            .send(ClearRaised {
+                owner_id: alice.id,
+                ..clear
+            })
+            .await
+            .unwrap();
+        let cleared = bob.frames_of_type("raisedcleared").await;
+        assert_eq!(cleared.len(), 1);
+        assert_eq!(cleared[0]["object"], 1);
+
+        let carol = join(&server, "room", 3, "carol").await;
+        let all = carol.frames_of_type("all").await;
+        assert_eq!(all[0]["raised"], json!([]));
+    }
+
+    #[actix_rt::test]
+    async fn users_can_list_their_own_raised_objects() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(raise(&alice, "room", json!("hand")))
+            .await
+            .unwrap();
+        server
+            .send(raise(&bob, "room", json!("coffee")))
+            .await
+            .unwrap();
+        server.send(raise(&alice, "room", json!(42))).await.unwrap();
+
+        server
+            .send(MyRaised {
+                user_id: alice.id,
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let listed = alice.frames_of_type("myraised").await;
+        assert_eq!(listed.len(), 1);
+        let objects: Vec<Arbitrary> = listed[0]["object"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|raised| raised["object"].clone())
+            .collect();
+        assert_eq!(objects, vec![json!("hand"), json!(42)]);
+        assert!(bob.frames_of_type("myraised").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn poll_status_reports_the_tally_of_an_open_poll() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join(&server, "room", 3, "carol").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        for option in &["pizza", "pasta"] {
+            server
+                .send(poll_option(&alice, "room", "lunch", option))
+                .await
+                .unwrap();
+        }
+        server
+            .send(vote(&bob, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+        server
+            .send(vote(&carol, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+
+        let status = PollStatus {
+            sender_id: bob.id,
+            room_name: "room".to_string(),
+            poll_title: "lunch".to_string(),
+            msg_id: None,
+        };
+        server.send(status.clone()).await.unwrap();
+        assert_eq!(bob.errors().await, vec!["no_permission"]);
+        assert!(bob.frames_of_type("pollstatus").await.is_empty());
+
+        server
+            .send(PollStatus {
+                sender_id: alice.id,
+                ..status
+            })
+            .await
+            .unwrap();
+
+        let status = alice.frames_of_type("pollstatus").await;
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0]["object"]["pollobject"], "lunch");
+        assert_eq!(status[0]["object"]["state"], "open");
+        assert_eq!(status[0]["object"]["votes"], 2);
+        assert_eq!(
+            status[0]["object"]["options"],
+            json!([
+                {"polloptionobject": "pizza", "votes": 2},
+                {"polloptionobject": "pasta", "votes": 0},
+            ])
+        );
+        assert!(carol.frames_of_type("pollstatus").await.is_empty());
+
+        // the poll is still open, votes keep counting
+        server
+            .send(vote(&alice, "room", "lunch", "pasta"))
+            .await
+            .unwrap();
+        assert!(alice.errors().await.is_empty());
+        assert!(alice.frames_of_type("pollclose").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn poll_results_report_every_winner_of_a_tie() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join(&server, "room", 3, "carol").await;
+        let dave = join(&server, "room", 4, "dave").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        for option in &["salad", "pizza", "pasta"] {
+            server
+                .send(poll_option(&alice, "room", "lunch", option))
+                .await
+                .unwrap();
+        }
+        for (client, option) in &[(&bob, "pasta"), (&carol, "pizza"), (&dave, "pasta")] {
+            server
+                .send(vote(client, "room", "lunch", option))
+                .await
+                .unwrap();
+        }
+        server
+            .send(vote(&alice, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+
+        server
+            .send(CloseAllPolls {
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+
+        let results = bob.frames_of_type("pollresults").await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["object"]["winners"], json!(["pizza", "pasta"]));
+        assert_eq!(
+            results[0]["object"]["ranking"],
+            json!(["pizza", "pasta", "salad"])
+        );
+        // every client gets the same order
+        assert_eq!(
+            alice.frames_of_type("pollresults").await[0]["object"]["ranking"],
+            results[0]["object"]["ranking"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn poll_results_without_votes_have_no_winners() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        server
+            .send(poll_option(&alice, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+
+        server
+            .send(CloseAllPolls {
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+
+        let results = alice.frames_of_type("pollresults").await;
+        assert_eq!(results[0]["object"]["winners"], json!([]));
+        assert_eq!(results[0]["object"]["ranking"], json!(["pizza"]));
+    }
+
+    #[actix_rt::test]
+    async fn joining_twice_with_the_same_id_replaces_the_earlier_session() {
+        let server = start_server(Config::default());
+        let stale = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let alice = join(&server, "room", 1, "alice").await;
+
+        assert!(stale.is_stopped().await);
+        assert_eq!(stale.errors().await, vec!["replaced"]);
+        assert!(!alice.is_stopped().await);
+        assert!(alice.errors().await.is_empty());
+
+        // the disconnect of the stale session leaves the new one connected
+        stale.clear().await;
+        server
+            .send(raise(&bob, "room", json!("hand")))
+            .await
+            .unwrap();
+        assert!(!alice.frames_of_type("raised").await.is_empty());
+        assert!(stale.frames().await.is_empty());
+
+        let carol = join(&server, "room", 3, "carol").await;
+        let all = carol.frames_of_type("all").await;
+        let joined = all.last().unwrap()["joined"].as_object().unwrap();
+        let mut ids: Vec<&String> = joined.keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec!["1", "2", "3"]);
+    }
+
+    #[actix_rt::test]
+    async fn polls_created_within_the_cooldown_are_refused() {
+        let server = start_server(Config {
+            poll_cooldown: Some(Duration::from_millis(100)),
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+
+        server.send(poll(&alice, "room", "first")).await.unwrap();
+        server.send(poll(&alice, "room", "second")).await.unwrap();
+        assert_eq!(alice.errors().await, vec!["poll_cooldown"]);
+        assert_eq!(alice.frames_of_type("poll").await.len(), 1);
+
+        actix_rt::time::delay_for(Duration::from_millis(150)).await;
+        alice.clear().await;
+        server.send(poll(&alice, "room", "second")).await.unwrap();
+        assert!(alice.errors().await.is_empty());
+        assert_eq!(alice.frames_of_type("poll").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn polls_of_a_leaving_owner_are_closed_if_configured() {
+        for close_polls_on_owner_leave in &[false, true] {
+            let server = start_server(Config {
+                close_polls_on_owner_leave: *close_polls_on_owner_leave,
+                ..Config::default()
+            });
+            let alice = join(&server, "room", 1, "alice").await;
+            let bob = join(&server, "room", 2, "bob").await;
+            server.send(elevate(&alice, "room", &bob)).await.unwrap();
+            server.send(poll(&alice, "room", "lunch")).await.unwrap();
+            server.send(poll(&bob, "room", "dinner")).await.unwrap();
+
+            server.send(Disconnect { id: alice.id }).await.unwrap();
+
+            let closed: Vec<Arbitrary> = bob
+                .frames_of_type("pollclose")
+                .await
+                .iter()
+                .map(|close| close["object"].clone())
+                .collect();
+            if *close_polls_on_owner_leave {
+                assert_eq!(closed, vec![json!("lunch")]);
+            } else {
+                assert!(closed.is_empty());
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn raised_count_summarizes_the_raised_objects() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(raise(&alice, "room", json!("hand")))
+            .await
+            .unwrap();
+        server
+            .send(raise(&bob, "room", json!("hand")))
+            .await
+            .unwrap();
+        server
+            .send(raise(&bob, "room", json!("coffee")))
+            .await
+            .unwrap();
+        server.send(raise(&alice, "room", json!(42))).await.unwrap();
+        server
+            .send(Lower {
+                object: json!(42),
+                owner_id: alice.id,
+                owner_name: alice.name.clone(),
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+
+        server
+            .send(RaisedCount {
+                user_id: bob.id,
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let counts = bob.frames_of_type("raisedcount").await;
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0]["object"], 3);
+        assert_eq!(
+            counts[0]["objects"],
+            json!([
+                {"object": "hand", "count": 2},
+                {"object": "coffee", "count": 1},
+            ])
+        );
+        assert!(alice.frames_of_type("raisedcount").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn elevations_are_confirmed_by_a_second_elevated_user() {
+        let server = start_server(Config {
+            confirm_elevation: true,
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join(&server, "room", 3, "carol").await;
+
+        // the only elevated user has nobody to confirm
+        server.send(elevate(&alice, "room", &bob)).await.unwrap();
+        assert_eq!(carol.frames_of_type("elevated").await.len(), 1);
+        carol.clear().await;
+
+        server.send(elevate(&alice, "room", &carol)).await.unwrap();
+        for moderator in &[&alice, &bob] {
+            let pending = moderator.frames_of_type("elevationpending").await;
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0]["object"], carol.id);
+            assert_eq!(pending[0]["requester"], alice.id);
+        }
+        assert!(carol.frames_of_type("elevationpending").await.is_empty());
+        assert!(carol.frames_of_type("elevated").await.is_empty());
+
+        let confirm = ConfirmElevate {
+            object: carol.id,
+            owner_id: alice.id,
+            room_name: "room".to_string(),
+            msg_id: None,
+        };
+        server.send(confirm.clone()).await.unwrap();
+        assert_eq!(alice.errors().await, vec!["same_moderator"]);
+        assert!(carol.frames_of_type("elevated").await.is_empty());
+
+        server
+            .send(ConfirmElevate {
+                owner_id: bob.id,
+                ..confirm.clone()
+            })
+            .await
+            .unwrap();
+        let elevated = carol.frames_of_type("elevated").await;
+        assert_eq!(elevated.len(), 1);
+        assert_eq!(elevated[0]["object"], carol.id);
+
+        // a confirmed elevation can't be confirmed again
+        server
+            .send(ConfirmElevate {
+                owner_id: bob.id,
+                ..confirm
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob.errors().await, vec!["no_pending_elevation"]);
+    }
+
+    #[actix_rt::test]
+    async fn roster_lists_the_users_of_a_room_with_their_roles() {
+        let server = start_server(Config {
+            hide_participants: true,
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join_with(&server, "room", 3, "carol", &[], true).await;
+        server
+            .send(Mute {
+                object: bob.id,
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+
+        let roster = |client: &MockClient| Roster {
+            user_id: client.id,
+            room_name: "room".to_string(),
+        };
+        server.send(roster(&bob)).await.unwrap();
+        assert_eq!(bob.errors().await, vec!["no_permission"]);
+        assert!(bob.frames_of_type("roster").await.is_empty());
+
+        server.send(roster(&alice)).await.unwrap();
+        let listed = alice.frames_of_type("roster").await;
+        assert_eq!(listed.len(), 1);
+        let users = listed[0]["object"].as_object().unwrap();
+        assert_eq!(users.len(), 3);
+        assert_eq!(users["1"]["elevated"], true);
+        assert_eq!(users["2"]["name"], "bob");
+        assert_eq!(users["2"]["muted"], true);
+        assert_eq!(users["3"]["spectator"], true);
+
+        server.send(Disconnect { id: carol.id }).await.unwrap();
+        alice.clear().await;
+        server.send(roster(&alice)).await.unwrap();
+        let listed = alice.frames_of_type("roster").await;
+        let mut ids: Vec<&String> = listed[0]["object"].as_object().unwrap().keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec!["1", "2"]);
+    }
+
+    #[actix_rt::test]
+    async fn requests_beyond_the_mailbox_capacity_are_refused() {
+        let server = start_server(Config {
+            mailbox_capacity: 4,
+            ..Config::default()
+        });
+        // wait for the server to start and apply the capacity
+        server.send(Flush).await.unwrap();
+
+        // the server can't handle anything before the test yields
+        let results: Vec<bool> = (0..20)
+            .map(|_| match server.try_send(Activity { id: 1 }) {
+                Ok(()) => true,
+                Err(SendError::Full(_)) => false,
+                Err(SendError::Closed(_)) => panic!("server stopped"),
+            })
+            .collect();
+        let queued = results.iter().filter(|queued| **queued).count();
+        // every sender may queue one message beyond the capacity
+        assert!(queued >= 4 && queued <= 5, "queued {}", queued);
+        assert!(results[queued..].iter().all(|queued| !queued));
+
+        // once the server caught up, requests are queued again
+        server.send(Flush).await.unwrap();
+        assert!(server.try_send(Activity { id: 1 }).is_ok());
+    }
+
+    /// Let a user raise, lower, send an instant and relay a message
+    async fn act_in_every_echo(server: &Addr<WebSocketServer>, client: &MockClient) {
+        server
+            .send(raise(client, "room", json!("hand")))
+            .await
+            .unwrap();
+        server
+            .send(Lower {
+                object: json!("hand"),
+                owner_id: client.id,
+                owner_name: client.name.clone(),
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        server
+            .send(Instant {
+                object: json!("clap"),
+                owner_id: client.id,
+                owner_name: client.name.clone(),
+                room_name: "room".to_string(),
+                msg_id: None,
+                delay: None,
+            })
+            .await
+            .unwrap();
+        server
+            .send(ClientMessage {
+                id: client.id,
+                msg: r#"{"type": "custom"}"#.to_string(),
+                room: "room".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn actions_are_echoed_to_the_sender_by_default() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        alice.clear().await;
+
+        act_in_every_echo(&server, &alice).await;
+
+        for r#type in &["raised", "lower", "instant"] {
+            assert_eq!(alice.frames_of_type(r#type).await.len(), 1, "{}", r#type);
+        }
+        assert!(alice.frames_of_type("custom").await.is_empty());
+        for r#type in &["raised", "lower", "instant", "custom"] {
+            assert_eq!(bob.frames_of_type(r#type).await.len(), 1, "{}", r#type);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn echo_to_the_sender_is_configurable_per_broadcast() {
+        let server = start_server(Config {
+            echo: vec![Echo::ClientMessage].into_iter().collect(),
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        alice.clear().await;
+
+        act_in_every_echo(&server, &alice).await;
+
+        for r#type in &["raised", "lower", "instant"] {
+            assert!(alice.frames_of_type(r#type).await.is_empty(), "{}", r#type);
+        }
+        assert_eq!(alice.frames_of_type("custom").await.len(), 1);
+        for r#type in &["raised", "lower", "instant", "custom"] {
+            assert_eq!(bob.frames_of_type(r#type).await.len(), 1, "{}", r#type);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn ending_a_room_only_stops_sessions_without_further_rooms() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "a", 1, "alice").await;
+        let bob = join(&server, "b", 2, "bob").await;
+        server
+            .send(JoinRoom {
+                user_id: alice.id,
+                user_name: alice.name.clone(),
+                room_name: "b".to_string(),
+                spectator: false,
+            })
+            .await
+            .unwrap();
+
+        server
+            .send(EndRoom {
+                owner_id: bob.id,
+                room_name: "b".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+
+        assert!(bob.is_stopped().await);
+        assert!(!alice.is_stopped().await);
+        let ended = alice.frames_of_type("roomended").await;
+        assert_eq!(ended.len(), 1);
+        assert_eq!(ended[0]["object"], "b");
+
+        // alice left the ended room but still uses the other one
+        server
+            .send(raise(&alice, "a", json!("hand")))
+            .await
+            .unwrap();
+        assert_eq!(alice.frames_of_type("raised").await.len(), 1);
+        assert!(alice.errors().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn closing_an_idle_room_keeps_sessions_with_further_rooms() {
+        let server = start_server(Config {
+            room_idle_timeout: Some(Duration::from_millis(100)),
+            ..Config::default()
+        });
+        let bob = join(&server, "b", 2, "bob").await;
+        let alice = join(&server, "b", 1, "alice").await;
+
+        actix_rt::time::delay_for(Duration::from_millis(150)).await;
+        server
+            .send(JoinRoom {
+                user_id: alice.id,
+                user_name: alice.name.clone(),
+                room_name: "a".to_string(),
+                spectator: false,
+            })
+            .await
+            .unwrap();
+        server.send(SweepIdleRooms).await.unwrap();
+
+        assert!(bob.is_stopped().await);
+        assert!(!alice.is_stopped().await);
+        assert_eq!(alice.frames_of_type("roomclosing").await[0]["object"], "b");
+        assert_eq!(alice.frames_of_type("roomended").await[0]["object"], "b");
+    }
+
+    #[actix_rt::test]
+    async fn first_elevation_skips_spectators() {
+        let server = start_server(Config::default());
+        let carol = join_with(&server, "room", 3, "carol", &[], true).await;
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+
+        server
+            .send(Roster {
+                user_id: alice.id,
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap();
+        let listed = alice.frames_of_type("roster").await;
+        assert_eq!(listed.len(), 1);
+        let users = listed[0]["object"].as_object().unwrap();
+        assert_eq!(users[&carol.id.to_string()]["elevated"], false);
+        assert_eq!(users[&alice.id.to_string()]["elevated"], true);
+        assert_eq!(users[&bob.id.to_string()]["elevated"], false);
+    }
+
+    #[actix_rt::test]
+    async fn poll_results_count_the_quorum_of_participants() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        join_with(&server, "room", 3, "carol", &[], true).await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        server
+            .send(poll_option(&alice, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+        server
+            .send(vote(&bob, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+
+        server
+            .send(CloseAllPolls {
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+
+        let results = bob.frames_of_type("pollresults").await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0]["object"]["quorum"],
+            json!({"voters": 1, "eligible": 2})
+        );
+    }
+
+    #[actix_rt::test]
+    async fn moderation_and_poll_messages_are_acknowledged() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+
+        server
+            .send(Poll {
+                msg_id: Some(json!(1)),
+                ..poll(&alice, "room", "lunch")
+            })
+            .await
+            .unwrap();
+        server
+            .send(PollOption {
+                msg_id: Some(json!(2)),
+                ..poll_option(&alice, "room", "lunch", "pizza")
+            })
+            .await
+            .unwrap();
+        server
+            .send(Mute {
+                object: bob.id,
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(3)),
+            })
+            .await
+            .unwrap();
+        server
+            .send(Elevate {
+                msg_id: Some(json!(4)),
+                ..elevate(&alice, "room", &bob)
+            })
+            .await
+            .unwrap();
+        server
+            .send(Recede {
+                object: alice.id,
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(5)),
+            })
+            .await
+            .unwrap();
+
+        let acks = alice.frames_of_type("ack").await;
+        let ids: Vec<&Arbitrary> = acks.iter().map(|ack| &ack["object"]).collect();
+        assert_eq!(ids, vec![&json!(1), &json!(2), &json!(3), &json!(4)]);
+        assert!(acks.iter().all(|ack| ack["success"] == true));
+        let acks = bob.frames_of_type("ack").await;
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks[0]["object"], 5);
+        assert_eq!(acks[0]["success"], true);
+
+        // failures carry the error code, alice is not elevated anymore
+        server
+            .send(Kick {
+                object: 42,
+                ban: false,
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(6)),
+            })
+            .await
+            .unwrap();
+        server
+            .send(TransferElevation {
+                object: bob.id,
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(7)),
+            })
+            .await
+            .unwrap();
+        server
+            .send(EndRoom {
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(8)),
+            })
+            .await
+            .unwrap();
+        server
+            .send(PollOptionRename {
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(9)),
+                poll_title: "lunch".to_string(),
+                old_title: "pizza".to_string(),
+                new_title: "pasta".to_string(),
+            })
+            .await
+            .unwrap();
+        server
+            .send(SetSpectator {
+                object: alice.id,
+                spectator: false,
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(10)),
+            })
+            .await
+            .unwrap();
+        server
+            .send(Unban {
+                object: "nobody".to_string(),
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(11)),
+            })
+            .await
+            .unwrap();
+        server
+            .send(ConfirmElevate {
+                object: alice.id,
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(12)),
+            })
+            .await
+            .unwrap();
+        server
+            .send(RecedeByName {
+                object: "nobody".to_string(),
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(13)),
+            })
+            .await
+            .unwrap();
+        server
+            .send(ElevateByName {
+                object: "alice".to_string(),
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(14)),
+            })
+            .await
+            .unwrap();
+        server
+            .send(EndRoom {
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(15)),
+            })
+            .await
+            .unwrap();
+
+        let outcomes = |acks: Vec<Arbitrary>| -> Vec<(Arbitrary, Arbitrary)> {
+            acks.into_iter()
+                .map(|ack| (ack["object"].clone(), ack["error"].clone()))
+                .collect()
+        };
+        assert_eq!(
+            outcomes(alice.frames_of_type("ack").await)[4..],
+            [
+                (json!(7), json!("no_permission")),
+                (json!(8), json!("no_permission")),
+            ]
+        );
+        assert_eq!(
+            outcomes(bob.frames_of_type("ack").await)[1..],
+            [
+                (json!(6), json!("user_does_not_exist")),
+                (json!(9), Arbitrary::Null),
+                (json!(10), Arbitrary::Null),
+                (json!(11), json!("not_banned")),
+                (json!(12), json!("no_pending_elevation")),
+                (json!(13), json!("user_does_not_exist")),
+                (json!(14), Arbitrary::Null),
+                (json!(15), Arbitrary::Null),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn vote_switch_is_reported_to_elevated_users() {
+        let server = start_server(Config::default());
+        let alice = join_with(&server, "room", 1, "alice", &["votechanged"], false).await;
+        let bob = join_with(&server, "room", 2, "bob", &["votechanged"], false).await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        for option in &["pizza", "pasta"] {
+            server
+                .send(poll_option(&alice, "room", "lunch", option))
+                .await
+                .unwrap();
+        }
+
+        server
+            .send(vote(&bob, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+        assert!(alice.frames_of_type("votechanged").await.is_empty());
+
+        server
+            .send(vote(&bob, "room", "lunch", "pasta"))
+            .await
+            .unwrap();
+        let changed = alice.frames_of_type("votechanged").await;
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0]["pollobject"], "lunch");
+        assert_eq!(changed[0]["oldpolloptionobject"], "pizza");
+        assert_eq!(changed[0]["newpolloptionobject"], "pasta");
+        assert_eq!(changed[0]["username"], "bob");
+        assert_eq!(changed[0]["userid"], 2);
+        // the old frames are still sent
+        assert_eq!(alice.frames_of_type("votedelete").await.len(), 1);
+
+        // not elevated users don't learn who changed their vote
+        assert!(bob.frames_of_type("votechanged").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn banned_users_cannot_rejoin_until_unbanned() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(Kick {
+                object: bob.id,
+                ban: true,
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert!(bob.is_stopped().await);
+        assert_eq!(bob.errors().await, vec!["kicked"]);
+
+        let bob = join(&server, "room", 3, "bob").await;
+        assert!(bob.is_stopped().await);
+        assert_eq!(bob.errors().await, vec!["banned"]);
+
+        // the ban is per room
+        let elsewhere = join(&server, "other", 4, "bob").await;
+        assert!(!elsewhere.is_stopped().await);
+
+        server
+            .send(Unban {
+                object: "bob".to_string(),
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        let bob = join(&server, "room", 5, "bob").await;
+        assert!(!bob.is_stopped().await);
+        assert!(bob.errors().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn bans_expire() {
+        let server = start_server(Config {
+            ban_duration: Duration::from_millis(50),
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(Kick {
+                object: bob.id,
+                ban: true,
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert!(join(&server, "room", 3, "bob").await.is_stopped().await);
+
+        actix_rt::time::delay_for(Duration::from_millis(80)).await;
+        let bob = join(&server, "room", 4, "bob").await;
+        assert!(!bob.is_stopped().await);
+    }
+
+    #[actix_rt::test]
+    async fn kicking_without_ban_allows_rejoining() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(Kick {
+                object: bob.id,
+                ban: false,
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert!(bob.is_stopped().await);
+
+        let bob = join(&server, "room", 3, "bob").await;
+        assert!(!bob.is_stopped().await);
+    }
+
+    #[actix_rt::test]
+    async fn late_joiners_see_raised_objects_like_live_users() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(raise(&alice, "room", json!("hand")))
+            .await
+            .unwrap();
+        server
+            .send(raise(&bob, "room", json!({"topic": "idea"})))
+            .await
+            .unwrap();
+        let live = alice.frames_of_type("raised").await;
+        assert_eq!(live.len(), 2);
+
+        let carol = join(&server, "room", 3, "carol").await;
+        let all = carol.frames_of_type("all").await;
+        let replayed = all[0]["raised"].as_array().unwrap();
+        assert_eq!(replayed.len(), 2);
+        for (live, replayed) in live.iter().zip(replayed) {
+            for field in &["object", "owner_id", "owner_name", "elevated"] {
+                assert_eq!(live[field], replayed[field], "field {}", field);
+            }
+        }
+        assert_eq!(replayed[0]["elevated"], true);
+        assert_eq!(replayed[1]["elevated"], false);
+    }
+
+    #[actix_rt::test]
+    async fn muted_users_cannot_raise_but_still_take_part_in_polls() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(Mute {
+                object: bob.id,
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(alice.frames_of_type("muted").await[0]["object"], 2);
+        assert_eq!(bob.frames_of_type("muted").await.len(), 1);
+
+        server
+            .send(raise(&bob, "room", json!("hand")))
+            .await
+            .unwrap();
+        server
+            .send(Instant {
+                object: json!("applause"),
+                owner_id: bob.id,
+                owner_name: bob.name.clone(),
+                room_name: "room".to_string(),
+                msg_id: None,
+                delay: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob.errors().await, vec!["muted", "muted"]);
+        assert!(alice.frames_of_type("raised").await.is_empty());
+        assert!(alice.frames_of_type("instant").await.is_empty());
+
+        // polls work as before
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        server
+            .send(poll_option(&alice, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+        server
+            .send(vote(&bob, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+        assert_eq!(bob.frames_of_type("poll").await.len(), 1);
+        assert_eq!(alice.frames_of_type("vote").await.len(), 1);
+        assert_eq!(bob.errors().await.len(), 2);
+
+        server
+            .send(Unmute {
+                object: bob.id,
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        server
+            .send(raise(&bob, "room", json!("hand")))
+            .await
+            .unwrap();
+        assert_eq!(alice.frames_of_type("unmuted").await.len(), 1);
+        assert_eq!(alice.frames_of_type("raised").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn only_elevated_users_can_mute() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(Mute {
+                object: alice.id,
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: Some(json!(1)),
+            })
+            .await
+            .unwrap();
+
+        assert!(alice.frames_of_type("muted").await.is_empty());
+        assert_eq!(bob.frames_of_type("ack").await[0]["error"], "no_permission");
+        server
+            .send(raise(&alice, "room", json!("hand")))
+            .await
+            .unwrap();
+        assert!(alice.errors().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn raise_with_msg_id_is_acknowledged() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+
+        server
+            .send(Raise {
+                msg_id: Some(json!("r1")),
+                ..raise(&alice, "room", json!("hand"))
+            })
+            .await
+            .unwrap();
+        server
+            .send(Raise {
+                msg_id: Some(json!("r2")),
+                ..raise(&alice, "room", Arbitrary::Null)
+            })
+            .await
+            .unwrap();
+
+        let acks = alice.frames_of_type("ack").await;
+        assert_eq!(acks.len(), 2);
+        assert_eq!(acks[0]["object"], "r1");
+        assert_eq!(acks[0]["success"], true);
+        assert_eq!(acks[1]["object"], "r2");
+        assert_eq!(acks[1]["success"], false);
+        assert!(acks[1]["error"].is_string());
+
+        // only the sender is acknowledged, messages without msg_id are not
+        assert!(bob.frames_of_type("ack").await.is_empty());
+        server
+            .send(raise(&alice, "room", json!("idea")))
+            .await
+            .unwrap();
+        assert_eq!(alice.frames_of_type("ack").await.len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn blank_and_overlong_titles_are_refused() {
+        let server = start_server(Config {
+            max_title_length: 10,
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+
+        server.send(poll(&alice, "room", "   ")).await.unwrap();
+        server
+            .send(poll(&alice, "room", "a title too long"))
+            .await
+            .unwrap();
+        assert_eq!(alice.errors().await, vec!["title_invalid", "title_invalid"]);
+        assert!(alice.frames_of_type("poll").await.is_empty());
+
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        server
+            .send(poll_option(&alice, "room", "lunch", "\t"))
+            .await
+            .unwrap();
+        assert_eq!(alice.errors().await.len(), 3);
+        assert!(alice.frames_of_type("polloption").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn titles_are_trimmed_so_they_match_later_references() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+
+        server.send(poll(&alice, "room", "  lunch ")).await.unwrap();
+        server
+            .send(poll_option(&alice, "room", "lunch", " pizza"))
+            .await
+            .unwrap();
+        server
+            .send(vote(&alice, "room", " lunch", "pizza  "))
+            .await
+            .unwrap();
+
+        assert!(alice.errors().await.is_empty());
+        assert_eq!(alice.frames_of_type("poll").await[0]["object"], "lunch");
+        let votes = alice.frames_of_type("vote").await;
+        assert_eq!(votes.len(), 1);
+        assert_eq!(votes[0]["pollobject"], "lunch");
+        assert_eq!(votes[0]["polloptionobject"], "pizza");
+    }
+
+    #[test]
+    fn removing_a_user_only_removes_their_votes_in_unclosed_polls() {
+        let poll = |title: &str, state: PollState| Poll {
+            title: title.to_string(),
+            owner_id: 1,
+            owner_name: "alice".to_string(),
+            room_name: "room".to_string(),
+            options: vec![PollOption {
+                title: "pizza".to_string(),
+                owner_id: 2,
+                owner_name: "bob".to_string(),
+                room_name: "room".to_string(),
+                poll_title: title.to_string(),
+                order: 0,
+                msg_id: None,
+            }],
+            votes: vec![(1, "pizza".to_string()), (2, "pizza".to_string())]
+                .into_iter()
+                .collect(),
+            state,
+            lock_after_first_vote_secs: None,
+            voting_locked: false,
+            created: std::time::Instant::now(),
+            msg_id: None,
+        };
+        let mut room = Room::default();
+        room.polls.push(poll("lunch", PollState::Open));
+        room.polls.push(poll("breakfast", PollState::Closed));
+        room.polls.push(poll("dinner", PollState::Draft));
+
+        let mut removed = room.remove_user_votes(&2);
+        removed.sort();
+        assert_eq!(
+            removed,
+            vec![
+                ("dinner".to_string(), "pizza".to_string()),
+                ("lunch".to_string(), "pizza".to_string()),
+            ]
+        );
+
+        // the closed poll keeps its final result, options of the user stay
+        assert_eq!(room.polls[1].votes.len(), 2);
+        assert!(room.polls.iter().all(|poll| poll.options.len() == 1));
+        assert!(room.polls[0].votes.contains_key(&1));
+        assert!(room.remove_user_votes(&2).is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn poll_options_are_limited_per_poll() {
+        let server = start_server(Config {
+            max_poll_options: Some(2),
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+
+        for title in &["pizza", "curry"] {
+            server
+                .send(poll_option(&alice, "room", "lunch", title))
+                .await
+                .unwrap();
+        }
+        assert!(alice.errors().await.is_empty());
+
+        server
+            .send(poll_option(&alice, "room", "lunch", "salad"))
+            .await
+            .unwrap();
+        assert_eq!(alice.errors().await, vec!["too_many_options"]);
+        assert_eq!(alice.frames_of_type("polloption").await.len(), 2);
+
+        // the cap counts per poll
+        server.send(poll(&alice, "room", "dinner")).await.unwrap();
+        server
+            .send(poll_option(&alice, "room", "dinner", "soup"))
+            .await
+            .unwrap();
+        assert_eq!(alice.errors().await.len(), 1);
+        assert_eq!(alice.frames_of_type("polloption").await.len(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn closing_a_poll_frees_an_open_poll_slot() {
+        let server = start_server(Config {
+            max_open_polls: Some(1),
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+        server.send(poll(&alice, "room", "first")).await.unwrap();
+        server.send(poll(&alice, "room", "second")).await.unwrap();
+        assert_eq!(alice.errors().await, vec!["too_many_polls"]);
+
+        server
+            .send(PollCloseHelper {
+                sender_id: alice.id,
+                sender_name: alice.name.clone(),
+                room_name: "room".to_string(),
+                poll_title: "first".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        alice.clear().await;
+
+        server.send(poll(&alice, "room", "second")).await.unwrap();
+        assert!(alice.errors().await.is_empty());
+        assert_eq!(alice.frames_of_type("poll").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn disconnecting_announces_the_leaving_user_in_every_room() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "a", 1, "alice").await;
+        let bob = join(&server, "b", 2, "bob").await;
+        let carol = join(&server, "a", 3, "carol").await;
+        server
+            .send(JoinRoom {
+                user_id: carol.id,
+                user_name: carol.name.clone(),
+                room_name: "b".to_string(),
+                spectator: false,
+            })
+            .await
+            .unwrap();
+
+        server.send(Disconnect { id: carol.id }).await.unwrap();
+
+        for client in &[&alice, &bob] {
+            let left = client.frames_of_type("userleft").await;
+            assert_eq!(left.len(), 1);
+            assert_eq!(left[0]["object"]["id"], 3);
+            assert_eq!(left[0]["object"]["name"], "carol");
+        }
+    }
+
+    #[actix_rt::test]
+    async fn capabilities_are_agreed_in_hello_and_change_the_frames() {
+        let server = start_server(Config::default());
+        let alice = join_with(
+            &server,
+            "room",
+            1,
+            "alice",
+            &["votechanged", "delta"],
+            false,
+        )
+        .await;
+        let legacy = join(&server, "room", 2, "bob").await;
+
+        let hello = alice.frames_of_type("hello").await;
+        assert_eq!(hello[0]["capabilities"], json!(["delta", "votechanged"]));
+        let hello = legacy.frames_of_type("hello").await;
+        assert_eq!(hello[0]["capabilities"], json!([]));
+
+        alice.clear().await;
+        legacy.clear().await;
+        let carol = join(&server, "room", 3, "carol").await;
+        server.send(Disconnect { id: carol.id }).await.unwrap();
+
+        assert!(alice.frames_of_type("all").await.is_empty());
+        assert_eq!(legacy.frames_of_type("all").await.len(), 1);
+    }
+
+    /// Elevation of the first and second user joining a room with an elevation mode
+    async fn initial_elevation(elevation_mode: ElevationMode) -> (Arbitrary, Arbitrary) {
+        let server = start_server(Config {
+            elevation_mode,
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+
+        server
+            .send(Roster {
+                user_id: alice.id,
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap();
+        let listed = alice.frames_of_type("roster").await;
+        let users = &listed[0]["object"];
+        (
+            users[&alice.id.to_string()]["elevated"].clone(),
+            users[&bob.id.to_string()]["elevated"].clone(),
+        )
+    }
+
+    #[actix_rt::test]
+    async fn elevation_modes_decide_the_initial_elevation() {
+        assert_eq!(
+            initial_elevation(ElevationMode::First).await,
+            (json!(true), json!(false))
+        );
+        assert_eq!(
+            initial_elevation(ElevationMode::None).await,
+            (json!(false), json!(false))
+        );
+        assert_eq!(
+            initial_elevation(ElevationMode::All).await,
+            (json!(true), json!(true))
+        );
+    }
+
+    #[test]
+    fn elevation_modes_are_parsed() {
+        assert!(matches!("first".parse(), Ok(ElevationMode::First)));
+        assert!(matches!("none".parse(), Ok(ElevationMode::None)));
+        assert!(matches!("all".parse(), Ok(ElevationMode::All)));
+        assert!("First".parse::<ElevationMode>().is_err());
+        assert!("".parse::<ElevationMode>().is_err());
+    }
+
+    #[actix_rt::test]
+    async fn polls_created_over_http_reach_the_room() {
+        use actix_web::http::StatusCode;
+        use actix_web::{test, web, App};
+
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let mut app = test::init_service(
+            App::new()
+                .data(server.clone())
+                .data(crate::api::ApiToken(Some("secret".to_string())))
+                .service(
+                    web::resource("/rooms/{room}/polls")
+                        .route(web::post().to(crate::api::create_poll_route)),
+                ),
+        )
+        .await;
+        let create = |room_name: &str, token: &str| {
+            test::TestRequest::post()
+                .uri(&format!("/rooms/{}/polls", room_name))
+                .header("Authorization", format!("Bearer {}", token))
+                .set_json(&json!({"title": "lunch", "options": ["pizza", "curry"]}))
+                .to_request()
+        };
+
+        let response = test::call_service(&mut app, create("room", "guess")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = test::call_service(&mut app, create("other", "secret")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(alice.frames_of_type("poll").await.is_empty());
+
+        let response = test::call_service(&mut app, create("room", "secret")).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let polls = alice.frames_of_type("poll").await;
+        assert_eq!(polls.len(), 1);
+        assert_eq!(polls[0]["object"], "lunch");
+        let options: Vec<Arbitrary> = alice
+            .frames_of_type("polloption")
+            .await
+            .iter()
+            .map(|option| option["polloptionobject"].clone())
+            .collect();
+        assert_eq!(options, vec![json!("pizza"), json!("curry")]);
+
+        let response = test::call_service(&mut app, create("room", "secret")).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[test]
+    fn title_length_is_counted_in_characters() {
+        let server = WebSocketServer::new(Config {
+            max_title_length: 4,
+            ..Config::default()
+        });
+
+        // four emoji are 16 bytes but four characters
+        assert_eq!(
+            server.validate_title(" 🍕🍛🥗🍜 "),
+            Ok("🍕🍛🥗🍜".to_string())
+        );
+        assert_eq!(server.validate_title("🍕🍛🥗🍜🍣"), Err("title_invalid"));
+
+        // combining characters count on their own, an accented "e" built from two is two
+        assert_eq!(server.validate_title("cafe\u{301}"), Err("title_invalid"));
+        assert_eq!(
+            server.validate_title("caf\u{e9}"),
+            Ok("caf\u{e9}".to_string())
+        );
+        assert_eq!(
+            server.validate_title("ca\u{301}\u{301}"),
+            Ok("ca\u{301}\u{301}".to_string())
+        );
+
+        assert_eq!(server.validate_title(" \u{3000} "), Err("title_invalid"));
+        assert_eq!(server.validate_title("a\u{0}b"), Err("title_invalid"));
+    }
 
-                // send options for poll
-                for option in poll.options.clone() {
-                    let option_txt = json!(messages::outbound::PollOption {
-                        r#type: messages::outbound::Types::PollOption,
-                        pollobject: poll.title.clone(),
-                        polloptionobject: option.title.clone(),
-                    })
-                    .to_string();
-                    self.send_message_user(&room_name, &option_txt, user_id);
-                }
+    /// A session keeping the shared texts it receives instead of copying them
+    struct SharingSession {
+        texts: Arc<Mutex<Vec<Arc<str>>>>,
+    }
 
-                // send votes for poll
-                for (_, option_title) in poll.votes.clone() {
-                    let vote_txt = json!(messages::outbound::Vote {
-                        r#type: messages::outbound::Types::Vote,
-                        pollobject: poll.title.clone(),
-                        polloptionobject: option_title.clone(),
-                        username: "".to_string(),
-                        userid: 0,
-                    })
-                    .to_string();
-                    self.send_message_user(&room_name, &vote_txt, user_id);
-                }
-            }
+    impl Actor for SharingSession {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<Message> for SharingSession {
+        type Result = ();
+
+        fn handle(&mut self, msg: Message, _: &mut Context<Self>) {
+            self.texts.lock().unwrap().push(msg.0);
         }
     }
-}
 
-impl Handler<Raise> for WebSocketServer {
-    type Result = ();
+    impl Handler<Stop> for SharingSession {
+        type Result = ();
 
-    fn handle(&mut self, msg: Raise, _: &mut Context<Self>) {
-        let mut check_raised = self
-            .rooms
-            .get(msg.room_name.as_str())
-            .unwrap()
-            .raised
-            .clone();
-        check_raised.retain(|elem| elem.object == msg.object && elem.owner_id == msg.owner_id);
+        fn handle(&mut self, _: Stop, _: &mut Context<Self>) {}
+    }
 
-        if check_raised.len() > 0 {
-            self.send_error_user(
-                &msg.room_name,
-                "already_raised",
-                "Refusing to raise, already raised",
-                msg.owner_id,
-            );
-            println!("Refusing to raise, already raised");
-            return;
+    impl Handler<Flush> for SharingSession {
+        type Result = ();
+
+        fn handle(&mut self, _: Flush, _: &mut Context<Self>) {}
+    }
+
+    #[actix_rt::test]
+    async fn broadcasts_share_one_text_between_all_recipients() {
+        let server = start_server(Config::default());
+        let mut sessions = Vec::new();
+        for user_id in 0..100 {
+            let texts = Arc::new(Mutex::new(Vec::new()));
+            let addr = SharingSession {
+                texts: texts.clone(),
+            }
+            .start();
+            server
+                .send(Join {
+                    addr: addr.clone().recipient(),
+                    stop: addr.clone().recipient(),
+                    user_id,
+                    user_name: format!("user{}", user_id),
+                    room_name: "room".to_string(),
+                    capabilities: HashSet::new(),
+                    spectator: false,
+                    admin: false,
+                })
+                .await
+                .unwrap();
+            sessions.push((addr, texts));
+        }
+        server.send(Flush).await.unwrap();
+        for (addr, texts) in &sessions {
+            addr.send(Flush).await.unwrap();
+            texts.lock().unwrap().clear();
         }
 
-        let elevated = self
-            .rooms
-            .get(msg.room_name.as_str())
-            .unwrap()
-            .is_elevated(&msg.owner_id)
-            .unwrap_or(false);
+        server
+            .send(Raise {
+                object: json!("hand"),
+                owner_id: 0,
+                owner_name: "user0".to_string(),
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        server.send(Flush).await.unwrap();
+
+        let mut raised = Vec::new();
+        for (addr, texts) in &sessions {
+            addr.send(Flush).await.unwrap();
+            let texts = texts.lock().unwrap();
+            let text = texts.iter().find(|text| text.contains(r#""raised""#));
+            raised.push(text.unwrap().clone());
+        }
+        assert!(raised.iter().all(|text| Arc::ptr_eq(text, &raised[0])));
+    }
 
-        let txt = json!(messages::outbound::OwnedObject {
-            r#type: messages::outbound::Types::Raised,
-            owner_id: msg.owner_id,
-            owner_name: msg.owner_name.clone(),
-            object: msg.object.clone(),
-            elevated: elevated,
-        });
-        self.send_message_all(msg.room_name.as_str(), &txt.to_string());
+    #[actix_rt::test]
+    async fn users_are_elevated_and_receded_by_name() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        alice.clear().await;
+
+        server
+            .send(ElevateByName {
+                object: "bob".to_string(),
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        let elevated = bob.frames_of_type("elevated").await;
+        assert_eq!(elevated.len(), 1);
+        assert_eq!(elevated[0]["object"], bob.id);
+
+        server
+            .send(RecedeByName {
+                object: "bob".to_string(),
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        let receded = bob.frames_of_type("receded").await;
+        assert_eq!(receded.len(), 1);
+        assert_eq!(receded[0]["object"], bob.id);
+
+        server
+            .send(ElevateByName {
+                object: "nobody".to_string(),
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(alice.errors().await, vec!["user_does_not_exist"]);
+    }
 
-        let room = self.rooms.entry(msg.room_name).or_insert(Room::default());
-        room.raised.push(Raised {
-            object: msg.object,
-            owner_id: msg.owner_id,
-            owner_name: msg.owner_name,
-        });
+    #[actix_rt::test]
+    async fn elevating_a_duplicate_name_is_ambiguous() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let first = join(&server, "room", 2, "bob").await;
+        let second = join(&server, "room", 3, "bob").await;
+
+        server
+            .send(ElevateByName {
+                object: "bob".to_string(),
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(alice.errors().await, vec!["ambiguous_name"]);
+        assert!(first.frames_of_type("elevated").await.is_empty());
+        assert!(second.frames_of_type("elevated").await.is_empty());
     }
-}
 
-impl Handler<Lower> for WebSocketServer {
-    type Result = ();
+    #[actix_rt::test]
+    async fn snapshots_agree_with_raise_and_lower_deltas() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+
+        // the join is queued right behind the raise, its snapshot must contain the raised object
+        server.do_send(raise(&alice, "room", json!("hand")));
+        let carol = join(&server, "room", 3, "carol").await;
+        let replayed = carol.frames_of_type("all").await[0]["raised"].clone();
+        let live = carol.frames_of_type("raised").await;
+        assert_eq!(replayed.as_array().unwrap().len() + live.len(), 1);
+        assert_eq!(alice.frames_of_type("raised").await.len(), 1);
+
+        server.do_send(Lower {
+            object: json!("hand"),
+            owner_id: alice.id,
+            owner_name: alice.name.clone(),
+            room_name: "room".to_string(),
+            msg_id: None,
+        });
+        let dave = join(&server, "room", 4, "dave").await;
+        let replayed = dave.frames_of_type("all").await[0]["raised"].clone();
+        assert!(replayed.as_array().unwrap().is_empty());
+        assert_eq!(carol.frames_of_type("lower").await.len(), 1);
+    }
 
-    fn handle(&mut self, msg: Lower, _: &mut Context<Self>) {
-        let equiv_clone = msg.clone();
-        let room = self
-            .rooms
-            .entry(msg.room_name.clone())
-            .or_insert(Room::default());
+    #[actix_rt::test]
+    async fn vote_frames_are_identical_when_voting_elevating_and_leaving() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join(&server, "room", 3, "carol").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        server
+            .send(poll_option(&alice, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
 
-        let mut check_raised = room.raised.clone();
-        check_raised.retain(|elem| &elem.object == &msg.object && &elem.owner_id == &msg.owner_id);
+        // voting
+        server
+            .send(vote(&carol, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+        let identified_vote = alice.texts_of_type("vote").await;
+        let anon_vote = bob.texts_of_type("vote").await;
+        assert_eq!(identified_vote.len(), 1);
+        assert_eq!(anon_vote.len(), 1);
+        assert_ne!(identified_vote, anon_vote);
+        bob.clear().await;
+
+        // elevating replaces the anonymous votes with identified ones
+        server.send(elevate(&alice, "room", &bob)).await.unwrap();
+        assert_eq!(bob.texts_of_type("vote").await, identified_vote);
+        let anon_delete = bob.texts_of_type("votedelete").await;
+        assert_eq!(anon_delete.len(), 1);
+        bob.clear().await;
+
+        // receding replaces them with anonymous ones again
+        server
+            .send(Recede {
+                object: bob.id,
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob.texts_of_type("vote").await, anon_vote);
+        let identified_delete = bob.texts_of_type("votedelete").await;
+        assert_eq!(identified_delete.len(), 1);
+        assert_ne!(identified_delete, anon_delete);
+        bob.clear().await;
+
+        // leaving removes the votes
+        server.send(Disconnect { id: carol.id }).await.unwrap();
+        assert_eq!(alice.texts_of_type("votedelete").await, identified_delete);
+        assert_eq!(bob.texts_of_type("votedelete").await, anon_delete);
+    }
 
-        if check_raised.len() == 0 {
-            self.send_error_user(
-                &msg.room_name,
-                "not_raised",
-                "Refusing to lower, is not raised",
-                msg.owner_id,
-            );
-            println!("Refusing to lower, is not raised");
-            return;
+    /// Build an `Instant` of a user, sent after `delay` if set
+    fn instant(client: &MockClient, room_name: &str, delay: Option<Duration>) -> Instant {
+        Instant {
+            object: json!("clap"),
+            owner_id: client.id,
+            owner_name: client.name.clone(),
+            room_name: room_name.to_string(),
+            msg_id: None,
+            delay,
         }
+    }
 
-        let raised_equivalent = Raised {
-            object: json!(equiv_clone.object),
-            owner_id: equiv_clone.owner_id,
-            owner_name: equiv_clone.owner_name,
-        };
+    #[actix_rt::test]
+    async fn delayed_instants_are_broadcast_after_the_delay() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
 
-        room.raised.retain(|elem| elem != &raised_equivalent);
+        server
+            .send(instant(&alice, "room", Some(Duration::from_secs(0))))
+            .await
+            .unwrap();
+        assert_eq!(bob.frames_of_type("instant").await.len(), 1);
 
-        let elevated = self
-            .rooms
-            .get(msg.room_name.as_str())
-            .unwrap()
-            .is_elevated(&msg.owner_id)
-            .unwrap_or(false);
+        server
+            .send(instant(&alice, "room", Some(Duration::from_millis(100))))
+            .await
+            .unwrap();
+        assert_eq!(bob.frames_of_type("instant").await.len(), 1);
 
-        let txt = json!(messages::outbound::OwnedObject {
-            r#type: messages::outbound::Types::Lower,
-            owner_id: msg.owner_id,
-            owner_name: msg.owner_name,
-            object: msg.object,
-            elevated: elevated,
-        })
-        .to_string();
-        self.send_message_all(&msg.room_name, &txt);
+        actix_rt::time::delay_for(Duration::from_millis(150)).await;
+        assert_eq!(bob.frames_of_type("instant").await.len(), 2);
     }
-}
-
-impl Handler<Instant> for WebSocketServer {
-    type Result = ();
 
-    fn handle(&mut self, msg: Instant, _: &mut Context<Self>) {
-        let elevated = self
-            .rooms
-            .get(msg.room_name.as_str())
-            .unwrap()
-            .is_elevated(&msg.owner_id)
-            .unwrap_or(false);
+    #[actix_rt::test]
+    async fn delayed_instants_of_a_leaving_user_are_cancelled() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
 
-        let txt = json!(messages::outbound::OwnedObject {
-            r#type: messages::outbound::Types::Instant,
-            owner_id: msg.owner_id,
-            owner_name: msg.owner_name,
-            object: msg.object,
-            elevated: elevated,
-        })
-        .to_string();
+        server
+            .send(instant(&alice, "room", Some(Duration::from_millis(100))))
+            .await
+            .unwrap();
+        server.send(Disconnect { id: alice.id }).await.unwrap();
 
-        self.send_message_all(&msg.room_name, &txt);
+        actix_rt::time::delay_for(Duration::from_millis(150)).await;
+        assert!(bob.frames_of_type("instant").await.is_empty());
     }
-}
 
-impl Handler<Poll> for WebSocketServer {
-    type Result = ();
-
-    fn handle(&mut self, poll: Poll, _: &mut Context<Self>) {
-        // get room
-        let room = self
-            .rooms
-            .entry(poll.room_name.clone())
-            .or_insert(Room::default());
+    /// An `EventSink` recording the events it receives
+    struct RecordingSink {
+        events: Arc<Mutex<Vec<String>>>,
+    }
 
-        // check if user is elevated
-        let mut user_is_elevated = room.connected.clone();
-        user_is_elevated.retain(|id, user| {
-            id == &poll.owner_id && &user.name == &poll.owner_name && user.elevated
-        });
+    impl EventSink for RecordingSink {
+        fn user_joined(&self, room_name: &str, user_id: usize, user_name: &str) {
+            let event = format!("joined {} {} {}", room_name, user_id, user_name);
+            self.events.lock().unwrap().push(event);
+        }
 
-        if user_is_elevated.len() == 0 {
-            self.send_error_user(
-                &poll.room_name,
-                "no_permission",
-                "You do not have permission to create polls (because you're not elevated)",
-                poll.owner_id,
-            );
-            println!("User does not have permission to create polls (not elevated)");
-            return;
+        fn user_left(&self, room_name: &str, user_id: usize, user_name: &str) {
+            let event = format!("left {} {} {}", room_name, user_id, user_name);
+            self.events.lock().unwrap().push(event);
         }
 
-        // check if poll already exists
-        let mut poll_exists = room.polls.clone();
-        poll_exists.retain(|elem| &elem.title == &poll.title);
+        fn poll_created(&self, room_name: &str, poll_title: &str) {
+            let event = format!("created {} {}", room_name, poll_title);
+            self.events.lock().unwrap().push(event);
+        }
 
-        if poll_exists.len() > 0 {
-            self.send_error_user(
-                &poll.room_name,
-                "poll_already_exists",
-                "A poll with that title already exists",
-                poll.owner_id,
-            );
-            println!("A poll with that title already exists");
-            return;
+        fn poll_closed(&self, room_name: &str, poll_title: &str) {
+            let event = format!("closed {} {}", room_name, poll_title);
+            self.events.lock().unwrap().push(event);
         }
+    }
 
-        // clone later needed values
-        let poll_title = poll.title.clone();
-        let room_name = poll.room_name.clone();
+    #[actix_rt::test]
+    async fn lifecycle_events_reach_the_event_sink() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let server = WebSocketServer::new(Config::default())
+            .with_event_sink(Box::new(RecordingSink {
+                events: events.clone(),
+            }))
+            .start();
+
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        server
+            .send(PollCloseHelper {
+                sender_id: alice.id,
+                sender_name: alice.name.clone(),
+                room_name: "room".to_string(),
+                poll_title: "lunch".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        server.send(Disconnect { id: bob.id }).await.unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "joined room 1 alice",
+                "joined room 2 bob",
+                "created room lunch",
+                "closed room lunch",
+                "left room 2 bob",
+            ]
+        );
+    }
 
-        // add poll to room
-        room.polls.push(poll);
+    #[actix_rt::test]
+    async fn raising_is_open_to_everybody_by_default() {
+        let server = start_server(Config::default());
+        let _alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
 
-        // send poll message to clients
-        let poll_txt = json!(messages::outbound::Poll {
-            r#type: messages::outbound::Types::Poll,
-            object: poll_title.clone(),
-        })
-        .to_string();
-        self.send_message_all(&room_name, &poll_txt);
+        server
+            .send(raise(&bob, "room", json!("hand")))
+            .await
+            .unwrap();
+        assert!(bob.errors().await.is_empty());
+        assert_eq!(bob.frames_of_type("raised").await.len(), 1);
     }
-}
 
-impl Handler<PollOption> for WebSocketServer {
-    type Result = ();
+    #[actix_rt::test]
+    async fn raising_can_require_elevation() {
+        let server = start_server(Config {
+            raise_requires_elevation: true,
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
 
-    fn handle(&mut self, poll_option: PollOption, _: &mut Context<Self>) {
-        // get room
-        let room = self
-            .rooms
-            .entry(poll_option.room_name.clone())
-            .or_insert(Room::default());
+        server
+            .send(raise(&bob, "room", json!("hand")))
+            .await
+            .unwrap();
+        assert_eq!(bob.errors().await, vec!["no_permission"]);
+        assert!(alice.frames_of_type("raised").await.is_empty());
 
-        // check if user is elevated
-        let mut user_is_elevated = room.connected.clone();
-        user_is_elevated.retain(|id, user| {
-            id == &poll_option.owner_id && &user.name == &poll_option.owner_name && user.elevated
-        });
+        server
+            .send(raise(&alice, "room", json!("hand")))
+            .await
+            .unwrap();
+        assert!(alice.errors().await.is_empty());
+        assert_eq!(bob.frames_of_type("raised").await.len(), 1);
+    }
 
-        if user_is_elevated.len() == 0 {
-            self.send_error_user(
-                &poll_option.room_name,
-                "no_permission",
-                "You do not have permission to add poll options (because you're not elevated)",
-                poll_option.owner_id,
-            );
-            println!("User does not have permission to add poll options (not elevated)");
-            return;
+    #[actix_rt::test]
+    async fn raises_of_the_same_object_are_capped_per_room() {
+        let server = start_server(Config {
+            max_object_raises: Some(2),
+            ..Config::default()
+        });
+        let mut clients = Vec::new();
+        for user_id in 1..=4 {
+            clients.push(join(&server, "room", user_id, &format!("user{}", user_id)).await);
         }
 
-        // check if poll exists
-        let mut poll_exists = room.polls.clone();
-        poll_exists.retain(|poll| poll.title == poll_option.poll_title);
-
-        if poll_exists.len() == 0 {
-            self.send_error_user(
-                &poll_option.room_name,
-                "poll_does_not_exist",
-                "A poll with that title doesn't exist",
-                poll_option.owner_id,
-            );
-            println!("A poll with that title doesn't exist");
-            return;
+        for client in &clients {
+            server
+                .send(raise(client, "room", json!("hand")))
+                .await
+                .unwrap();
         }
+        assert!(clients[0].errors().await.is_empty());
+        assert!(clients[1].errors().await.is_empty());
+        assert_eq!(clients[2].errors().await, vec!["object_raise_limit"]);
+        assert_eq!(clients[3].errors().await, vec!["object_raise_limit"]);
+        assert_eq!(clients[0].frames_of_type("raised").await.len(), 2);
+
+        // other objects have their own cap
+        server
+            .send(raise(&clients[2], "room", json!("question")))
+            .await
+            .unwrap();
+        assert_eq!(clients[2].errors().await.len(), 1);
+        assert_eq!(clients[0].frames_of_type("raised").await.len(), 3);
+    }
 
-        // get poll
-        let poll_index = room
-            .polls
-            .iter()
-            .position(|poll| poll.title == poll_option.poll_title)
+    #[actix_rt::test]
+    async fn raising_many_objects_raises_every_valid_one() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(raise(&alice, "room", json!("budget")))
+            .await
+            .unwrap();
+        bob.clear().await;
+
+        server
+            .send(RaiseMany {
+                objects: vec![json!("agenda"), json!("budget"), json!("minutes")],
+                owner_id: alice.id,
+                owner_name: alice.name.clone(),
+                room_name: "room".to_string(),
+                msg_id: Some(json!(7)),
+            })
+            .await
             .unwrap();
-        let poll = room.polls.get_mut(poll_index).unwrap();
 
-        // check if poll is closed
-        if poll.closed {
-            self.send_error_user(
-                &poll_option.room_name,
-                "poll_closed",
-                "Sorry, the poll is already closed",
-                poll_option.owner_id,
-            );
-            println!("Poll is already closed");
-            return;
-        }
+        let raised: Vec<Arbitrary> = bob
+            .frames_of_type("raised")
+            .await
+            .iter()
+            .map(|raised| raised["object"].clone())
+            .collect();
+        assert_eq!(raised, vec![json!("agenda"), json!("minutes")]);
+        assert_eq!(alice.errors().await, vec!["already_raised"]);
+        let acks = alice.frames_of_type("ack").await;
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks[0]["success"], false);
+        assert_eq!(acks[0]["error"], "already_raised");
+        assert_eq!(
+            acks[0]["failed"],
+            json!([{"index": 1, "object": "budget", "error": "already_raised"}])
+        );
 
-        // check if poll_option already exists
-        let mut poll_option_exists = poll.options.clone();
-        poll_option_exists
-            .retain(|existing_poll_option| existing_poll_option.title == poll_option.title);
+        // without failures the list is empty
+        server
+            .send(RaiseMany {
+                objects: vec![json!("questions")],
+                owner_id: alice.id,
+                owner_name: alice.name.clone(),
+                room_name: "room".to_string(),
+                msg_id: Some(json!(8)),
+            })
+            .await
+            .unwrap();
+        let acks = alice.frames_of_type("ack").await;
+        assert_eq!(acks[1]["success"], true);
+        assert_eq!(acks[1]["failed"], json!([]));
+    }
 
-        if poll_option_exists.len() > 0 {
-            self.send_error_user(
-                &poll_option.room_name,
-                "poll_option_already_exists",
-                "A poll-option with that title in this poll does already exist",
-                poll_option.owner_id,
-            );
-            println!("A poll-option with that title in this poll does already exist");
-            return;
-        }
+    #[actix_rt::test]
+    async fn transferring_elevation_leaves_only_the_target_elevated() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join(&server, "room", 3, "carol").await;
+
+        // only elevated users can transfer their elevation
+        server
+            .send(TransferElevation {
+                object: carol.id,
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob.errors().await, vec!["no_permission"]);
+
+        server
+            .send(TransferElevation {
+                object: bob.id,
+                owner_id: alice.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        let elevated = carol.frames_of_type("elevated").await;
+        let receded = carol.frames_of_type("receded").await;
+        assert_eq!(elevated.len(), 1);
+        assert_eq!(elevated[0]["object"], bob.id);
+        assert_eq!(receded.len(), 1);
+        assert_eq!(receded[0]["object"], alice.id);
+
+        server
+            .send(Roster {
+                user_id: bob.id,
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap();
+        let listed = bob.frames_of_type("roster").await;
+        let users = listed[0]["object"].as_object().unwrap();
+        let elevated: Vec<&String> = users
+            .iter()
+            .filter(|(_, user)| user["elevated"] == true)
+            .map(|(user_id, _)| user_id)
+            .collect();
+        assert_eq!(elevated, vec![&bob.id.to_string()]);
+    }
 
-        // clone later needed values
-        let poll_option_title = poll_option.title.clone();
-        let room_name = poll_option.room_name.clone();
+    /// A session whose first message blocks its arbiter until released
+    struct StuckSession {
+        release: Arc<Mutex<std::sync::mpsc::Receiver<()>>>,
+        frames: Arc<Mutex<Vec<String>>>,
+        stopped: Arc<Mutex<bool>>,
+    }
 
-        // add poll_option to poll
-        poll.options.push(poll_option);
+    impl Actor for StuckSession {
+        type Context = Context<Self>;
+    }
 
-        // send poll option message to clients
-        let txt = json!(messages::outbound::PollOption {
-            r#type: messages::outbound::Types::PollOption,
-            pollobject: poll.title.clone(),
-            polloptionobject: poll_option_title.clone(),
-        })
-        .to_string();
-        self.send_message_all(&room_name, &txt);
+    impl Handler<Message> for StuckSession {
+        type Result = ();
+
+        fn handle(&mut self, msg: Message, _: &mut Context<Self>) {
+            let _ = self.release.lock().unwrap().recv();
+            self.frames.lock().unwrap().push(msg.0.to_string());
+        }
     }
-}
 
-impl Handler<PollVoteHelper> for WebSocketServer {
-    type Result = ();
+    impl Handler<Stop> for StuckSession {
+        type Result = ();
 
-    fn handle(&mut self, vote: PollVoteHelper, _: &mut Context<Self>) {
-        let room = self
-            .rooms
-            .entry(vote.room_name.clone())
-            .or_insert(Room::default());
+        fn handle(&mut self, _: Stop, _: &mut Context<Self>) {
+            *self.stopped.lock().unwrap() = true;
+        }
+    }
 
-        // check if poll exists
-        let mut poll_exists = room.polls.clone();
-        poll_exists.retain(|elem| &elem.title == &vote.poll_title);
+    impl Handler<Flush> for StuckSession {
+        type Result = ();
 
-        if poll_exists.len() == 0 {
-            self.send_error_user(
-                &vote.room_name,
-                "poll_does_not_exist",
-                "A poll with that title doesn't exist",
-                vote.owner_id,
-            );
-            println!("A poll with that title doesn't exist");
-            return;
+        fn handle(&mut self, _: Flush, _: &mut Context<Self>) {}
+    }
+
+    #[actix_rt::test]
+    async fn stuck_sessions_miss_instants_and_are_disconnected() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+
+        let (release, blocked) = std::sync::mpsc::channel();
+        let blocked = Arc::new(Mutex::new(blocked));
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let stopped = Arc::new(Mutex::new(false));
+        let arbiter = Arbiter::new();
+        let stuck = {
+            let frames = frames.clone();
+            let stopped = stopped.clone();
+            StuckSession::start_in_arbiter(&arbiter, move |_| StuckSession {
+                release: blocked,
+                frames,
+                stopped,
+            })
+        };
+        server
+            .send(Join {
+                addr: stuck.clone().recipient(),
+                stop: stuck.clone().recipient(),
+                user_id: 2,
+                user_name: "bob".to_string(),
+                room_name: "room".to_string(),
+                capabilities: HashSet::new(),
+                spectator: false,
+                admin: false,
+            })
+            .await
+            .unwrap();
+
+        let count = MAX_DROPPED_MESSAGES + 50;
+        for _ in 0..count {
+            server
+                .send(Instant {
+                    object: json!("clap"),
+                    owner_id: alice.id,
+                    owner_name: alice.name.clone(),
+                    room_name: "room".to_string(),
+                    msg_id: None,
+                    delay: None,
+                })
+                .await
+                .unwrap();
         }
+        // the other users get every instant
+        assert_eq!(alice.frames_of_type("instant").await.len(), count);
 
-        // get poll
-        let poll_index = room
-            .polls
+        // release the stuck session and let it catch up
+        drop(release);
+        server.send(Flush).await.unwrap();
+        stuck.send(Flush).await.unwrap();
+
+        let frames = frames.lock().unwrap();
+        let instants = frames
             .iter()
-            .position(|poll| poll.title == vote.poll_title)
-            .unwrap();
-        let poll = room.polls.get_mut(poll_index).unwrap();
+            .filter(|frame| frame.contains(r#""instant""#))
+            .count();
+        assert!(instants < count);
+        assert!(frames.iter().any(|frame| frame.contains("slow_consumer")));
+        assert!(*stopped.lock().unwrap());
+        arbiter.stop();
+    }
 
-        // check if poll is closed
-        if poll.closed {
-            self.send_error_user(
-                &vote.room_name,
-                "poll_closed",
-                "Sorry, the poll is already closed",
-                vote.owner_id,
-            );
-            println!("Poll is already closed!");
-            return;
+    #[actix_rt::test]
+    async fn only_room_names_matching_the_pattern_can_be_joined() {
+        let server = start_server(Config {
+            room_name_pattern: Some(Regex::new("^(?:meet-[0-9]+)$").unwrap()),
+            ..Config::default()
+        });
+
+        let alice = join(&server, "meet-42", 1, "alice").await;
+        assert!(alice.errors().await.is_empty());
+        assert_eq!(alice.frames_of_type("all").await.len(), 1);
+        assert!(!alice.is_stopped().await);
+
+        for (user_id, room_name) in (2..).zip(&["lobby", "meet-42x", "xmeet-42", ""]) {
+            let bob = join(&server, room_name, user_id, "bob").await;
+            assert_eq!(bob.errors().await, vec!["invalid_room_name"]);
+            assert!(bob.frames_of_type("all").await.is_empty());
+            assert!(bob.is_stopped().await);
         }
 
-        // check if poll_option exists
-        let mut poll_option_exists = poll.options.clone();
-        poll_option_exists
-            .retain(|existing_poll_option| existing_poll_option.title == vote.option_title);
+        // joining a further room is refused the same way
+        server
+            .send(JoinRoom {
+                user_id: alice.id,
+                user_name: alice.name.clone(),
+                room_name: "lobby".to_string(),
+                spectator: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(alice.errors().await, vec!["invalid_room_name"]);
+        assert_eq!(alice.frames_of_type("all").await.len(), 1);
+    }
 
-        if poll_option_exists.len() == 0 {
-            self.send_error_user(
-                &vote.room_name,
-                "poll_option_does_not_exist",
-                "A poll-option with that title in this poll doesn't exist",
-                vote.owner_id,
-            );
-            println!("Poll-Option with that title in this poll doesn't exist");
-            return;
+    #[actix_rt::test]
+    async fn any_room_name_can_be_joined_without_a_pattern() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "any room", 1, "alice").await;
+        assert!(alice.errors().await.is_empty());
+        assert_eq!(alice.frames_of_type("all").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn join_replay_is_batched_for_clients_with_the_capability() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        for title in &["pizza", "curry"] {
+            server
+                .send(poll_option(&alice, "room", "lunch", title))
+                .await
+                .unwrap();
         }
+        server
+            .send(vote(&alice, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
 
-        // check if user has already voted
-        let mut remove_vote = false;
-        let mut remove_vote_option_title = "".to_string();
+        let legacy = join(&server, "room", 2, "bob").await;
+        let batching = join_with(&server, "room", 3, "carol", &["batch"], false).await;
+
+        // without the capability every replayed message is a frame of its own
+        let replayed: Vec<Arbitrary> = legacy
+            .frames()
+            .await
+            .into_iter()
+            .filter(|frame| frame["type"] != "hello")
+            .take_while(|frame| frame["type"] != "user")
+            .collect();
+        assert!(replayed.len() > 1);
+
+        let batches = batching.frames_of_type("batch").await;
+        assert_eq!(batches.len(), 1);
+        let batched = batches[0]["object"].as_array().unwrap();
+        let types = |frames: &[Arbitrary]| -> Vec<Arbitrary> {
+            frames.iter().map(|frame| frame["type"].clone()).collect()
+        };
+        assert_eq!(types(batched), types(&replayed));
+        assert!(batched.iter().all(|frame| frame["room"] == "room"));
+        assert!(batching.frames_of_type("poll").await.is_empty());
+    }
 
-        if poll.votes.contains_key(&vote.owner_id) {
-            println!(
-                "User has already votes in this poll, removing existing vote and adding new vote."
-            );
+    /// Forget a session but leave its user in the rooms, as a bug could
+    #[derive(Message)]
+    #[rtype(result = "()")]
+    struct OrphanUser(usize);
 
-            // send delete vote message to clients
-            for (userid, poll_option_title) in poll.votes.clone() {
-                if userid == vote.owner_id {
-                    remove_vote = true;
-                    remove_vote_option_title = poll_option_title.to_string().clone();
-                    break;
-                }
-            }
+    impl Handler<OrphanUser> for WebSocketServer {
+        type Result = ();
 
-            // remove existing vote
-            poll.votes.remove(&vote.owner_id);
+        fn handle(&mut self, msg: OrphanUser, _: &mut Context<Self>) {
+            self.sessions.remove(&msg.0);
         }
+    }
 
-        // clone later needed values
-        let poll_option_title = vote.option_title.clone();
-        let poll_title = poll.title.clone();
+    /// Reap orphaned users now instead of waiting for the reap interval
+    #[derive(Message)]
+    #[rtype(result = "()")]
+    struct ReapOrphanedUsers;
 
-        // add vote to poll
-        poll.votes.insert(vote.owner_id, vote.option_title);
+    impl Handler<ReapOrphanedUsers> for WebSocketServer {
+        type Result = ();
 
-        // inform other users if one vote has to be removed
-        if remove_vote {
-            let elevated_txt = json!(messages::outbound::VoteDelete {
-                r#type: messages::outbound::Types::VoteDelete,
-                pollobject: poll_title.clone(),
-                polloptionobject: remove_vote_option_title.clone(),
-                userid: vote.owner_id,
-            })
-            .to_string();
-            let not_elevated_txt = json!(messages::outbound::VoteDelete {
-                r#type: messages::outbound::Types::VoteDelete,
-                pollobject: poll_title.clone(),
-                polloptionobject: remove_vote_option_title.clone(),
-                userid: 0,
-            })
-            .to_string();
+        fn handle(&mut self, _: ReapOrphanedUsers, _: &mut Context<Self>) {
+            self.reap_orphaned_users();
+        }
+    }
 
-            self.send_message_all_elevated(&vote.room_name, &elevated_txt);
-            self.send_message_all_not_elevated(&vote.room_name, &not_elevated_txt);
+    #[actix_rt::test]
+    async fn orphaned_users_are_reaped() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server.send(OrphanUser(bob.id)).await.unwrap();
+        alice.clear().await;
+
+        server.send(ReapOrphanedUsers).await.unwrap();
+        let left = alice.frames_of_type("userleft").await;
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0]["object"]["id"], bob.id);
+        let all = alice.frames_of_type("all").await;
+        assert_eq!(all.len(), 1);
+        let joined = all[0]["joined"].as_object().unwrap();
+        assert_eq!(joined.len(), 1);
+        assert!(joined.contains_key(&alice.id.to_string()));
+
+        // users with sessions stay
+        alice.clear().await;
+        server.send(ReapOrphanedUsers).await.unwrap();
+        assert!(alice.frames().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn renaming_an_option_keeps_its_votes() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join(&server, "room", 3, "carol").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        for option in &["piza", "pasta"] {
+            server
+                .send(poll_option(&alice, "room", "lunch", option))
+                .await
+                .unwrap();
         }
+        server
+            .send(vote(&bob, "room", "lunch", "piza"))
+            .await
+            .unwrap();
+        server
+            .send(vote(&carol, "room", "lunch", "piza"))
+            .await
+            .unwrap();
 
-        // send poll option message to clients
-        let elevated_txt = json!(messages::outbound::Vote {
-            r#type: messages::outbound::Types::Vote,
-            pollobject: poll_title.clone(),
-            polloptionobject: poll_option_title.clone(),
-            username: vote.owner_name.clone(),
-            userid: vote.owner_id,
-        })
-        .to_string();
-        let not_elevated_txt = json!(messages::outbound::Vote {
-            r#type: messages::outbound::Types::Vote,
-            pollobject: poll_title.clone(),
-            polloptionobject: poll_option_title.clone(),
-            username: "".to_string(),
-            userid: 0,
-        })
-        .to_string();
+        let rename = |owner: &MockClient, new_title: &str| PollOptionRename {
+            owner_id: owner.id,
+            room_name: "room".to_string(),
+            msg_id: None,
+            poll_title: "lunch".to_string(),
+            old_title: "piza".to_string(),
+            new_title: new_title.to_string(),
+        };
+        server.send(rename(&bob, "pizza")).await.unwrap();
+        assert_eq!(bob.errors().await, vec!["no_permission"]);
+        server.send(rename(&alice, "pasta")).await.unwrap();
+        assert_eq!(alice.errors().await, vec!["poll_option_already_exists"]);
+
+        server.send(rename(&alice, "pizza")).await.unwrap();
+        let renamed = carol.frames_of_type("polloptionrenamed").await;
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0]["oldpolloptionobject"], "piza");
+        assert_eq!(renamed[0]["newpolloptionobject"], "pizza");
+
+        server
+            .send(PollStatus {
+                sender_id: alice.id,
+                room_name: "room".to_string(),
+                poll_title: "lunch".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        let status = alice.frames_of_type("pollstatus").await;
+        assert_eq!(
+            status[0]["object"]["options"],
+            json!([
+                {"polloptionobject": "pizza", "votes": 2},
+                {"polloptionobject": "pasta", "votes": 0},
+            ])
+        );
 
-        self.send_message_all_elevated(&vote.room_name, &elevated_txt);
-        self.send_message_all_not_elevated(&vote.room_name, &not_elevated_txt);
+        // voting again replaces the migrated vote
+        bob.clear().await;
+        server
+            .send(vote(&bob, "room", "lunch", "pasta"))
+            .await
+            .unwrap();
+        let deleted = bob.frames_of_type("votedelete").await;
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0]["polloptionobject"], "pizza");
     }
-}
 
-impl Handler<PollCloseHelper> for WebSocketServer {
-    type Result = ();
+    #[actix_rt::test]
+    async fn vote_receipts_reflect_the_latest_vote_privately() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        for option in &["pizza", "pasta"] {
+            server
+                .send(poll_option(&alice, "room", "lunch", option))
+                .await
+                .unwrap();
+        }
 
-    fn handle(&mut self, close: PollCloseHelper, _: &mut Context<Self>) {
-        // get room
-        let room = self
-            .rooms
-            .entry(close.room_name.clone())
-            .or_insert(Room::default());
+        for option in &["pizza", "pasta"] {
+            server
+                .send(vote(&bob, "room", "lunch", option))
+                .await
+                .unwrap();
+        }
 
-        // check if poll exists
-        let mut poll_exists = room.polls.clone();
-        poll_exists.retain(|elem| &elem.title == &close.poll_title);
+        let receipts = bob.frames_of_type("votereceipt").await;
+        assert_eq!(receipts.len(), 2);
+        assert_eq!(receipts[0]["pollobject"], "lunch");
+        assert_eq!(receipts[0]["polloptionobject"], "pizza");
+        assert_eq!(receipts[1]["polloptionobject"], "pasta");
+        assert!(alice.frames_of_type("votereceipt").await.is_empty());
+    }
 
-        if poll_exists.len() == 0 {
-            self.send_error_user(
-                &close.room_name,
-                "poll_does_not_exist",
-                "A poll with that title doesn't exist",
-                close.sender_id,
-            );
-            println!("Poll with that title doesn't exist!");
-            return;
+    #[actix_rt::test]
+    async fn open_polls_are_listed_on_demand() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        for title in &["lunch", "dinner"] {
+            server.send(poll(&alice, "room", title)).await.unwrap();
+            server
+                .send(poll_option(&alice, "room", title, "pizza"))
+                .await
+                .unwrap();
         }
+        server
+            .send(vote(&alice, "room", "lunch", "pizza"))
+            .await
+            .unwrap();
+        server
+            .send(PollCloseHelper {
+                sender_id: alice.id,
+                sender_name: alice.name.clone(),
+                room_name: "room".to_string(),
+                poll_title: "dinner".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        alice.clear().await;
+        bob.clear().await;
 
-        // get poll
-        let poll_index = room
-            .polls
+        server
+            .send(ListPolls {
+                user_id: bob.id,
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let types: Vec<Arbitrary> = bob
+            .frames()
+            .await
             .iter()
-            .position(|poll| poll.title == close.poll_title)
+            .map(|frame| frame["type"].clone())
+            .collect();
+        assert_eq!(
+            types,
+            vec![json!("poll"), json!("polloption"), json!("vote")]
+        );
+        let polls = bob.frames_of_type("poll").await;
+        assert_eq!(polls[0]["object"], "lunch");
+        // votes are replayed anonymously
+        assert_eq!(
+            bob.frames_of_type("vote").await[0]["userid"],
+            ANONYMOUS_USER_ID
+        );
+        assert!(alice.frames().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn activity_keeps_every_joined_room_open() {
+        let server = start_server(Config {
+            room_idle_timeout: Some(Duration::from_millis(100)),
+            ..Config::default()
+        });
+        let alice = join(&server, "a", 1, "alice").await;
+        server
+            .send(JoinRoom {
+                user_id: alice.id,
+                user_name: alice.name.clone(),
+                room_name: "b".to_string(),
+                spectator: false,
+            })
+            .await
             .unwrap();
-        let poll = room.polls.get_mut(poll_index).unwrap();
+        let bob = join(&server, "c", 2, "bob").await;
 
-        // check if poll is closed
-        if poll.closed {
-            self.send_error_user(
-                &close.room_name,
-                "poll_closed",
-                "Sorry, the poll is already closed",
-                close.sender_id,
-            );
-            println!("Poll is already closed!");
-            return;
-        }
+        actix_rt::time::delay_for(Duration::from_millis(150)).await;
+        server.send(Activity { id: alice.id }).await.unwrap();
+        server.send(SweepIdleRooms).await.unwrap();
 
-        // close poll
-        poll.closed = true;
+        assert!(alice.frames_of_type("roomclosing").await.is_empty());
+        assert!(!alice.is_stopped().await);
+        assert_eq!(bob.frames_of_type("roomclosing").await[0]["object"], "c");
+        assert!(bob.is_stopped().await);
+    }
 
-        // send poll option message to clients
-        let txt = json!(messages::outbound::PollClose {
-            r#type: messages::outbound::Types::PollClose,
-            object: poll.title.clone(),
-        })
-        .to_string();
-        self.send_message_all(&close.room_name, &txt);
+    #[actix_rt::test]
+    async fn a_replaced_session_timing_out_leaves_the_new_session_connected() {
+        let server = start_server(Config::default());
+        let stale = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+
+        // the heartbeat of the stale session fails while it is being replaced
+        stale.addr.do_send(TimeOut);
+        let alice = join(&server, "room", 1, "alice").await;
+        assert!(stale.is_stopped().await);
+
+        server
+            .send(raise(&bob, "room", json!("hand")))
+            .await
+            .unwrap();
+        assert!(!alice.frames_of_type("raised").await.is_empty());
+
+        let carol = join(&server, "room", 3, "carol").await;
+        let all = carol.frames_of_type("all").await;
+        let joined = all.last().unwrap()["joined"].as_object().unwrap();
+        let mut ids: Vec<&String> = joined.keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec!["1", "2", "3"]);
     }
-}
 
-impl WebSocketServer {
-    /// Handles managing priligiges on request
-    ///
-    /// # Arguments
-    /// * `room_name` - The room in which the user's priviliges should be changed
-    /// * `requested_id` - The user who requests the change. Elevated priviliges needed.
-    /// * `user_id` - The user whose priviliges should be changed.
-    /// * `elevated` - If the user should have elevated priviliges or not.
-    fn process_priviliges(
-        &mut self,
-        room_name: &String,
-        requester_id: usize,
-        user_id: usize,
-        elevated: bool,
-    ) -> Result<(), &'static str> {
-        if let Some(room) = self.rooms.get_mut(room_name) {
-            if room.is_elevated(&requester_id)? && room.is_elevated(&user_id)? != elevated {
-                room.set_elevated(&user_id, elevated);
-
-                // resend votes (with user_id and user_name) for open polls
-                let room_imut = room.clone();
-                for poll in room_imut.polls.clone() {
-                    if !poll.closed {
-                        // send votes for poll
-                        for (userid, option_title) in poll.votes.clone() {
-                            let user = room_imut.connected.get(&userid).unwrap();
-
-                            if elevated {
-                                let del_vote_txt = json!(messages::outbound::VoteDelete {
-                                    r#type: messages::outbound::Types::VoteDelete,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: option_title.clone(),
-                                    userid: 0,
-                                })
-                                .to_string();
-                                self.send_message_user(&room_name, &del_vote_txt, user_id);
-
-                                let vote_txt = json!(messages::outbound::Vote {
-                                    r#type: messages::outbound::Types::Vote,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: option_title.clone(),
-                                    username: user.name.clone(),
-                                    userid: userid,
-                                })
-                                .to_string();
-                                self.send_message_user(&room_name, &vote_txt, user_id);
-                            } else {
-                                let del_vote_txt = json!(messages::outbound::VoteDelete {
-                                    r#type: messages::outbound::Types::VoteDelete,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: option_title.clone(),
-                                    userid: userid,
-                                })
-                                .to_string();
-                                self.send_message_user(&room_name, &del_vote_txt, user_id);
-
-                                let vote_txt = json!(messages::outbound::Vote {
-                                    r#type: messages::outbound::Types::Vote,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: option_title.clone(),
-                                    username: "".to_string(),
-                                    userid: 0,
-                                })
-                                .to_string();
-                                self.send_message_user(&room_name, &vote_txt, user_id);
-                            }
-                        }
-                    }
-                }
+    #[actix_rt::test]
+    async fn delayed_instants_of_a_replaced_session_are_cancelled() {
+        let server = start_server(Config::default());
+        let stale = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
 
-                return Ok(());
-            }
-        }
-        Err("")
+        server
+            .send(instant(&stale, "room", Some(Duration::from_millis(100))))
+            .await
+            .unwrap();
+        let _alice = join(&server, "room", 1, "alice").await;
+
+        actix_rt::time::delay_for(Duration::from_millis(150)).await;
+        assert!(bob.frames_of_type("instant").await.is_empty());
     }
-}
 
-impl Handler<Elevate> for WebSocketServer {
-    type Result = ();
+    #[actix_rt::test]
+    async fn only_elevated_users_can_close_polls() {
+        let server = start_server(Config::default());
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server.send(poll(&alice, "room", "lunch")).await.unwrap();
+        bob.clear().await;
+
+        server
+            .send(PollCloseHelper {
+                sender_id: bob.id,
+                sender_name: bob.name.clone(),
+                room_name: "room".to_string(),
+                poll_title: "lunch".to_string(),
+                msg_id: Some(json!(1)),
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob.errors().await, vec!["no_permission"]);
+        assert_eq!(bob.frames_of_type("ack").await[0]["error"], "no_permission");
+        assert!(alice.frames_of_type("pollresults").await.is_empty());
+
+        server
+            .send(PollCloseHelper {
+                sender_id: alice.id,
+                sender_name: alice.name.clone(),
+                room_name: "room".to_string(),
+                poll_title: "lunch".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(alice.frames_of_type("pollresults").await.len(), 1);
+    }
 
-    fn handle(&mut self, msg: Elevate, _: &mut Context<Self>) {
-        match self.process_priviliges(&msg.room_name, msg.owner_id, msg.object, true) {
-            Err(_) => (),
-            Ok(_) => {
-                let txt = json!(messages::outbound::PermissionChange {
-                    r#type: messages::outbound::Types::Elevated,
-                    object: msg.object,
-                })
-                .to_string();
-                self.send_message_all(&msg.room_name, &txt);
-            }
-        }
+    #[actix_rt::test]
+    async fn pending_elevations_of_a_receded_requester_are_dropped() {
+        let server = start_server(Config {
+            confirm_elevation: true,
+            ..Config::default()
+        });
+        let alice = join(&server, "room", 1, "alice").await;
+        let bob = join(&server, "room", 2, "bob").await;
+        let carol = join(&server, "room", 3, "carol").await;
+        server.send(elevate(&alice, "room", &bob)).await.unwrap();
+        server.send(elevate(&alice, "room", &carol)).await.unwrap();
+
+        server
+            .send(Recede {
+                object: alice.id,
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        bob.clear().await;
+        carol.clear().await;
+
+        server
+            .send(ConfirmElevate {
+                object: carol.id,
+                owner_id: bob.id,
+                room_name: "room".to_string(),
+                msg_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob.errors().await, vec!["no_pending_elevation"]);
+        assert!(carol.frames_of_type("elevated").await.is_empty());
     }
-}
 
-impl Handler<Recede> for WebSocketServer {
-    type Result = ();
+    #[test]
+    fn the_room_is_added_to_outbound_messages() {
+        let message: Arbitrary = serde_json::from_str(&with_room(
+            "a \"room\"",
+            r#"{"type":"lower","object":"hand"}"#,
+        ))
+        .unwrap();
+        assert_eq!(
+            message,
+            json!({"room": "a \"room\"", "type": "lower", "object": "hand"})
+        );
+        assert_eq!(with_room("room", "{}"), r#"{"room":"room"}"#);
+    }
 
-    fn handle(&mut self, msg: Recede, _: &mut Context<Self>) {
-        match self.process_priviliges(&msg.room_name, msg.owner_id, msg.object, false) {
-            Err(_) => (),
-            Ok(_) => {
-                let txt = json!(messages::outbound::PermissionChange {
-                    r#type: messages::outbound::Types::Receded,
-                    object: msg.object,
-                })
-                .to_string();
-                self.send_message_all(&msg.room_name, &txt);
-            }
-        }
+    #[actix_rt::test]
+    async fn camel_case_sessions_receive_converted_messages() {
+        let server = start_server(Config::default());
+        let alice = join_with(&server, "room", 1, "alice", &["camelcase"], false).await;
+        let bob = join(&server, "room", 2, "bob").await;
+        server
+            .send(raise(&bob, "room", json!("hand")))
+            .await
+            .unwrap();
+
+        let raised = alice.frames_of_type("raised").await;
+        assert_eq!(raised[0]["ownerId"], bob.id);
+        assert_eq!(raised[0]["room"], "room");
+        assert!(raised[0].get("owner_id").is_none());
+
+        let raised = bob.frames_of_type("raised").await;
+        assert_eq!(raised[0]["owner_id"], bob.id);
+        assert!(raised[0].get("ownerId").is_none());
     }
 }